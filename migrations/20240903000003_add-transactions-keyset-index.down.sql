@@ -0,0 +1 @@
+DROP INDEX "IDX_transactions_date_created_id";
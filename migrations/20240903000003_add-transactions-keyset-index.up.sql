@@ -0,0 +1 @@
+CREATE INDEX "IDX_transactions_date_created_id" ON "transactions" ("date_created", "id");
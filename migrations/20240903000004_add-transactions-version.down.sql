@@ -0,0 +1,5 @@
+DROP TRIGGER "bump_tx_version" ON "transactions";
+
+DROP FUNCTION bump_row_version();
+
+ALTER TABLE "transactions" DROP COLUMN "version";
@@ -0,0 +1,13 @@
+ALTER TABLE "transactions" ADD COLUMN "version" bigint NOT NULL DEFAULT 1;
+
+CREATE FUNCTION bump_row_version() RETURNS trigger AS $$
+BEGIN
+	NEW.version := OLD.version + 1;
+	RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "bump_tx_version"
+BEFORE UPDATE ON "transactions"
+FOR EACH ROW
+EXECUTE PROCEDURE bump_row_version();
@@ -0,0 +1,13 @@
+CREATE TABLE "idempotency_keys" (
+	"user_id" uuid NOT NULL,
+	"key" varchar(255) NOT NULL,
+	"request_hash" char(64) NOT NULL,
+	"transaction_id" uuid DEFAULT NULL,
+	"response" jsonb DEFAULT NULL,
+	"date_created" TIMESTAMPTZ NOT NULL DEFAULT (now() at time zone 'utc'),
+
+	CONSTRAINT "PK_idempotency_keys" PRIMARY KEY ("user_id", "key"),
+	CONSTRAINT "FK_idempotency_keys_transaction" FOREIGN KEY ("transaction_id") REFERENCES "transactions" ("id") ON DELETE CASCADE
+);
+
+CREATE INDEX "IDX_idempotency_keys_date_created" ON "idempotency_keys" ("date_created");
@@ -0,0 +1,3 @@
+DROP TABLE "transaction_history";
+
+DROP TYPE "history_operation";
@@ -0,0 +1,16 @@
+CREATE TYPE "history_operation" AS ENUM ('create', 'update', 'delete', 'restore');
+
+CREATE TABLE "transaction_history" (
+	"id" uuid DEFAULT gen_random_uuid(),
+	"transaction_id" uuid NOT NULL,
+	"operation" history_operation NOT NULL,
+	"user_id" uuid NOT NULL,
+	"before" jsonb DEFAULT NULL,
+	"after" jsonb DEFAULT NULL,
+	"date_created" TIMESTAMPTZ NOT NULL DEFAULT (now() at time zone 'utc'),
+
+	CONSTRAINT "PK_transaction_history" PRIMARY KEY ("id"),
+	CONSTRAINT "FK_transaction_history_transaction" FOREIGN KEY ("transaction_id") REFERENCES "transactions" ("id") ON DELETE CASCADE
+);
+
+CREATE INDEX "IDX_transaction_history_transaction_id" ON "transaction_history" ("transaction_id", "date_created");
@@ -0,0 +1,4 @@
+ALTER TABLE "transactions"
+	DROP COLUMN "created_by",
+	DROP COLUMN "updated_by",
+	DROP COLUMN "deleted_by";
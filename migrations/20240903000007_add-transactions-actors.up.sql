@@ -0,0 +1,26 @@
+ALTER TABLE "transactions"
+	ADD COLUMN "created_by" uuid,
+	ADD COLUMN "updated_by" uuid DEFAULT NULL,
+	ADD COLUMN "deleted_by" uuid DEFAULT NULL;
+
+-- the backfill is not an edit, so it must not touch date_updated and version
+ALTER TABLE "transactions" DISABLE TRIGGER USER;
+
+-- rows created after the history table appeared know their author,
+-- for older ones the last actor is the best guess we have
+UPDATE "transactions" AS t
+SET "created_by" = COALESCE(
+		(
+			SELECT h."user_id"
+			FROM "transaction_history" AS h
+			WHERE h."transaction_id" = t."id" AND h."operation" = 'create'
+			LIMIT 1
+		),
+		t."user_id"
+	),
+	"updated_by" = CASE WHEN t."version" > 1 AND NOT t."deleted" THEN t."user_id" END,
+	"deleted_by" = CASE WHEN t."deleted" THEN t."user_id" END;
+
+ALTER TABLE "transactions" ENABLE TRIGGER USER;
+
+ALTER TABLE "transactions" ALTER COLUMN "created_by" SET NOT NULL;
@@ -0,0 +1 @@
+-- the added keys are harmless for the previous schema, nothing to undo
@@ -0,0 +1,24 @@
+-- snapshots written before the actor columns existed cannot be read back as a transaction,
+-- they get the author the transaction row was backfilled with
+UPDATE "transaction_history" AS h
+SET "before" = CASE
+		WHEN h."before" IS NULL OR h."before" ? 'created_by' THEN h."before"
+		ELSE h."before" || jsonb_build_object('created_by', t."created_by")
+	END,
+	"after" = CASE
+		WHEN h."after" IS NULL OR h."after" ? 'created_by' THEN h."after"
+		ELSE h."after" || jsonb_build_object('created_by', t."created_by")
+	END
+FROM "transactions" AS t
+WHERE t."id" = h."transaction_id"
+	AND (
+		(h."before" IS NOT NULL AND NOT h."before" ? 'created_by')
+		OR (h."after" IS NOT NULL AND NOT h."after" ? 'created_by')
+	);
+
+UPDATE "idempotency_keys" AS k
+SET "response" = k."response" || jsonb_build_object('created_by', t."created_by")
+FROM "transactions" AS t
+WHERE k."response" IS NOT NULL
+	AND NOT k."response" ? 'created_by'
+	AND t."id" = k."transaction_id";
@@ -0,0 +1,5 @@
+ALTER TABLE "transactions"
+	DROP COLUMN "margin_plan",
+	DROP COLUMN "margin_fact",
+	DROP COLUMN "margin_plan_net",
+	DROP COLUMN "margin_fact_net";
@@ -0,0 +1,16 @@
+-- margins follow the sums they are computed from, NDS parts missing from a row count as zero
+ALTER TABLE "transactions"
+	ADD COLUMN "margin_plan" numeric(15,2)
+		GENERATED ALWAYS AS ("sell_sum_plan" - "buy_sum_plan") STORED,
+	ADD COLUMN "margin_fact" numeric(15,2)
+		GENERATED ALWAYS AS ("sell_sum_fact" - "buy_sum_fact") STORED,
+	ADD COLUMN "margin_plan_net" numeric(15,2)
+		GENERATED ALWAYS AS (
+			("sell_sum_plan" - COALESCE("sell_nds_sum_plan", 0))
+			- ("buy_sum_plan" - COALESCE("buy_nds_sum_plan", 0))
+		) STORED,
+	ADD COLUMN "margin_fact_net" numeric(15,2)
+		GENERATED ALWAYS AS (
+			("sell_sum_fact" - COALESCE("sell_nds_sum_fact", 0))
+			- ("buy_sum_fact" - COALESCE("buy_nds_sum_fact", 0))
+		) STORED;
@@ -0,0 +1,6 @@
+DROP INDEX "IDX_transactions_original_transaction_id";
+
+ALTER TABLE "transactions"
+	DROP CONSTRAINT "CHK_transactions_original_refund",
+	DROP CONSTRAINT "FK_transactions_original",
+	DROP COLUMN "original_transaction_id";
@@ -0,0 +1,7 @@
+ALTER TABLE "transactions"
+	ADD COLUMN "original_transaction_id" uuid DEFAULT NULL,
+	ADD CONSTRAINT "FK_transactions_original" FOREIGN KEY ("original_transaction_id") REFERENCES "transactions" ("id"),
+	ADD CONSTRAINT "CHK_transactions_original_refund" CHECK ("original_transaction_id" IS NULL OR "refund");
+
+CREATE INDEX "IDX_transactions_original_transaction_id" ON "transactions" ("original_transaction_id")
+WHERE "original_transaction_id" IS NOT NULL;
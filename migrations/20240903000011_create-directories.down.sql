@@ -0,0 +1,10 @@
+ALTER TABLE "transactions"
+	DROP CONSTRAINT "FK_transactions_gas_station",
+	DROP CONSTRAINT "FK_transactions_card",
+	DROP CONSTRAINT "FK_transactions_contract",
+	DROP CONSTRAINT "FK_transactions_nomenclature";
+
+DROP TABLE "nomenclatures";
+DROP TABLE "contracts";
+DROP TABLE "cards";
+DROP TABLE "gas_stations";
@@ -0,0 +1,86 @@
+CREATE TABLE "gas_stations" (
+	"id" uuid DEFAULT gen_random_uuid(),
+	"name" varchar(255) NOT NULL,
+	"code" varchar(64) NOT NULL,
+	"active" boolean NOT NULL DEFAULT true,
+	"date_created" TIMESTAMPTZ NOT NULL DEFAULT (now() at time zone 'utc'),
+	"date_updated" TIMESTAMPTZ DEFAULT (now() at time zone 'utc'),
+
+	CONSTRAINT "PK_gas_stations" PRIMARY KEY ("id"),
+	CONSTRAINT "UQ_gas_stations_code" UNIQUE ("code")
+);
+
+CREATE TABLE "cards" (
+	"id" uuid DEFAULT gen_random_uuid(),
+	"name" varchar(255) NOT NULL,
+	"code" varchar(64) NOT NULL,
+	"active" boolean NOT NULL DEFAULT true,
+	"date_created" TIMESTAMPTZ NOT NULL DEFAULT (now() at time zone 'utc'),
+	"date_updated" TIMESTAMPTZ DEFAULT (now() at time zone 'utc'),
+
+	CONSTRAINT "PK_cards" PRIMARY KEY ("id"),
+	CONSTRAINT "UQ_cards_code" UNIQUE ("code")
+);
+
+CREATE TABLE "contracts" (
+	"id" uuid DEFAULT gen_random_uuid(),
+	"name" varchar(255) NOT NULL,
+	"code" varchar(64) NOT NULL,
+	"active" boolean NOT NULL DEFAULT true,
+	"date_created" TIMESTAMPTZ NOT NULL DEFAULT (now() at time zone 'utc'),
+	"date_updated" TIMESTAMPTZ DEFAULT (now() at time zone 'utc'),
+
+	CONSTRAINT "PK_contracts" PRIMARY KEY ("id"),
+	CONSTRAINT "UQ_contracts_code" UNIQUE ("code")
+);
+
+CREATE TABLE "nomenclatures" (
+	"id" uuid DEFAULT gen_random_uuid(),
+	"name" varchar(255) NOT NULL,
+	"code" varchar(64) NOT NULL,
+	"active" boolean NOT NULL DEFAULT true,
+	"unit" varchar(32) NOT NULL,
+	"date_created" TIMESTAMPTZ NOT NULL DEFAULT (now() at time zone 'utc'),
+	"date_updated" TIMESTAMPTZ DEFAULT (now() at time zone 'utc'),
+
+	CONSTRAINT "PK_nomenclatures" PRIMARY KEY ("id"),
+	CONSTRAINT "UQ_nomenclatures_code" UNIQUE ("code")
+);
+
+CREATE TRIGGER "mod_gas_station_updated" BEFORE UPDATE ON "gas_stations"
+FOR EACH ROW EXECUTE PROCEDURE moddatetime ("date_updated");
+
+CREATE TRIGGER "mod_card_updated" BEFORE UPDATE ON "cards"
+FOR EACH ROW EXECUTE PROCEDURE moddatetime ("date_updated");
+
+CREATE TRIGGER "mod_contract_updated" BEFORE UPDATE ON "contracts"
+FOR EACH ROW EXECUTE PROCEDURE moddatetime ("date_updated");
+
+CREATE TRIGGER "mod_nomenclature_updated" BEFORE UPDATE ON "nomenclatures"
+FOR EACH ROW EXECUTE PROCEDURE moddatetime ("date_updated");
+
+-- ids already used by transactions get inactive placeholders named after themselves,
+-- so that the foreign keys can be added; they are meant to be renamed afterwards
+INSERT INTO "gas_stations" ("id", "name", "code", "active")
+SELECT DISTINCT "gas_station_id", "gas_station_id"::text, "gas_station_id"::text, false
+FROM "transactions";
+
+INSERT INTO "cards" ("id", "name", "code", "active")
+SELECT DISTINCT "card_id", "card_id"::text, "card_id"::text, false
+FROM "transactions"
+WHERE "card_id" IS NOT NULL;
+
+INSERT INTO "contracts" ("id", "name", "code", "active")
+SELECT DISTINCT "contract_id", "contract_id"::text, "contract_id"::text, false
+FROM "transactions"
+WHERE "contract_id" IS NOT NULL;
+
+INSERT INTO "nomenclatures" ("id", "name", "code", "active", "unit")
+SELECT DISTINCT "nomenclature_id", "nomenclature_id"::text, "nomenclature_id"::text, false, ''
+FROM "transactions";
+
+ALTER TABLE "transactions"
+	ADD CONSTRAINT "FK_transactions_gas_station" FOREIGN KEY ("gas_station_id") REFERENCES "gas_stations" ("id"),
+	ADD CONSTRAINT "FK_transactions_card" FOREIGN KEY ("card_id") REFERENCES "cards" ("id"),
+	ADD CONSTRAINT "FK_transactions_contract" FOREIGN KEY ("contract_id") REFERENCES "contracts" ("id"),
+	ADD CONSTRAINT "FK_transactions_nomenclature" FOREIGN KEY ("nomenclature_id") REFERENCES "nomenclatures" ("id");
@@ -0,0 +1,7 @@
+DROP INDEX "IDX_transactions_implementation_id";
+
+ALTER TABLE "transactions" DROP CONSTRAINT "FK_transactions_implementation";
+
+DROP TABLE "implementations";
+
+DROP TYPE "implementation_status";
@@ -0,0 +1,37 @@
+CREATE TYPE "implementation_status" AS ENUM ('draft', 'posted');
+
+CREATE TABLE "implementations" (
+	"id" uuid DEFAULT gen_random_uuid(),
+	"number" varchar(64) NOT NULL,
+	"doc_date" date NOT NULL,
+	-- only documents backfilled from transactions without a contract lack one
+	"contract_id" uuid,
+	"status" implementation_status NOT NULL DEFAULT 'draft',
+	"posted_at" TIMESTAMPTZ,
+	"date_created" TIMESTAMPTZ NOT NULL DEFAULT (now() at time zone 'utc'),
+	"date_updated" TIMESTAMPTZ DEFAULT (now() at time zone 'utc'),
+
+	CONSTRAINT "PK_implementations" PRIMARY KEY ("id"),
+	CONSTRAINT "UQ_implementations_number" UNIQUE ("number"),
+	CONSTRAINT "FK_implementations_contract" FOREIGN KEY ("contract_id") REFERENCES "contracts" ("id")
+);
+
+CREATE TRIGGER "mod_implementation_updated" BEFORE UPDATE ON "implementations"
+FOR EACH ROW EXECUTE PROCEDURE moddatetime ("date_updated");
+
+-- ids already used by transactions become drafts numbered after themselves
+INSERT INTO "implementations" ("id", "number", "doc_date", "contract_id")
+SELECT
+	"implementation_id",
+	"implementation_id"::text,
+	MIN("op_date" AT TIME ZONE 'UTC')::date,
+	MIN("contract_id"::text)::uuid
+FROM "transactions"
+WHERE "implementation_id" IS NOT NULL
+GROUP BY "implementation_id";
+
+ALTER TABLE "transactions"
+	ADD CONSTRAINT "FK_transactions_implementation" FOREIGN KEY ("implementation_id") REFERENCES "implementations" ("id");
+
+CREATE INDEX "IDX_transactions_implementation_id" ON "transactions" ("implementation_id")
+WHERE "implementation_id" IS NOT NULL;
@@ -0,0 +1,9 @@
+CREATE TABLE "closed_periods" (
+	-- first day of the closed month
+	"month" date NOT NULL,
+	"closed_by" uuid NOT NULL,
+	"date_created" TIMESTAMPTZ NOT NULL DEFAULT (now() at time zone 'utc'),
+
+	CONSTRAINT "PK_closed_periods" PRIMARY KEY ("month"),
+	CONSTRAINT "CHK_closed_periods_month" CHECK (EXTRACT(DAY FROM "month") = 1)
+);
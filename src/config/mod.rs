@@ -1,5 +1,12 @@
 use ::std::env::var as readEnvVar;
 use ::std::net::SocketAddr;
+use ::std::str::FromStr;
+use ::std::sync::OnceLock;
+use chrono::Duration;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::i18n::Lang;
 
 pub fn get_http_host_to_serve() -> SocketAddr {
 	let app_host = readEnvVar("APP_HOST").expect("APP_HOST environment variable is not defined");
@@ -46,6 +53,68 @@ pub fn get_db_max_pool_size() -> u32 {
 	);
 }
 
+pub fn get_idempotency_key_ttl() -> Duration {
+	let default_ttl = String::from("86400");
+
+	let seconds = readEnvVar("IDEMPOTENCY_KEY_TTL_SECONDS")
+		.unwrap_or(default_ttl)
+		.parse::<u32>()
+		.expect("IDEMPOTENCY_KEY_TTL_SECONDS is not a correct u32");
+
+	return Duration::seconds(i64::from(seconds));
+}
+
+static VARIANCE_ABS_THRESHOLD: OnceLock<Decimal> = OnceLock::new();
+static VARIANCE_PCT_THRESHOLD: OnceLock<Decimal> = OnceLock::new();
+
+/// Deviation of fact from plan, in roubles, above which the variance report lists a transaction
+pub fn get_variance_abs_threshold() -> Decimal {
+	return *VARIANCE_ABS_THRESHOLD.get_or_init(|| {
+		let default_threshold = String::from("1");
+
+		return Decimal::from_str(&readEnvVar("VARIANCE_ABS_THRESHOLD").unwrap_or(default_threshold))
+			.expect("VARIANCE_ABS_THRESHOLD is not a correct decimal");
+	});
+}
+
+/// Deviation of fact from plan, in percent of the plan, above which the variance report lists a transaction
+pub fn get_variance_pct_threshold() -> Decimal {
+	return *VARIANCE_PCT_THRESHOLD.get_or_init(|| {
+		let default_threshold = String::from("5");
+
+		return Decimal::from_str(&readEnvVar("VARIANCE_PCT_THRESHOLD").unwrap_or(default_threshold))
+			.expect("VARIANCE_PCT_THRESHOLD is not a correct decimal");
+	});
+}
+
+static ADMIN_USER_IDS: OnceLock<Vec<Uuid>> = OnceLock::new();
+
+/// Users allowed to close and reopen accounting periods, nobody by default
+pub fn get_admin_user_ids() -> &'static [Uuid] {
+	return ADMIN_USER_IDS.get_or_init(|| {
+		return readEnvVar("ADMIN_USER_IDS")
+			.unwrap_or_default()
+			.split(',')
+			.map(|id| id.trim())
+			.filter(|id| !id.is_empty())
+			.map(|id| Uuid::parse_str(id).expect("ADMIN_USER_IDS is not a correct list of uuids"))
+			.collect();
+	});
+}
+
+static DEFAULT_LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Language of messages for requests without a supported `Accept-Language`.
+/// Read once, the first call at startup fails on a bad value instead of a request
+pub fn get_default_lang() -> Lang {
+	return *DEFAULT_LANG.get_or_init(|| {
+		let default_lang = String::from("ru");
+
+		return Lang::from_str(&readEnvVar("DEFAULT_LANG").unwrap_or(default_lang))
+			.expect("DEFAULT_LANG is not a supported language, use ru or en");
+	});
+}
+
 pub fn is_test() -> bool {
 	return match readEnvVar("ENV") {
 		Err(_) => false,
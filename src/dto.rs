@@ -1,37 +1,190 @@
 use ::std::error::Error;
+use ::std::fmt::{Formatter, Result as FmtResult};
+use ::std::str::FromStr;
 use axum::{
 	async_trait,
-	extract::{rejection::JsonRejection, FromRequest, Request},
-	http::{HeaderMap, Uri},
+	extract::{
+		rejection::{BytesRejection, FailedToBufferBody, JsonRejection},
+		FromRequest, Query, Request,
+	},
+	http::{header, HeaderMap, Uri},
 	Json, RequestExt,
 };
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use rust_decimal::Decimal;
+use serde::{
+	de::{self, Visitor},
+	Deserialize, Deserializer, Serialize,
+};
+use sha2::{Digest, Sha256};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-use crate::system_models::AppError;
+use crate::config;
+use crate::i18n::{current_lang, t, Lang, Message};
+use crate::repository::models::{ImplementationStatus, Transaction};
+use crate::system_models::{AppError, ErrorCode, FieldViolation};
 
-#[derive(Serialize, Deserialize, Debug, ToSchema)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct ApiTransaction {
 	pub op_date: DateTime<Utc>,
 	pub gas_station_id: Uuid,
 	pub card_id: Option<Uuid>,
 	pub contract_id: Option<Uuid>,
 	pub nomenclature_id: Uuid,
-	pub amount: Option<f64>,
-	pub stella_sum: Option<f64>,
-	pub stella_nds_sum: Option<f64>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub amount: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub stella_sum: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub stella_nds_sum: Option<Decimal>,
+
 	pub refund: bool,
-	pub buy_sum_plan: Option<f64>,
-	pub buy_nds_sum_plan: Option<f64>,
-	pub buy_sum_fact: Option<f64>,
-	pub buy_nds_sum_fact: Option<f64>,
-	pub sell_sum_plan: Option<f64>,
-	pub sell_nds_sum_plan: Option<f64>,
-	pub sell_sum_fact: Option<f64>,
-	pub sell_nds_sum_fact: Option<f64>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub buy_sum_plan: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub buy_nds_sum_plan: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub buy_sum_fact: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub buy_nds_sum_fact: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub sell_sum_plan: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub sell_nds_sum_plan: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub sell_sum_fact: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, deserialize_with = "deserialize_money")]
+	pub sell_nds_sum_fact: Option<Decimal>,
+
 	pub implementation_id: Option<Uuid>,
+
+	/// The purchase this refund reverses.
+	/// Left out of the JSON when empty to keep fingerprints of older requests stable
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub original_transaction_id: Option<Uuid>,
+}
+
+/// Money columns are `numeric(15,2)`
+const MONEY_SCALE: u32 = 2;
+const MONEY_INTEGER_DIGITS: u32 = 15 - MONEY_SCALE;
+
+fn parse_money(raw: &str) -> Result<Decimal, String> {
+	let value = Decimal::from_str_exact(raw.trim()).map_err(|_| t(Message::InvalidMoney(raw)))?;
+
+	if value.normalize().scale() > MONEY_SCALE {
+		return Err(t(Message::MoneyFractionDigits(raw, MONEY_SCALE)));
+	}
+
+	if value.abs() >= Decimal::from(10_u64.pow(MONEY_INTEGER_DIGITS)) {
+		return Err(t(Message::MoneyIntegerDigits(raw, MONEY_INTEGER_DIGITS)));
+	}
+
+	return Ok(value.round_dp(MONEY_SCALE));
+}
+
+struct MoneyVisitor;
+
+impl<'de> Visitor<'de> for MoneyVisitor {
+	type Value = Option<Decimal>;
+
+	fn expecting(&self, f: &mut Formatter) -> FmtResult {
+		return write!(
+			f,
+			"a decimal number or string with at most 2 fraction digits"
+		);
+	}
+
+	fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+		return Ok(None);
+	}
+
+	fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+		return Ok(None);
+	}
+
+	fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+		return deserializer.deserialize_any(self);
+	}
+
+	fn visit_i64<E: de::Error>(self, n: i64) -> Result<Self::Value, E> {
+		return parse_money(&n.to_string()).map(Some).map_err(E::custom);
+	}
+
+	fn visit_u64<E: de::Error>(self, n: u64) -> Result<Self::Value, E> {
+		return parse_money(&n.to_string()).map(Some).map_err(E::custom);
+	}
+
+	// JSON numbers arrive as f64, its shortest round-trip representation is exact
+	// for every value fitting into numeric(15,2)
+	fn visit_f64<E: de::Error>(self, n: f64) -> Result<Self::Value, E> {
+		return parse_money(&n.to_string()).map(Some).map_err(E::custom);
+	}
+
+	fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+		return parse_money(s).map(Some).map_err(E::custom);
+	}
+}
+
+fn deserialize_money<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<Decimal>, D::Error> {
+	return deserializer.deserialize_option(MoneyVisitor);
+}
+
+fn json_rejection_to_error(err: JsonRejection) -> AppError {
+	return match err {
+		JsonRejection::JsonDataError(data_err) => match data_err.source() {
+			Some(source_err) => {
+				let reason = source_err.to_string();
+				AppError::InvalidBody(t(Message::InvalidBody(Some(&reason))))
+			}
+			None => AppError::InvalidBody(t(Message::InvalidBody(None))),
+		},
+
+		JsonRejection::JsonSyntaxError(_) => AppError::MalformedJson(t(Message::MalformedJson)),
+
+		JsonRejection::MissingJsonContentType(_) => {
+			AppError::MissingContentType(t(Message::MissingJsonContentType))
+		}
+
+		JsonRejection::BytesRejection(err) => bytes_rejection_to_error(err),
+
+		_ => AppError::SystemError(t(Message::InternalError)),
+	};
+}
+
+/// A body over `DefaultBodyLimit` is the client's fault, any other read failure is not
+pub fn bytes_rejection_to_error(err: BytesRejection) -> AppError {
+	return match err {
+		BytesRejection::FailedToBufferBody(FailedToBufferBody::LengthLimitError(_)) => {
+			AppError::PayloadTooLarge(t(Message::PayloadTooLarge))
+		}
+		_ => AppError::UnreadableBody(t(Message::UnreadableBody)),
+	};
 }
 
 #[async_trait]
@@ -41,44 +194,870 @@ impl<S> FromRequest<S> for ApiTransaction {
 	async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
 		let body = req.extract::<Json<ApiTransaction>, _>().await;
 
-		return match body {
-			Err(err) => Err(match err {
-				JsonRejection::JsonDataError(data_err) => match data_err.source() {
-					Some(source_err) => {
-						AppError::BadRequest(format!("Передано некорректное тело запроса: {source_err}"))
-					}
-					None => AppError::BadRequest(String::from("Передано некорректное тело запроса")),
-				},
+		let dto = match body {
+			Err(err) => return Err(json_rejection_to_error(err)),
+			Ok(Json(dto)) => dto,
+		};
+
+		dto.validate_at("")?;
+		return Ok(dto);
+	}
+}
 
-				JsonRejection::JsonSyntaxError(_) => {
-					AppError::BadRequest(String::from("Передано некорректное тело запроса"))
+impl ApiTransaction {
+	/// SHA-256 of the canonical JSON representation, used to detect a reused idempotency key
+	pub fn fingerprint(&self) -> String {
+		let canonical = serde_json::to_vec(self).unwrap_or_default();
+		return hex::encode(Sha256::digest(canonical));
+	}
+
+	fn sums(&self) -> [(&'static str, Option<Decimal>); 10] {
+		return [
+			("stella_sum", self.stella_sum),
+			("stella_nds_sum", self.stella_nds_sum),
+			("buy_sum_plan", self.buy_sum_plan),
+			("buy_nds_sum_plan", self.buy_nds_sum_plan),
+			("buy_sum_fact", self.buy_sum_fact),
+			("buy_nds_sum_fact", self.buy_nds_sum_fact),
+			("sell_sum_plan", self.sell_sum_plan),
+			("sell_nds_sum_plan", self.sell_nds_sum_plan),
+			("sell_sum_fact", self.sell_sum_fact),
+			("sell_nds_sum_fact", self.sell_nds_sum_fact),
+		];
+	}
+
+	/// Directory entries the transaction refers to
+	pub fn references(&self) -> [(DirectoryKind, Option<Uuid>); 4] {
+		return [
+			(DirectoryKind::GasStations, Some(self.gas_station_id)),
+			(DirectoryKind::Cards, self.card_id),
+			(DirectoryKind::Contracts, self.contract_id),
+			(DirectoryKind::Nomenclatures, Some(self.nomenclature_id)),
+		];
+	}
+
+	/// Values which refunds of a purchase can't exceed in total, compared by absolute value
+	pub fn refundable(&self) -> [(&'static str, Option<Decimal>); 11] {
+		let [stella_sum, stella_nds_sum, buy_sum_plan, buy_nds_sum_plan, buy_sum_fact, buy_nds_sum_fact, sell_sum_plan, sell_nds_sum_plan, sell_sum_fact, sell_nds_sum_fact] =
+			self.sums();
+
+		return [
+			("amount", self.amount),
+			stella_sum,
+			stella_nds_sum,
+			buy_sum_plan,
+			buy_nds_sum_plan,
+			buy_sum_fact,
+			buy_nds_sum_fact,
+			sell_sum_plan,
+			sell_nds_sum_plan,
+			sell_sum_fact,
+			sell_nds_sum_fact,
+		];
+	}
+
+	/// What is left to refund of each value of this purchase, negative when refunds exceed it
+	pub fn refund_balance(&self, refunds: &[ApiTransaction]) -> [(&'static str, Decimal); 11] {
+		let mut balance = self
+			.refundable()
+			.map(|(field, value)| (field, value.unwrap_or_default().abs()));
+
+		for refund in refunds {
+			for ((_, left), (_, value)) in balance.iter_mut().zip(refund.refundable()) {
+				*left -= value.unwrap_or_default().abs();
+			}
+		}
+
+		return balance;
+	}
+
+	/// Checks a refund against the purchase it reverses, `refunds` are the other active
+	/// refunds of that purchase
+	pub fn validate_refund_at(
+		&self,
+		pointer: &str,
+		own_id: Option<Uuid>,
+		original: Option<&Transaction>,
+		refunds: &[ApiTransaction],
+	) -> Result<(), AppError> {
+		let Some(original_id) = self.original_transaction_id else {
+			return Ok(());
+		};
+
+		let violation = |field: &str, message: String| FieldViolation {
+			pointer: format!("{pointer}/{field}"),
+			message,
+		};
+
+		// the stored row is no refund yet, so the checks below would let it through
+		if own_id == Some(original_id) {
+			return Err(AppError::Validation(vec![violation(
+				"original_transaction_id",
+				t(Message::RefundOfItself),
+			)]));
+		}
+
+		let original = match original {
+			None => {
+				return Err(AppError::Validation(vec![violation(
+					"original_transaction_id",
+					t(Message::OriginalNotFound(original_id)),
+				)]));
+			}
+			Some(original) if original.refund => {
+				return Err(AppError::Validation(vec![violation(
+					"original_transaction_id",
+					t(Message::OriginalIsRefund(original_id)),
+				)]));
+			}
+			Some(original) => ApiTransaction::from(original),
+		};
+
+		let balance = original.refund_balance(refunds);
+		let mut violations = Vec::new();
+
+		for ((field, left), (_, value)) in balance.into_iter().zip(self.refundable()) {
+			if value.unwrap_or_default().abs() > left {
+				violations.push(violation(field, t(Message::RefundExceedsOriginal(left))));
+			}
+		}
+
+		return match violations.is_empty() {
+			true => Ok(()),
+			false => Err(AppError::Validation(violations)),
+		};
+	}
+
+	/// Checks a purchase against the active refunds it already has
+	pub fn validate_refunded_at(
+		&self,
+		pointer: &str,
+		refunds: &[ApiTransaction],
+	) -> Result<(), AppError> {
+		if refunds.is_empty() {
+			return Ok(());
+		}
+
+		let violation = |field: &str, message: String| FieldViolation {
+			pointer: format!("{pointer}/{field}"),
+			message,
+		};
+
+		if self.refund {
+			return Err(AppError::Validation(vec![violation(
+				"refund",
+				t(Message::RefundedTransactionIsRefund),
+			)]));
+		}
+
+		let mut violations = Vec::new();
+
+		for ((field, left), (_, value)) in self
+			.refund_balance(refunds)
+			.into_iter()
+			.zip(self.refundable())
+		{
+			if left < Decimal::ZERO {
+				let refunded = value.unwrap_or_default().abs() - left;
+				violations.push(violation(field, t(Message::BelowRefunded(refunded))));
+			}
+		}
+
+		return match violations.is_empty() {
+			true => Ok(()),
+			false => Err(AppError::Validation(violations)),
+		};
+	}
+
+	/// NDS parts paired with the sums they are included into
+	fn nds_parts(&self) -> [(&'static str, Option<Decimal>, &'static str, Option<Decimal>); 5] {
+		return [
+			(
+				"stella_nds_sum",
+				self.stella_nds_sum,
+				"stella_sum",
+				self.stella_sum,
+			),
+			(
+				"buy_nds_sum_plan",
+				self.buy_nds_sum_plan,
+				"buy_sum_plan",
+				self.buy_sum_plan,
+			),
+			(
+				"buy_nds_sum_fact",
+				self.buy_nds_sum_fact,
+				"buy_sum_fact",
+				self.buy_sum_fact,
+			),
+			(
+				"sell_nds_sum_plan",
+				self.sell_nds_sum_plan,
+				"sell_sum_plan",
+				self.sell_sum_plan,
+			),
+			(
+				"sell_nds_sum_fact",
+				self.sell_nds_sum_fact,
+				"sell_sum_fact",
+				self.sell_sum_fact,
+			),
+		];
+	}
+
+	/// Checks the business rules and reports every broken one at once,
+	/// `pointer` is the location of the transaction in the request body
+	pub fn validate_at(&self, pointer: &str) -> Result<(), AppError> {
+		let violation = |field: &str, message: String| FieldViolation {
+			pointer: format!("{pointer}/{field}"),
+			message,
+		};
+		let mut violations = Vec::new();
+
+		if self.op_date > Utc::now() {
+			violations.push(violation("op_date", t(Message::OpDateInFuture)));
+		}
+
+		if self.amount.is_some_and(|amount| amount < Decimal::ZERO) {
+			violations.push(violation("amount", t(Message::NegativeAmount)));
+		}
+
+		if self.original_transaction_id.is_some() && !self.refund {
+			violations.push(violation(
+				"original_transaction_id",
+				t(Message::OriginalOfNonRefund),
+			));
+		}
+
+		// refunds carry their sums with the minus sign
+		for (field, sum) in self.sums() {
+			match (self.refund, sum) {
+				(false, Some(sum)) if sum < Decimal::ZERO => {
+					violations.push(violation(field, t(Message::NegativeSum)))
 				}
+				(true, Some(sum)) if sum > Decimal::ZERO => {
+					violations.push(violation(field, t(Message::PositiveRefundSum)))
+				}
+				_ => {}
+			}
+		}
+
+		for (nds_field, nds, sum_field, sum) in self.nds_parts() {
+			if let (Some(nds), Some(sum)) = (nds, sum) {
+				if nds.abs() > sum.abs() {
+					violations.push(violation(nds_field, t(Message::NdsExceedsSum(sum_field))));
+				}
+			}
+		}
 
-				JsonRejection::MissingJsonContentType(_) => AppError::BadRequest(String::from(
-					"Пожалуйста, укажите заголовок `Content-Type: application/json`",
+		return match violations.is_empty() {
+			true => Ok(()),
+			false => Err(AppError::Validation(violations)),
+		};
+	}
+}
+
+impl From<&Transaction> for ApiTransaction {
+	fn from(tx: &Transaction) -> Self {
+		return Self {
+			op_date: tx.op_date,
+			gas_station_id: tx.gas_station_id,
+			card_id: tx.card_id,
+			contract_id: tx.contract_id,
+			nomenclature_id: tx.nomenclature_id,
+			amount: tx.amount,
+			stella_sum: tx.stella_sum,
+			stella_nds_sum: tx.stella_nds_sum,
+			refund: tx.refund,
+			buy_sum_plan: tx.buy_sum_plan,
+			buy_nds_sum_plan: tx.buy_nds_sum_plan,
+			buy_sum_fact: tx.buy_sum_fact,
+			buy_nds_sum_fact: tx.buy_nds_sum_fact,
+			sell_sum_plan: tx.sell_sum_plan,
+			sell_nds_sum_plan: tx.sell_nds_sum_plan,
+			sell_sum_fact: tx.sell_sum_fact,
+			sell_nds_sum_fact: tx.sell_nds_sum_fact,
+			implementation_id: tx.implementation_id,
+			original_transaction_id: tx.original_transaction_id,
+		};
+	}
+}
+
+pub const MAX_BATCH_SIZE: usize = 10_000;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchMode {
+	/// Either every transaction is created or none of them
+	#[default]
+	Atomic,
+	/// Valid transactions are created, the rest are reported by index
+	BestEffort,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BatchParams {
+	pub mode: Option<BatchMode>,
+}
+
+impl BatchParams {
+	pub fn from_uri(uri: &Uri) -> Result<BatchMode, AppError> {
+		return match Query::<BatchParams>::try_from_uri(uri) {
+			Ok(Query(params)) => Ok(params.mode.unwrap_or_default()),
+			Err(err) => Err(AppError::BadRequest(t(Message::InvalidBatchParams(
+				&err.body_text(),
+			)))),
+		};
+	}
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct BatchItemError {
+	pub index: usize,
+	pub code: ErrorCode,
+	pub message: String,
+	/// Pointers are relative to the whole batch, e.g. `/3/amount`
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub violations: Vec<FieldViolation>,
+}
+
+impl BatchItemError {
+	pub fn new(index: usize, err: AppError) -> Self {
+		let code = err.code();
+		let message = err.message();
+
+		let violations = match err {
+			AppError::Validation(violations) => violations,
+			_ => Vec::new(),
+		};
+
+		return BatchItemError {
+			index,
+			code,
+			message,
+			violations,
+		};
+	}
+}
+
+/// Array of transactions, every item is deserialized on its own so that a broken item
+/// doesn't hide the errors of the others
+pub struct ApiTransactionBatch {
+	pub items: Vec<(usize, ApiTransaction)>,
+	pub errors: Vec<BatchItemError>,
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for ApiTransactionBatch {
+	type Rejection = AppError;
+
+	async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
+		let body = req.extract::<Json<Vec<serde_json::Value>>, _>().await;
+
+		let values = match body {
+			Err(err) => return Err(json_rejection_to_error(err)),
+			Ok(Json(values)) => values,
+		};
+
+		if values.is_empty() || values.len() > MAX_BATCH_SIZE {
+			return Err(AppError::BadRequest(t(Message::BatchSize(MAX_BATCH_SIZE))));
+		}
+
+		let mut items = Vec::with_capacity(values.len());
+		let mut errors = Vec::new();
+
+		for (index, value) in values.into_iter().enumerate() {
+			match serde_json::from_value::<ApiTransaction>(value) {
+				Ok(tx) => match tx.validate_at(&format!("/{index}")) {
+					Ok(()) => items.push((index, tx)),
+					Err(err) => errors.push(BatchItemError::new(index, err)),
+				},
+				Err(err) => errors.push(BatchItemError::new(
+					index,
+					AppError::InvalidBody(t(Message::InvalidBatchItem(&err.to_string()))),
 				)),
+			}
+		}
 
-				JsonRejection::BytesRejection(_) => {
-					AppError::SystemError(String::from("Не удалось прочитать тело запроса"))
-				}
+		return Ok(ApiTransactionBatch { items, errors });
+	}
+}
+
+/// JSON Merge Patch (RFC 7396) of a transaction: a missing key leaves the column alone,
+/// an explicit `null` clears a nullable column
+#[derive(Deserialize, Debug, Default, ToSchema)]
+pub struct ApiTransactionPatch {
+	#[serde(default, deserialize_with = "deserialize_required")]
+	pub op_date: Option<DateTime<Utc>>,
+
+	#[serde(default, deserialize_with = "deserialize_required")]
+	pub gas_station_id: Option<Uuid>,
+
+	#[schema(value_type = Option<Uuid>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_nullable")]
+	pub card_id: Option<Option<Uuid>>,
+
+	#[schema(value_type = Option<Uuid>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_nullable")]
+	pub contract_id: Option<Option<Uuid>>,
+
+	#[serde(default, deserialize_with = "deserialize_required")]
+	pub nomenclature_id: Option<Uuid>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub amount: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub stella_sum: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub stella_nds_sum: Option<Option<Decimal>>,
+
+	#[serde(default, deserialize_with = "deserialize_required")]
+	pub refund: Option<bool>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub buy_sum_plan: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub buy_nds_sum_plan: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub buy_sum_fact: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub buy_nds_sum_fact: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub sell_sum_plan: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub sell_nds_sum_plan: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub sell_sum_fact: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Decimal>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_money_patch")]
+	pub sell_nds_sum_fact: Option<Option<Decimal>>,
+
+	#[schema(value_type = Option<Uuid>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_nullable")]
+	pub implementation_id: Option<Option<Uuid>>,
+
+	#[schema(value_type = Option<Uuid>, nullable)]
+	#[serde(default, deserialize_with = "deserialize_nullable")]
+	pub original_transaction_id: Option<Option<Uuid>>,
+}
+
+fn deserialize_required<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: Deserialize<'de>,
+{
+	return match Option::<T>::deserialize(deserializer)? {
+		None => Err(de::Error::custom(t(Message::NullNotAllowed))),
+		Some(value) => Ok(Some(value)),
+	};
+}
+
+fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+	D: Deserializer<'de>,
+	T: Deserialize<'de>,
+{
+	return Option::<T>::deserialize(deserializer).map(Some);
+}
+
+fn deserialize_money_patch<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<Option<Decimal>>, D::Error> {
+	return deserialize_money(deserializer).map(Some);
+}
+
+impl ApiTransactionPatch {
+	pub fn apply(self, tx: &Transaction) -> ApiTransaction {
+		let current = ApiTransaction::from(tx);
+
+		return ApiTransaction {
+			op_date: self.op_date.unwrap_or(current.op_date),
+			gas_station_id: self.gas_station_id.unwrap_or(current.gas_station_id),
+			card_id: self.card_id.unwrap_or(current.card_id),
+			contract_id: self.contract_id.unwrap_or(current.contract_id),
+			nomenclature_id: self.nomenclature_id.unwrap_or(current.nomenclature_id),
+			amount: self.amount.unwrap_or(current.amount),
+			stella_sum: self.stella_sum.unwrap_or(current.stella_sum),
+			stella_nds_sum: self.stella_nds_sum.unwrap_or(current.stella_nds_sum),
+			refund: self.refund.unwrap_or(current.refund),
+			buy_sum_plan: self.buy_sum_plan.unwrap_or(current.buy_sum_plan),
+			buy_nds_sum_plan: self.buy_nds_sum_plan.unwrap_or(current.buy_nds_sum_plan),
+			buy_sum_fact: self.buy_sum_fact.unwrap_or(current.buy_sum_fact),
+			buy_nds_sum_fact: self.buy_nds_sum_fact.unwrap_or(current.buy_nds_sum_fact),
+			sell_sum_plan: self.sell_sum_plan.unwrap_or(current.sell_sum_plan),
+			sell_nds_sum_plan: self.sell_nds_sum_plan.unwrap_or(current.sell_nds_sum_plan),
+			sell_sum_fact: self.sell_sum_fact.unwrap_or(current.sell_sum_fact),
+			sell_nds_sum_fact: self.sell_nds_sum_fact.unwrap_or(current.sell_nds_sum_fact),
+			implementation_id: self.implementation_id.unwrap_or(current.implementation_id),
+			original_transaction_id: self
+				.original_transaction_id
+				.unwrap_or(current.original_transaction_id),
+		};
+	}
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for ApiTransactionPatch {
+	type Rejection = AppError;
+
+	async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
+		let body = req.extract::<Json<ApiTransactionPatch>, _>().await;
 
-				non_exhaustive => AppError::SystemError(non_exhaustive.to_string()),
-			}),
+		return match body {
+			Err(err) => Err(json_rejection_to_error(err)),
 			Ok(Json(dto)) => Ok(dto),
 		};
 	}
 }
 
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TransactionsFilter {
+	pub gas_station_id: Option<Uuid>,
+	pub card_id: Option<Uuid>,
+	pub contract_id: Option<Uuid>,
+	pub nomenclature_id: Option<Uuid>,
+	pub implementation_id: Option<Uuid>,
+	pub user_id: Option<Uuid>,
+	pub created_by: Option<Uuid>,
+	pub refund: Option<bool>,
+	/// Inclusive lower bound of `op_date`
+	pub op_date_from: Option<DateTime<Utc>>,
+	/// Exclusive upper bound of `op_date`
+	pub op_date_to: Option<DateTime<Utc>>,
+}
+
+impl TransactionsFilter {
+	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
+		return match Query::<TransactionsFilter>::try_from_uri(uri) {
+			Ok(Query(filter)) => Ok(filter),
+			Err(err) => Err(AppError::BadRequest(t(Message::InvalidFilterParams(
+				&err.body_text(),
+			)))),
+		};
+	}
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for TransactionsFilter {
+	type Rejection = AppError;
+
+	async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
+		return TransactionsFilter::from_uri(req.uri());
+	}
+}
+
+/// Grouping dimension of the transaction statistics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsDimension {
+	GasStationId,
+	CardId,
+	ContractId,
+	NomenclatureId,
+	Day,
+	Week,
+	Month,
+}
+
+impl StatsDimension {
+	fn parse(raw: &str) -> Option<Self> {
+		return match raw {
+			"gas_station_id" => Some(StatsDimension::GasStationId),
+			"card_id" => Some(StatsDimension::CardId),
+			"contract_id" => Some(StatsDimension::ContractId),
+			"nomenclature_id" => Some(StatsDimension::NomenclatureId),
+			"day" => Some(StatsDimension::Day),
+			"week" => Some(StatsDimension::Week),
+			"month" => Some(StatsDimension::Month),
+			_ => None,
+		};
+	}
+
+	/// Periods group by the start of the day, ISO week or month of `op_date` in UTC
+	pub fn is_period(&self) -> bool {
+		return matches!(
+			self,
+			StatsDimension::Day | StatsDimension::Week | StatsDimension::Month
+		);
+	}
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsParams {
+	/// Comma separated `gas_station_id`, `card_id`, `contract_id`, `nomenclature_id`
+	/// and at most one of `day`, `week`, `month`
+	pub group_by: Option<String>,
+}
+
+impl StatsParams {
+	pub fn from_uri(uri: &Uri) -> Result<Vec<StatsDimension>, AppError> {
+		let Query(params) = Query::<StatsParams>::try_from_uri(uri)
+			.map_err(|err| AppError::BadRequest(t(Message::InvalidStatsParams(&err.body_text()))))?;
+
+		let mut dimensions: Vec<StatsDimension> = Vec::new();
+
+		for raw in params
+			.group_by
+			.iter()
+			.flat_map(|group_by| group_by.split(','))
+			.map(|raw| raw.trim())
+			.filter(|raw| !raw.is_empty())
+		{
+			let Some(dimension) = StatsDimension::parse(raw) else {
+				return Err(AppError::BadRequest(t(Message::UnknownStatsDimension(raw))));
+			};
+
+			if !dimensions.contains(&dimension) {
+				dimensions.push(dimension);
+			}
+		}
+
+		if dimensions.iter().filter(|d| d.is_period()).count() > 1 {
+			return Err(AppError::BadRequest(t(Message::SeveralStatsPeriods)));
+		}
+
+		return Ok(dimensions);
+	}
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VarianceParams {
+	/// Absolute deviation of fact from plan. Without both thresholds the
+	/// `VARIANCE_ABS_THRESHOLD` and `VARIANCE_PCT_THRESHOLD` defaults apply together
+	#[param(value_type = Option<f64>)]
+	pub abs_threshold: Option<Decimal>,
+	/// Deviation of fact from plan in percent of the plan
+	#[param(value_type = Option<f64>)]
+	pub pct_threshold: Option<Decimal>,
+}
+
+/// A transaction deviates when the fact differs from the plan by more than either threshold.
+/// A threshold passed alone is the only one applied, the other one is null
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct VarianceThresholds {
+	#[schema(value_type = Option<Decimal>)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub abs: Option<Decimal>,
+	#[schema(value_type = Option<Decimal>)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub pct: Option<Decimal>,
+}
+
+impl VarianceParams {
+	pub fn from_uri(uri: &Uri) -> Result<VarianceThresholds, AppError> {
+		let Query(params) = Query::<VarianceParams>::try_from_uri(uri).map_err(|err| {
+			AppError::BadRequest(t(Message::InvalidVarianceParams(&err.body_text())))
+		})?;
+
+		let thresholds = match (params.abs_threshold, params.pct_threshold) {
+			(None, None) => VarianceThresholds {
+				abs: Some(config::get_variance_abs_threshold()),
+				pct: Some(config::get_variance_pct_threshold()),
+			},
+			(abs, pct) => VarianceThresholds { abs, pct },
+		};
+
+		let negative = [thresholds.abs, thresholds.pct]
+			.iter()
+			.flatten()
+			.any(Decimal::is_sign_negative);
+
+		if negative {
+			return Err(AppError::BadRequest(t(Message::NegativeVarianceThreshold)));
+		}
+
+		return Ok(thresholds);
+	}
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeletedVisibility {
+	/// Return soft-deleted transactions along with the active ones
+	pub include_deleted: Option<bool>,
+	/// Return only soft-deleted transactions
+	pub only_deleted: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeletedMode {
+	#[default]
+	Exclude,
+	Include,
+	Only,
+}
+
+impl DeletedMode {
+	pub fn allows(&self, deleted: bool) -> bool {
+		return match self {
+			DeletedMode::Exclude => !deleted,
+			DeletedMode::Include => true,
+			DeletedMode::Only => deleted,
+		};
+	}
+}
+
+impl DeletedVisibility {
+	pub fn from_uri(uri: &Uri) -> Result<DeletedMode, AppError> {
+		let Query(visibility) = Query::<DeletedVisibility>::try_from_uri(uri)
+			.map_err(|err| AppError::BadRequest(t(Message::InvalidDeletedParams(&err.body_text()))))?;
+
+		return match (
+			visibility.include_deleted.unwrap_or(false),
+			visibility.only_deleted.unwrap_or(false),
+		) {
+			(true, true) => Err(AppError::BadRequest(t(Message::DeletedModesConflict))),
+			(true, false) => Ok(DeletedMode::Include),
+			(false, true) => Ok(DeletedMode::Only),
+			(false, false) => Ok(DeletedMode::Exclude),
+		};
+	}
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for DeletedMode {
+	type Rejection = AppError;
+
+	async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
+		return DeletedVisibility::from_uri(req.uri());
+	}
+}
+
+const DEFAULT_PAGE_LIMIT: u32 = 100;
+const MAX_PAGE_LIMIT: u32 = 1000;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Pagination {
+	/// Page size, 100 by default and 1000 at most
+	pub limit: Option<u32>,
+	/// Number of rows to skip, can't be combined with `cursor`
+	pub offset: Option<u32>,
+	/// Opaque cursor taken from `next_cursor` of the previous page
+	pub cursor: Option<String>,
+	/// Count all rows matching the filters and return it as `total`
+	pub with_total: Option<bool>,
+}
+
+/// Keyset position of a row in the `(date_created, id)` ordering
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+	pub date_created: DateTime<Utc>,
+	pub id: Uuid,
+}
+
+impl Cursor {
+	pub fn encode(&self) -> String {
+		let raw = format!(
+			"{}|{}",
+			self
+				.date_created
+				.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+			self.id
+		);
+		return URL_SAFE_NO_PAD.encode(raw);
+	}
+
+	pub fn decode(encoded: &str) -> Result<Self, AppError> {
+		let invalid = || AppError::BadRequest(t(Message::InvalidCursor));
+
+		let raw = URL_SAFE_NO_PAD.decode(encoded).map_err(|_| invalid())?;
+		let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+		let (date_created, id) = raw.split_once('|').ok_or_else(invalid)?;
+
+		return Ok(Cursor {
+			date_created: DateTime::parse_from_rfc3339(date_created)
+				.map_err(|_| invalid())?
+				.with_timezone(&Utc),
+			id: Uuid::parse_str(id).map_err(|_| invalid())?,
+		});
+	}
+}
+
+/// Validated paging parameters passed down to the stores
+#[derive(Debug, Clone, Copy)]
+pub struct Page {
+	pub limit: u32,
+	pub offset: u32,
+	pub after: Option<Cursor>,
+	pub with_total: bool,
+}
+
+impl Pagination {
+	pub fn from_uri(uri: &Uri) -> Result<Page, AppError> {
+		let Query(pagination) = Query::<Pagination>::try_from_uri(uri).map_err(|err| {
+			AppError::BadRequest(t(Message::InvalidPaginationParams(&err.body_text())))
+		})?;
+
+		let limit = pagination.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+
+		if limit == 0 || limit > MAX_PAGE_LIMIT {
+			return Err(AppError::BadRequest(t(Message::PageLimit(MAX_PAGE_LIMIT))));
+		}
+
+		if pagination.cursor.is_some() && pagination.offset.is_some() {
+			return Err(AppError::BadRequest(t(Message::CursorWithOffset)));
+		}
+
+		let after = match pagination.cursor {
+			None => None,
+			Some(encoded) => Some(Cursor::decode(&encoded)?),
+		};
+
+		return Ok(Page {
+			limit,
+			offset: pagination.offset.unwrap_or(0),
+			after,
+			with_total: pagination.with_total.unwrap_or(false),
+		});
+	}
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for Page {
+	type Rejection = AppError;
+
+	async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
+		return Pagination::from_uri(req.uri());
+	}
+}
+
 pub struct TxId(pub Uuid);
 
 impl TxId {
 	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
-		let id_param = uri.to_string().split("/").map(|s| s.to_owned()).last();
+		let id_param = uri
+			.path()
+			.split("/")
+			.skip_while(|s| *s != "transactions")
+			.nth(1)
+			.map(|s| s.to_owned());
 
 		if id_param.is_none() {
-			return Err(AppError::BadRequest(String::from(
-				"Некорректный путь запроса",
-			)));
+			return Err(AppError::BadRequest(t(Message::InvalidPath)));
 		}
 
 		let id_param = id_param.unwrap();
@@ -86,9 +1065,7 @@ impl TxId {
 		let tx_id = Uuid::parse_str(&id_param);
 
 		if tx_id.is_err() {
-			return Err(AppError::BadRequest(String::from(
-				"Некорректное значение идентификатора транзакции",
-			)));
+			return Err(AppError::BadRequest(t(Message::InvalidTransactionId)));
 		}
 
 		return Ok(TxId(tx_id.unwrap()));
@@ -104,6 +1081,101 @@ impl<S> FromRequest<S> for TxId {
 	}
 }
 
+/// Parsed `If-Match` or `If-None-Match` header
+#[derive(Debug, Clone)]
+pub enum ETagCondition {
+	Any,
+	Versions(Vec<i64>),
+}
+
+impl ETagCondition {
+	fn from_header(
+		headers: &HeaderMap,
+		name: header::HeaderName,
+		weak_allowed: bool,
+	) -> Result<Option<Self>, AppError> {
+		let value = match headers.get(&name) {
+			None => return Ok(None),
+			Some(value) => value
+				.to_str()
+				.map_err(|_| AppError::BadRequest(t(Message::InvalidHeader(name.as_str()))))?,
+		};
+
+		if value.trim() == "*" {
+			return Ok(Some(ETagCondition::Any));
+		}
+
+		// tags which were not issued by this service simply never match
+		let versions = value
+			.split(',')
+			.map(|tag| tag.trim())
+			.filter_map(|tag| match tag.strip_prefix("W/") {
+				Some(weak_tag) if weak_allowed => Some(weak_tag),
+				Some(_) => None,
+				None => Some(tag),
+			})
+			.filter_map(|tag| {
+				tag.strip_prefix('"')?
+					.strip_suffix('"')?
+					.parse::<i64>()
+					.ok()
+			})
+			.collect();
+
+		return Ok(Some(ETagCondition::Versions(versions)));
+	}
+
+	/// `If-Match` uses the strong comparison, so weak tags never match
+	pub fn if_match(headers: &HeaderMap) -> Result<Option<Self>, AppError> {
+		return ETagCondition::from_header(headers, header::IF_MATCH, false);
+	}
+
+	pub fn if_none_match(headers: &HeaderMap) -> Result<Option<Self>, AppError> {
+		return ETagCondition::from_header(headers, header::IF_NONE_MATCH, true);
+	}
+
+	pub fn matches(&self, version: i64) -> bool {
+		return match self {
+			ETagCondition::Any => true,
+			ETagCondition::Versions(versions) => versions.contains(&version),
+		};
+	}
+
+	pub fn check(if_match: &Option<Self>, tx_id: Uuid, version: i64) -> Result<(), AppError> {
+		return match if_match {
+			Some(condition) if !condition.matches(version) => Err(AppError::PreconditionFailed(t(
+				Message::TransactionModified(tx_id, version),
+			))),
+			_ => Ok(()),
+		};
+	}
+}
+
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+pub struct IdempotencyKey(pub String);
+
+impl IdempotencyKey {
+	pub fn from_headers(headers: &HeaderMap) -> Result<Option<Self>, AppError> {
+		let key_header = match headers.get("Idempotency-Key") {
+			None => return Ok(None),
+			Some(value) => value
+				.to_str()
+				.map_err(|_| AppError::BadRequest(t(Message::InvalidHeader("Idempotency-Key"))))?,
+		};
+
+		let key = key_header.trim();
+
+		if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+			return Err(AppError::BadRequest(t(Message::IdempotencyKeyLength(
+				MAX_IDEMPOTENCY_KEY_LENGTH,
+			))));
+		}
+
+		return Ok(Some(IdempotencyKey(key.to_owned())));
+	}
+}
+
 pub struct UserId(pub Uuid);
 
 impl UserId {
@@ -111,17 +1183,13 @@ impl UserId {
 		let user_id_header = headers.get("X-USER-ID");
 
 		if user_id_header.is_none() {
-			return Err(AppError::BadRequest(String::from(
-				"Не передан заголовок X-USER-ID",
-			)));
+			return Err(AppError::BadRequest(t(Message::MissingHeader("X-USER-ID"))));
 		}
 
 		let user_id_header = user_id_header.unwrap().to_str();
 
 		if user_id_header.is_err() {
-			return Err(AppError::BadRequest(String::from(
-				"Некорректное значение заголовка X-USER-ID",
-			)));
+			return Err(AppError::BadRequest(t(Message::InvalidHeader("X-USER-ID"))));
 		}
 
 		let user_id_header = user_id_header.unwrap();
@@ -129,11 +1197,562 @@ impl UserId {
 		let user_id = Uuid::parse_str(user_id_header);
 
 		if user_id.is_err() {
-			return Err(AppError::BadRequest(String::from(
-				"Некорректное значение заголовка X-USER-ID",
-			)));
+			return Err(AppError::BadRequest(t(Message::InvalidHeader("X-USER-ID"))));
 		}
 
 		return Ok(UserId(user_id.unwrap()));
 	}
 }
+
+/// A user listed in `ADMIN_USER_IDS`
+pub struct AdminId(pub Uuid);
+
+impl AdminId {
+	pub fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+		let UserId(user_id) = UserId::from_headers(headers)?;
+
+		if !config::get_admin_user_ids().contains(&user_id) {
+			return Err(AppError::Forbidden(t(Message::AdminOnly)));
+		}
+
+		return Ok(AdminId(user_id));
+	}
+}
+
+/// Month of an accounting period, given as `YYYY-MM` and kept as its first day
+pub struct PeriodMonth(pub NaiveDate);
+
+impl PeriodMonth {
+	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
+		let month = uri
+			.path()
+			.split('/')
+			.skip_while(|s| *s != "closed-periods")
+			.nth(1)
+			.unwrap_or_default();
+
+		return match NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d") {
+			Ok(month) => Ok(PeriodMonth(month)),
+			Err(_) => Err(AppError::BadRequest(t(Message::InvalidPeriod))),
+		};
+	}
+}
+
+/// Reference directory a transaction points to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DirectoryKind {
+	GasStations,
+	Cards,
+	Contracts,
+	Nomenclatures,
+}
+
+impl DirectoryKind {
+	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
+		let kind = uri
+			.path()
+			.split('/')
+			.skip_while(|s| *s != "directories")
+			.nth(1)
+			.unwrap_or_default();
+
+		return match kind {
+			"gas_stations" => Ok(DirectoryKind::GasStations),
+			"cards" => Ok(DirectoryKind::Cards),
+			"contracts" => Ok(DirectoryKind::Contracts),
+			"nomenclatures" => Ok(DirectoryKind::Nomenclatures),
+			_ => Err(AppError::BadRequest(t(Message::UnknownDirectory(kind)))),
+		};
+	}
+
+	/// Name of the directory in `expand`, the same as the embedded field of `Transaction`
+	fn from_expand(raw: &str) -> Option<Self> {
+		return match raw {
+			"gas_station" => Some(DirectoryKind::GasStations),
+			"card" => Some(DirectoryKind::Cards),
+			"contract" => Some(DirectoryKind::Contracts),
+			"nomenclature" => Some(DirectoryKind::Nomenclatures),
+			_ => None,
+		};
+	}
+
+	pub fn table(&self) -> &'static str {
+		return match self {
+			DirectoryKind::GasStations => "gas_stations",
+			DirectoryKind::Cards => "cards",
+			DirectoryKind::Contracts => "contracts",
+			DirectoryKind::Nomenclatures => "nomenclatures",
+		};
+	}
+
+	/// Only nomenclature has a unit of measure
+	pub fn has_unit(&self) -> bool {
+		return *self == DirectoryKind::Nomenclatures;
+	}
+}
+
+pub struct DirectoryEntryId(pub Uuid);
+
+impl DirectoryEntryId {
+	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
+		let id = uri
+			.path()
+			.split('/')
+			.skip_while(|s| *s != "directories")
+			.nth(2)
+			.unwrap_or_default();
+
+		return match Uuid::parse_str(id) {
+			Ok(id) => Ok(DirectoryEntryId(id)),
+			Err(_) => Err(AppError::BadRequest(t(Message::InvalidDirectoryEntryId))),
+		};
+	}
+}
+
+const MAX_DIRECTORY_NAME_LENGTH: usize = 255;
+const MAX_DIRECTORY_CODE_LENGTH: usize = 64;
+const MAX_DIRECTORY_UNIT_LENGTH: usize = 32;
+
+fn default_active() -> bool {
+	return true;
+}
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct ApiDirectoryEntry {
+	pub name: String,
+	/// Unique within the directory
+	pub code: String,
+	#[serde(default = "default_active")]
+	pub active: bool,
+	/// Unit of measure, required for nomenclature and not allowed elsewhere
+	pub unit: Option<String>,
+}
+
+impl ApiDirectoryEntry {
+	pub fn validate(&self, kind: DirectoryKind) -> Result<(), AppError> {
+		let violation = |field: &str, message: String| FieldViolation {
+			pointer: format!("/{field}"),
+			message,
+		};
+		let mut violations = Vec::new();
+
+		let lengths = [
+			("name", Some(&self.name), MAX_DIRECTORY_NAME_LENGTH),
+			("code", Some(&self.code), MAX_DIRECTORY_CODE_LENGTH),
+			("unit", self.unit.as_ref(), MAX_DIRECTORY_UNIT_LENGTH),
+		];
+
+		for (field, value, max_length) in lengths {
+			match value.map(|value| value.trim()) {
+				Some("") => violations.push(violation(field, t(Message::EmptyValue))),
+				Some(value) if value.chars().count() > max_length => {
+					violations.push(violation(field, t(Message::ValueTooLong(max_length))))
+				}
+				_ => {}
+			}
+		}
+
+		match (kind.has_unit(), &self.unit) {
+			(true, None) => violations.push(violation("unit", t(Message::UnitRequired))),
+			(false, Some(_)) => violations.push(violation("unit", t(Message::UnitNotAllowed))),
+			_ => {}
+		}
+
+		return match violations.is_empty() {
+			true => Ok(()),
+			false => Err(AppError::Validation(violations)),
+		};
+	}
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for ApiDirectoryEntry {
+	type Rejection = AppError;
+
+	async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
+		let kind = DirectoryKind::from_uri(req.uri())?;
+		let body = req.extract::<Json<ApiDirectoryEntry>, _>().await;
+
+		let dto = match body {
+			Err(err) => return Err(json_rejection_to_error(err)),
+			Ok(Json(dto)) => ApiDirectoryEntry {
+				name: dto.name.trim().to_owned(),
+				code: dto.code.trim().to_owned(),
+				unit: dto.unit.map(|unit| unit.trim().to_owned()),
+				..dto
+			},
+		};
+
+		dto.validate(kind)?;
+		return Ok(dto);
+	}
+}
+
+pub struct ImplementationId(pub Uuid);
+
+impl ImplementationId {
+	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
+		let id = uri
+			.path()
+			.split('/')
+			.skip_while(|s| *s != "implementations")
+			.nth(1)
+			.unwrap_or_default();
+
+		return match Uuid::parse_str(id) {
+			Ok(id) => Ok(ImplementationId(id)),
+			Err(_) => Err(AppError::BadRequest(t(Message::InvalidImplementationId))),
+		};
+	}
+}
+
+const MAX_IMPLEMENTATION_NUMBER_LENGTH: usize = 64;
+
+#[derive(Deserialize, Debug, Clone, ToSchema)]
+pub struct ApiImplementation {
+	/// Unique among all documents
+	pub number: String,
+	pub doc_date: NaiveDate,
+	/// Contract of the counterparty
+	pub contract_id: Uuid,
+}
+
+impl ApiImplementation {
+	pub fn validate(&self) -> Result<(), AppError> {
+		let message = match self.number.chars().count() {
+			0 => t(Message::EmptyValue),
+			length if length > MAX_IMPLEMENTATION_NUMBER_LENGTH => {
+				t(Message::ValueTooLong(MAX_IMPLEMENTATION_NUMBER_LENGTH))
+			}
+			_ => return Ok(()),
+		};
+
+		return Err(AppError::Validation(vec![FieldViolation {
+			pointer: String::from("/number"),
+			message,
+		}]));
+	}
+}
+
+#[async_trait]
+impl<S> FromRequest<S> for ApiImplementation {
+	type Rejection = AppError;
+
+	async fn from_request(req: Request, _: &S) -> Result<Self, Self::Rejection> {
+		let body = req.extract::<Json<ApiImplementation>, _>().await;
+
+		let dto = match body {
+			Err(err) => return Err(json_rejection_to_error(err)),
+			Ok(Json(dto)) => ApiImplementation {
+				number: dto.number.trim().to_owned(),
+				..dto
+			},
+		};
+
+		dto.validate()?;
+		return Ok(dto);
+	}
+}
+
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImplementationsFilter {
+	pub status: Option<ImplementationStatus>,
+	pub contract_id: Option<Uuid>,
+}
+
+impl ImplementationsFilter {
+	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
+		return match Query::<ImplementationsFilter>::try_from_uri(uri) {
+			Ok(Query(filter)) => Ok(filter),
+			Err(err) => Err(AppError::BadRequest(t(Message::InvalidFilterParams(
+				&err.body_text(),
+			)))),
+		};
+	}
+}
+
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DirectoryFilter {
+	pub active: Option<bool>,
+}
+
+impl DirectoryFilter {
+	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
+		return match Query::<DirectoryFilter>::try_from_uri(uri) {
+			Ok(Query(filter)) => Ok(filter),
+			Err(err) => Err(AppError::BadRequest(t(Message::InvalidFilterParams(
+				&err.body_text(),
+			)))),
+		};
+	}
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExpandParams {
+	/// Comma separated `gas_station`, `card`, `contract`, `nomenclature` to embed into transactions
+	pub expand: Option<String>,
+}
+
+impl ExpandParams {
+	pub fn from_uri(uri: &Uri) -> Result<Vec<DirectoryKind>, AppError> {
+		let Query(params) = Query::<ExpandParams>::try_from_uri(uri)
+			.map_err(|err| AppError::BadRequest(t(Message::InvalidExpandParams(&err.body_text()))))?;
+
+		let mut kinds: Vec<DirectoryKind> = Vec::new();
+
+		for raw in params
+			.expand
+			.iter()
+			.flat_map(|expand| expand.split(','))
+			.map(|raw| raw.trim())
+			.filter(|raw| !raw.is_empty())
+		{
+			let Some(kind) = DirectoryKind::from_expand(raw) else {
+				return Err(AppError::BadRequest(t(Message::UnknownExpand(raw))));
+			};
+
+			if !kinds.contains(&kind) {
+				kinds.push(kind);
+			}
+		}
+
+		return Ok(kinds);
+	}
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CsvExportParams {
+	/// Single ASCII character between the fields, `,` by default, `;` suits Excel with the Russian locale
+	pub delimiter: Option<String>,
+	/// `.` by default or `,`
+	pub decimal_separator: Option<String>,
+	/// Language of the header row, `ru` or `en`, the language of the request by default
+	pub lang: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CsvFormat {
+	pub delimiter: u8,
+	pub decimal_separator: char,
+	pub lang: Lang,
+}
+
+impl CsvExportParams {
+	pub fn from_uri(uri: &Uri) -> Result<CsvFormat, AppError> {
+		let Query(params) = Query::<CsvExportParams>::try_from_uri(uri)
+			.map_err(|err| AppError::BadRequest(t(Message::InvalidExportParams(&err.body_text()))))?;
+
+		return Ok(CsvFormat {
+			delimiter: parse_delimiter(params.delimiter.as_deref())?,
+			decimal_separator: parse_decimal_separator(params.decimal_separator.as_deref())?,
+			lang: export_lang(params.lang.as_deref())?,
+		});
+	}
+}
+
+fn parse_delimiter(raw: Option<&str>) -> Result<u8, AppError> {
+	return match raw.map(str::as_bytes) {
+		None => Ok(b','),
+		Some([byte]) if byte.is_ascii() && !matches!(byte, b'"' | b'\r' | b'\n') => Ok(*byte),
+		Some(_) => Err(AppError::BadRequest(t(Message::InvalidCsvDelimiter))),
+	};
+}
+
+fn parse_decimal_separator(raw: Option<&str>) -> Result<char, AppError> {
+	return match raw {
+		None | Some(".") => Ok('.'),
+		Some(",") => Ok(','),
+		Some(_) => Err(AppError::BadRequest(t(Message::InvalidDecimalSeparator))),
+	};
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct XlsxExportParams {
+	/// Language of the sheet names and the headers, `ru` or `en`, the language of the request by default
+	pub lang: Option<String>,
+}
+
+impl XlsxExportParams {
+	pub fn from_uri(uri: &Uri) -> Result<Lang, AppError> {
+		let Query(params) = Query::<XlsxExportParams>::try_from_uri(uri)
+			.map_err(|err| AppError::BadRequest(t(Message::InvalidExportParams(&err.body_text()))))?;
+
+		return export_lang(params.lang.as_deref());
+	}
+}
+
+fn export_lang(raw: Option<&str>) -> Result<Lang, AppError> {
+	return match raw {
+		None => Ok(current_lang()),
+		Some(raw) => {
+			Lang::from_str(raw).map_err(|_| AppError::BadRequest(t(Message::UnknownLanguage(raw))))
+		}
+	};
+}
+
+/// Lines of a CSV file which can be imported at once
+pub const MAX_IMPORT_LINES: usize = 100_000;
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportParams {
+	/// Single ASCII character between the fields, `,` by default
+	pub delimiter: Option<String>,
+	/// `.` by default or `,`
+	pub decimal_separator: Option<String>,
+	/// Check every line against the stored data without writing anything
+	pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+	pub delimiter: u8,
+	pub decimal_separator: char,
+	pub dry_run: bool,
+}
+
+impl ImportOptions {
+	pub fn new(
+		delimiter: Option<&str>,
+		decimal_separator: Option<&str>,
+		dry_run: bool,
+	) -> Result<Self, AppError> {
+		return Ok(ImportOptions {
+			delimiter: parse_delimiter(delimiter)?,
+			decimal_separator: parse_decimal_separator(decimal_separator)?,
+			dry_run,
+		});
+	}
+}
+
+impl ImportParams {
+	pub fn from_uri(uri: &Uri) -> Result<ImportOptions, AppError> {
+		let Query(params) = Query::<ImportParams>::try_from_uri(uri)
+			.map_err(|err| AppError::BadRequest(t(Message::InvalidImportParams(&err.body_text()))))?;
+
+		return ImportOptions::new(
+			params.delimiter.as_deref(),
+			params.decimal_separator.as_deref(),
+			params.dry_run.unwrap_or(false),
+		);
+	}
+}
+
+/// Header of the CSV column holding each transaction field. A field which is not mapped
+/// is looked up by its own name and by its title in the CSV export
+#[derive(Deserialize, Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportColumns {
+	pub op_date: Option<String>,
+	pub gas_station_id: Option<String>,
+	pub card_id: Option<String>,
+	pub contract_id: Option<String>,
+	pub nomenclature_id: Option<String>,
+	pub amount: Option<String>,
+	pub stella_sum: Option<String>,
+	pub stella_nds_sum: Option<String>,
+	pub refund: Option<String>,
+	pub buy_sum_plan: Option<String>,
+	pub buy_nds_sum_plan: Option<String>,
+	pub buy_sum_fact: Option<String>,
+	pub buy_nds_sum_fact: Option<String>,
+	pub sell_sum_plan: Option<String>,
+	pub sell_nds_sum_plan: Option<String>,
+	pub sell_sum_fact: Option<String>,
+	pub sell_nds_sum_fact: Option<String>,
+	pub implementation_id: Option<String>,
+	pub original_transaction_id: Option<String>,
+}
+
+impl ImportColumns {
+	pub fn from_uri(uri: &Uri) -> Result<Self, AppError> {
+		return match Query::<ImportColumns>::try_from_uri(uri) {
+			Ok(Query(columns)) => Ok(columns),
+			Err(err) => Err(AppError::BadRequest(t(Message::InvalidImportParams(
+				&err.body_text(),
+			)))),
+		};
+	}
+
+	/// Builds the mapping out of `field=Header` pairs
+	pub fn from_pairs<'a>(pairs: impl IntoIterator<Item = &'a str>) -> Result<Self, AppError> {
+		let known = ImportColumns::default().fields().map(|(field, _)| field);
+		let mut mapping = serde_json::Map::new();
+
+		for pair in pairs {
+			let (field, header) = pair.split_once('=').unwrap_or((pair, ""));
+
+			if !known.contains(&field) {
+				return Err(AppError::BadRequest(t(Message::UnknownImportField(field))));
+			}
+
+			mapping.insert(field.to_owned(), serde_json::Value::from(header));
+		}
+
+		return serde_json::from_value(serde_json::Value::Object(mapping))
+			.map_err(|err| AppError::BadRequest(t(Message::InvalidImportParams(&err.to_string()))));
+	}
+
+	/// Every field with the header it is mapped to
+	pub fn fields(&self) -> [(&'static str, Option<&str>); 19] {
+		return [
+			("op_date", self.op_date.as_deref()),
+			("gas_station_id", self.gas_station_id.as_deref()),
+			("card_id", self.card_id.as_deref()),
+			("contract_id", self.contract_id.as_deref()),
+			("nomenclature_id", self.nomenclature_id.as_deref()),
+			("amount", self.amount.as_deref()),
+			("stella_sum", self.stella_sum.as_deref()),
+			("stella_nds_sum", self.stella_nds_sum.as_deref()),
+			("refund", self.refund.as_deref()),
+			("buy_sum_plan", self.buy_sum_plan.as_deref()),
+			("buy_nds_sum_plan", self.buy_nds_sum_plan.as_deref()),
+			("buy_sum_fact", self.buy_sum_fact.as_deref()),
+			("buy_nds_sum_fact", self.buy_nds_sum_fact.as_deref()),
+			("sell_sum_plan", self.sell_sum_plan.as_deref()),
+			("sell_nds_sum_plan", self.sell_nds_sum_plan.as_deref()),
+			("sell_sum_fact", self.sell_sum_fact.as_deref()),
+			("sell_nds_sum_fact", self.sell_nds_sum_fact.as_deref()),
+			("implementation_id", self.implementation_id.as_deref()),
+			(
+				"original_transaction_id",
+				self.original_transaction_id.as_deref(),
+			),
+		];
+	}
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct ImportLineError {
+	/// Line of the file the transaction starts at, the header is line 1
+	pub line: usize,
+	pub code: ErrorCode,
+	pub message: String,
+	/// Pointers are relative to the transaction, e.g. `/amount`
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub violations: Vec<FieldViolation>,
+}
+
+impl ImportLineError {
+	pub fn new(line: usize, err: AppError) -> Self {
+		let BatchItemError {
+			code,
+			message,
+			violations,
+			..
+		} = BatchItemError::new(line, err);
+
+		return ImportLineError {
+			line,
+			code,
+			message,
+			violations,
+		};
+	}
+}
@@ -0,0 +1,72 @@
+use ::csv::{Terminator, Writer, WriterBuilder};
+use axum::body::Bytes;
+use chrono::SecondsFormat;
+
+use super::{transaction_cells, Cell, TRANSACTION_COLUMNS};
+use crate::dto::CsvFormat;
+use crate::i18n::{t, Message};
+use crate::repository::models::Transaction;
+use crate::system_models::AppError;
+
+/// Encodes the transaction register as RFC 4180 CSV chunk by chunk
+pub struct CsvEncoder {
+	builder: WriterBuilder,
+	format: CsvFormat,
+}
+
+impl CsvEncoder {
+	pub fn new(format: CsvFormat) -> Self {
+		let mut builder = WriterBuilder::new();
+		builder
+			.delimiter(format.delimiter)
+			.terminator(Terminator::CRLF);
+
+		return Self { builder, format };
+	}
+
+	pub fn header(&self) -> Result<Bytes, AppError> {
+		let lang = self.format.lang;
+		let titles = TRANSACTION_COLUMNS.map(|column| Message::ExportColumn(column).render(lang));
+
+		let mut writer = self.builder.from_writer(Vec::new());
+		writer.write_record(&titles).map_err(encoding_error)?;
+
+		return finish(writer);
+	}
+
+	pub fn rows(&self, txs: &[Transaction]) -> Result<Bytes, AppError> {
+		let mut writer = self.builder.from_writer(Vec::new());
+
+		for tx in txs {
+			let fields = transaction_cells(tx).map(|cell| self.render(cell));
+			writer.write_record(&fields).map_err(encoding_error)?;
+		}
+
+		return finish(writer);
+	}
+
+	fn render(&self, cell: Cell) -> String {
+		return match cell {
+			Cell::Empty => String::new(),
+			Cell::Text(text) => text,
+			Cell::Integer(value) | Cell::Count(value) => value.to_string(),
+			Cell::Bool(value) => value.to_string(),
+			Cell::Quantity(value) | Cell::Money(value) | Cell::Percent(value) => value
+				.to_string()
+				.replace('.', &self.format.decimal_separator.to_string()),
+			Cell::Date(value) => value.format("%Y-%m-%d").to_string(),
+			Cell::DateTime(value) => value.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+		};
+	}
+}
+
+fn finish(writer: Writer<Vec<u8>>) -> Result<Bytes, AppError> {
+	return match writer.into_inner() {
+		Ok(buffer) => Ok(Bytes::from(buffer)),
+		Err(err) => Err(encoding_error(err)),
+	};
+}
+
+fn encoding_error<E>(_: E) -> AppError {
+	return AppError::SystemError(t(Message::InternalError));
+}
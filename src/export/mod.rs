@@ -0,0 +1,309 @@
+mod csv;
+mod xlsx;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::dto::StatsDimension;
+use crate::repository::models::{
+	MarginReport, MarginTotals, Transaction, TransactionsStats, VarianceReport, VarianceTotals,
+};
+
+pub use self::csv::CsvEncoder;
+pub use self::xlsx::{build_xlsx, XlsxEncoder};
+
+/// Typed value of an exported cell, every format renders it its own way
+#[derive(Debug, Clone)]
+pub enum Cell {
+	Empty,
+	Text(String),
+	Integer(i64),
+	/// A number of transactions, summed up in totals
+	Count(i64),
+	Bool(bool),
+	Quantity(Decimal),
+	Money(Decimal),
+	Percent(Decimal),
+	Date(NaiveDate),
+	DateTime(DateTime<Utc>),
+}
+
+impl Cell {
+	fn uuid(id: Option<Uuid>) -> Self {
+		return id.map_or(Cell::Empty, |id| Cell::Text(id.to_string()));
+	}
+
+	fn money(value: Option<Decimal>) -> Self {
+		return value.map_or(Cell::Empty, Cell::Money);
+	}
+}
+
+/// Columns of the transaction register, the names double as keys of the header translations
+pub const TRANSACTION_COLUMNS: [&str; 32] = [
+	"id",
+	"op_date",
+	"gas_station_id",
+	"card_id",
+	"contract_id",
+	"nomenclature_id",
+	"amount",
+	"stella_sum",
+	"stella_nds_sum",
+	"refund",
+	"buy_sum_plan",
+	"buy_nds_sum_plan",
+	"buy_sum_fact",
+	"buy_nds_sum_fact",
+	"sell_sum_plan",
+	"sell_nds_sum_plan",
+	"sell_sum_fact",
+	"sell_nds_sum_fact",
+	"margin_plan",
+	"margin_fact",
+	"margin_plan_net",
+	"margin_fact_net",
+	"implementation_id",
+	"original_transaction_id",
+	"user_id",
+	"created_by",
+	"updated_by",
+	"deleted_by",
+	"date_created",
+	"date_updated",
+	"deleted",
+	"version",
+];
+
+/// Cells of a transaction in the order of `TRANSACTION_COLUMNS`
+pub fn transaction_cells(tx: &Transaction) -> [Cell; TRANSACTION_COLUMNS.len()] {
+	return [
+		Cell::Text(tx.id.to_string()),
+		Cell::DateTime(tx.op_date),
+		Cell::Text(tx.gas_station_id.to_string()),
+		Cell::uuid(tx.card_id),
+		Cell::uuid(tx.contract_id),
+		Cell::Text(tx.nomenclature_id.to_string()),
+		tx.amount.map_or(Cell::Empty, Cell::Quantity),
+		Cell::money(tx.stella_sum),
+		Cell::money(tx.stella_nds_sum),
+		Cell::Bool(tx.refund),
+		Cell::money(tx.buy_sum_plan),
+		Cell::money(tx.buy_nds_sum_plan),
+		Cell::money(tx.buy_sum_fact),
+		Cell::money(tx.buy_nds_sum_fact),
+		Cell::money(tx.sell_sum_plan),
+		Cell::money(tx.sell_nds_sum_plan),
+		Cell::money(tx.sell_sum_fact),
+		Cell::money(tx.sell_nds_sum_fact),
+		Cell::money(tx.margin_plan),
+		Cell::money(tx.margin_fact),
+		Cell::money(tx.margin_plan_net),
+		Cell::money(tx.margin_fact_net),
+		Cell::uuid(tx.implementation_id),
+		Cell::uuid(tx.original_transaction_id),
+		Cell::Text(tx.user_id.to_string()),
+		Cell::Text(tx.created_by.to_string()),
+		Cell::uuid(tx.updated_by),
+		Cell::uuid(tx.deleted_by),
+		Cell::DateTime(tx.date_created),
+		tx.date_updated.map_or(Cell::Empty, Cell::DateTime),
+		Cell::Bool(tx.deleted),
+		Cell::Integer(tx.version),
+	];
+}
+
+/// A sheet of an exported report, the name and the columns are keys of the translations
+pub struct Table {
+	pub name: &'static str,
+	pub columns: Vec<&'static str>,
+	pub rows: Vec<Vec<Cell>>,
+}
+
+const STATS_SUM_COLUMNS: [&str; 13] = [
+	"count",
+	"refund_count",
+	"amount",
+	"stella_sum",
+	"stella_nds_sum",
+	"buy_sum_plan",
+	"buy_nds_sum_plan",
+	"buy_sum_fact",
+	"buy_nds_sum_fact",
+	"sell_sum_plan",
+	"sell_nds_sum_plan",
+	"sell_sum_fact",
+	"sell_nds_sum_fact",
+];
+
+/// One sheet with a column per grouping key followed by the sums
+pub fn stats_tables(stats: &TransactionsStats) -> Vec<Table> {
+	let keys: Vec<&'static str> = stats
+		.group_by
+		.iter()
+		.map(|dimension| match dimension {
+			StatsDimension::GasStationId => "gas_station_id",
+			StatsDimension::CardId => "card_id",
+			StatsDimension::ContractId => "contract_id",
+			StatsDimension::NomenclatureId => "nomenclature_id",
+			StatsDimension::Day | StatsDimension::Week | StatsDimension::Month => "period",
+		})
+		.collect();
+
+	let rows = stats
+		.rows
+		.iter()
+		.map(|row| {
+			let mut cells: Vec<Cell> = keys
+				.iter()
+				.map(|key| match *key {
+					"gas_station_id" => Cell::uuid(row.gas_station_id),
+					"card_id" => Cell::uuid(row.card_id),
+					"contract_id" => Cell::uuid(row.contract_id),
+					"nomenclature_id" => Cell::uuid(row.nomenclature_id),
+					_ => row.period.map_or(Cell::Empty, Cell::Date),
+				})
+				.collect();
+
+			cells.extend([
+				Cell::Count(row.count),
+				Cell::Count(row.refund_count),
+				Cell::Quantity(row.amount),
+				Cell::Money(row.stella_sum),
+				Cell::Money(row.stella_nds_sum),
+				Cell::Money(row.buy_sum_plan),
+				Cell::Money(row.buy_nds_sum_plan),
+				Cell::Money(row.buy_sum_fact),
+				Cell::Money(row.buy_nds_sum_fact),
+				Cell::Money(row.sell_sum_plan),
+				Cell::Money(row.sell_nds_sum_plan),
+				Cell::Money(row.sell_sum_fact),
+				Cell::Money(row.sell_nds_sum_fact),
+			]);
+
+			return cells;
+		})
+		.collect();
+
+	let mut columns = keys;
+	columns.extend(STATS_SUM_COLUMNS);
+
+	return vec![Table {
+		name: "stats",
+		columns,
+		rows,
+	}];
+}
+
+fn variance_totals_table(
+	name: &'static str,
+	key: &'static str,
+	totals: &[VarianceTotals],
+) -> Table {
+	return Table {
+		name,
+		columns: vec![key, "count", "buy_variance", "sell_variance"],
+		rows: totals
+			.iter()
+			.map(|group| {
+				vec![
+					Cell::uuid(group.id),
+					Cell::Count(group.count),
+					Cell::Money(group.buy_variance),
+					Cell::Money(group.sell_variance),
+				]
+			})
+			.collect(),
+	};
+}
+
+/// The deviating transactions followed by their totals per contract and per station
+pub fn variance_tables(report: &VarianceReport) -> Vec<Table> {
+	let transactions = Table {
+		name: "transactions",
+		columns: vec![
+			"id",
+			"op_date",
+			"gas_station_id",
+			"contract_id",
+			"nomenclature_id",
+			"refund",
+			"buy_sum_plan",
+			"buy_sum_fact",
+			"buy_variance",
+			"buy_variance_pct",
+			"sell_sum_plan",
+			"sell_sum_fact",
+			"sell_variance",
+			"sell_variance_pct",
+		],
+		rows: report
+			.transactions
+			.iter()
+			.map(|tx| {
+				vec![
+					Cell::Text(tx.id.to_string()),
+					Cell::DateTime(tx.op_date),
+					Cell::Text(tx.gas_station_id.to_string()),
+					Cell::uuid(tx.contract_id),
+					Cell::Text(tx.nomenclature_id.to_string()),
+					Cell::Bool(tx.refund),
+					Cell::money(tx.buy_sum_plan),
+					Cell::money(tx.buy_sum_fact),
+					Cell::money(tx.buy_variance),
+					tx.buy_variance_pct.map_or(Cell::Empty, Cell::Percent),
+					Cell::money(tx.sell_sum_plan),
+					Cell::money(tx.sell_sum_fact),
+					Cell::money(tx.sell_variance),
+					tx.sell_variance_pct.map_or(Cell::Empty, Cell::Percent),
+				]
+			})
+			.collect(),
+	};
+
+	return vec![
+		transactions,
+		variance_totals_table("by_contract", "contract_id", &report.by_contract),
+		variance_totals_table("by_station", "gas_station_id", &report.by_station),
+	];
+}
+
+fn margin_table(name: &'static str, key: &'static str, totals: &[MarginTotals]) -> Table {
+	return Table {
+		name,
+		columns: vec![
+			key,
+			"count",
+			"margin_plan",
+			"margin_fact",
+			"margin_plan_net",
+			"margin_fact_net",
+		],
+		rows: totals
+			.iter()
+			.map(|group| {
+				vec![
+					Cell::uuid(group.id),
+					Cell::Count(group.count),
+					Cell::Money(group.margin_plan),
+					Cell::Money(group.margin_fact),
+					Cell::Money(group.margin_plan_net),
+					Cell::Money(group.margin_fact_net),
+				]
+			})
+			.collect(),
+	};
+}
+
+/// A sheet per section, the totals row of each one is the overall total of the report
+pub fn margin_tables(report: &MarginReport) -> Vec<Table> {
+	return vec![
+		margin_table("by_contract", "contract_id", &report.by_contract),
+		margin_table("by_station", "gas_station_id", &report.by_station),
+		margin_table(
+			"by_nomenclature",
+			"nomenclature_id",
+			&report.by_nomenclature,
+		),
+	];
+}
@@ -0,0 +1,334 @@
+use axum::body::Bytes;
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+use rust_xlsxwriter::{
+	cell_range, ColNum, Format, Formula, RowNum, Workbook, Worksheet, XlsxError,
+};
+
+use super::{Cell, Table};
+use crate::i18n::{t, Lang, Message};
+use crate::system_models::AppError;
+
+/// Rows of an Excel sheet including the header and the totals
+const EXCEL_ROWS: RowNum = 1_048_576;
+
+/// Columns of a streamed sheet don't grow wider than this many characters
+const MAX_COLUMN_WIDTH: usize = 60;
+
+struct Formats {
+	header: Format,
+	quantity: Format,
+	money: Format,
+	percent: Format,
+	date: Format,
+	datetime: Format,
+	total: Format,
+	total_quantity: Format,
+	total_money: Format,
+}
+
+impl Formats {
+	fn new(lang: Lang) -> Self {
+		let date = match lang {
+			Lang::Ru => "dd.mm.yyyy",
+			Lang::En => "yyyy-mm-dd",
+		};
+
+		return Self {
+			header: Format::new().set_bold(),
+			quantity: Format::new().set_num_format("#,##0.000"),
+			money: Format::new().set_num_format("#,##0.00"),
+			percent: Format::new().set_num_format("0.00"),
+			date: Format::new().set_num_format(date),
+			datetime: Format::new().set_num_format(format!("{date} hh:mm:ss")),
+			total: Format::new().set_bold(),
+			total_quantity: Format::new().set_bold().set_num_format("#,##0.000"),
+			total_money: Format::new().set_bold().set_num_format("#,##0.00"),
+		};
+	}
+}
+
+/// Running sum of a column, columns of other cells have no total
+#[derive(Clone, Copy)]
+enum Total {
+	None,
+	Count(i64),
+	Quantity(Decimal),
+	Money(Decimal),
+}
+
+impl Total {
+	fn add(&mut self, cell: &Cell) {
+		let next = match (*self, cell) {
+			(Total::None, Cell::Count(value)) => Total::Count(*value),
+			(Total::Count(sum), Cell::Count(value)) => Total::Count(sum + value),
+			(Total::None, Cell::Quantity(value)) => Total::Quantity(*value),
+			(Total::Quantity(sum), Cell::Quantity(value)) => Total::Quantity(sum + value),
+			(Total::None, Cell::Money(value)) => Total::Money(*value),
+			(Total::Money(sum), Cell::Money(value)) => Total::Money(sum + value),
+			_ => return,
+		};
+
+		*self = next;
+	}
+}
+
+/// Builds an Excel workbook in memory, a sheet after another
+pub struct XlsxEncoder {
+	workbook: Workbook,
+	formats: Formats,
+	lang: Lang,
+}
+
+/// A sheet being filled, the header is frozen and the totals row follows the data
+pub struct XlsxSheet<'a> {
+	worksheet: &'a mut Worksheet,
+	formats: &'a Formats,
+	lang: Lang,
+	row: RowNum,
+	totals: Vec<Total>,
+	/// Widths of a streamed sheet, its flushed rows are out of reach of `autofit`
+	widths: Option<Vec<usize>>,
+}
+
+impl XlsxEncoder {
+	pub fn new(lang: Lang) -> Self {
+		return Self {
+			workbook: Workbook::new(),
+			formats: Formats::new(lang),
+			lang,
+		};
+	}
+
+	pub fn sheet(&mut self, name: &str, columns: &[&str]) -> Result<XlsxSheet<'_>, AppError> {
+		return self.add_sheet(name, columns, false);
+	}
+
+	/// A sheet whose rows are flushed to a temporary file as they are written instead of
+	/// being kept as cells. Rows can't be changed once the next one is written, and the saved
+	/// workbook is still buffered whole
+	pub fn streaming_sheet(
+		&mut self,
+		name: &str,
+		columns: &[&str],
+	) -> Result<XlsxSheet<'_>, AppError> {
+		return self.add_sheet(name, columns, true);
+	}
+
+	fn add_sheet(
+		&mut self,
+		name: &str,
+		columns: &[&str],
+		constant_memory: bool,
+	) -> Result<XlsxSheet<'_>, AppError> {
+		let worksheet = match constant_memory {
+			true => self.workbook.add_worksheet_with_constant_memory(),
+			false => self.workbook.add_worksheet(),
+		};
+		worksheet
+			.set_name(Message::ExportSheet(name).render(self.lang))
+			.map_err(encoding_error)?;
+
+		let titles: Vec<String> = columns
+			.iter()
+			.map(|column| Message::ExportColumn(column).render(self.lang))
+			.collect();
+
+		for (col, title) in (0..).zip(&titles) {
+			worksheet
+				.write_string_with_format(0, col, title, &self.formats.header)
+				.map_err(encoding_error)?;
+		}
+		worksheet.set_freeze_panes(1, 0).map_err(encoding_error)?;
+
+		let widths = constant_memory.then(|| {
+			return titles.iter().map(|title| title.chars().count()).collect();
+		});
+
+		return Ok(XlsxSheet {
+			worksheet,
+			formats: &self.formats,
+			lang: self.lang,
+			row: 1,
+			totals: vec![Total::None; columns.len()],
+			widths,
+		});
+	}
+
+	pub fn table(&mut self, table: &Table) -> Result<(), AppError> {
+		let mut sheet = self.sheet(table.name, &table.columns)?;
+
+		for row in &table.rows {
+			sheet.row(row)?;
+		}
+
+		return sheet.finish();
+	}
+
+	/// Compresses the whole workbook into memory
+	pub fn finish(mut self) -> Result<Bytes, AppError> {
+		let buffer = self.workbook.save_to_buffer().map_err(encoding_error)?;
+
+		return Ok(Bytes::from(buffer));
+	}
+}
+
+/// Fills and saves a workbook away from the async runtime, streamed sheets write
+/// to their temporary files on every row
+pub async fn build_xlsx<F>(build: F) -> Result<Bytes, AppError>
+where
+	F: FnOnce() -> Result<Bytes, AppError> + Send + 'static,
+{
+	return match tokio::task::spawn_blocking(build).await {
+		Ok(result) => result,
+		Err(err) => Err(encoding_error(err)),
+	};
+}
+
+impl XlsxSheet<'_> {
+	pub fn row(&mut self, cells: &[Cell]) -> Result<(), AppError> {
+		// The last row is kept for the totals
+		if self.row >= EXCEL_ROWS - 1 {
+			return Err(AppError::UnprocessableEntity(t(Message::XlsxRowLimit(
+				EXCEL_ROWS - 2,
+			))));
+		}
+
+		for (col, cell) in (0..).zip(cells) {
+			self.write(col, cell).map_err(encoding_error)?;
+		}
+		for (total, cell) in self.totals.iter_mut().zip(cells) {
+			total.add(cell);
+		}
+		if let Some(widths) = &mut self.widths {
+			for (width, cell) in widths.iter_mut().zip(cells) {
+				*width = (*width).max(cell_width(cell));
+			}
+		}
+		self.row += 1;
+
+		return Ok(());
+	}
+
+	/// Sums every numeric column with a formula, the value is stored too for the viewers which don't recalculate
+	pub fn finish(self) -> Result<(), AppError> {
+		let formats = self.formats;
+		let row = self.row;
+
+		if matches!(self.totals.first(), Some(Total::None)) {
+			self
+				.worksheet
+				.write_string_with_format(
+					row,
+					0,
+					Message::ExportTotal.render(self.lang),
+					&formats.total,
+				)
+				.map_err(encoding_error)?;
+		}
+
+		for (col, total) in (0..).zip(&self.totals) {
+			let (result, format) = match total {
+				Total::None => continue,
+				Total::Count(sum) => (sum.to_string(), &formats.total),
+				Total::Quantity(sum) => (sum.to_string(), &formats.total_quantity),
+				Total::Money(sum) => (sum.to_string(), &formats.total_money),
+			};
+
+			let formula =
+				Formula::new(format!("=SUM({})", cell_range(1, col, row - 1, col))).set_result(result);
+			self
+				.worksheet
+				.write_formula_with_format(row, col, formula, format)
+				.map_err(encoding_error)?;
+		}
+
+		match &self.widths {
+			Some(widths) => {
+				for (col, width) in (0..).zip(widths) {
+					// a little room for the bold header and the filter button
+					let width = (width + 2).min(MAX_COLUMN_WIDTH);
+					self
+						.worksheet
+						.set_column_width(col, width as f64)
+						.map_err(encoding_error)?;
+				}
+			}
+			None => {
+				self.worksheet.autofit();
+			}
+		}
+
+		return Ok(());
+	}
+
+	fn write(&mut self, col: ColNum, cell: &Cell) -> Result<(), XlsxError> {
+		let row = self.row;
+		let formats = self.formats;
+
+		match cell {
+			Cell::Empty => {}
+			Cell::Text(text) => {
+				self.worksheet.write_string(row, col, text)?;
+			}
+			Cell::Integer(value) | Cell::Count(value) => {
+				self.worksheet.write_number(row, col, *value as f64)?;
+			}
+			Cell::Bool(value) => {
+				self.worksheet.write_boolean(row, col, *value)?;
+			}
+			Cell::Quantity(value) => {
+				self
+					.worksheet
+					.write_number_with_format(row, col, number(value), &formats.quantity)?;
+			}
+			Cell::Money(value) => {
+				self
+					.worksheet
+					.write_number_with_format(row, col, number(value), &formats.money)?;
+			}
+			Cell::Percent(value) => {
+				self
+					.worksheet
+					.write_number_with_format(row, col, number(value), &formats.percent)?;
+			}
+			Cell::Date(value) => {
+				self
+					.worksheet
+					.write_datetime_with_format(row, col, value, &formats.date)?;
+			}
+			Cell::DateTime(value) => {
+				self.worksheet.write_datetime_with_format(
+					row,
+					col,
+					value.naive_utc(),
+					&formats.datetime,
+				)?;
+			}
+		}
+
+		return Ok(());
+	}
+}
+
+/// Characters a cell takes in its format, close enough to size a column
+fn cell_width(cell: &Cell) -> usize {
+	return match cell {
+		Cell::Empty => 0,
+		Cell::Text(text) => text.chars().count(),
+		Cell::Integer(value) | Cell::Count(value) => value.to_string().len(),
+		Cell::Bool(_) => 5,
+		// digit groups take a separator per three digits
+		Cell::Quantity(value) | Cell::Money(value) => value.to_string().len() * 4 / 3,
+		Cell::Percent(value) => value.to_string().len(),
+		Cell::Date(_) => 10,
+		Cell::DateTime(_) => 19,
+	};
+}
+
+fn number(value: &Decimal) -> f64 {
+	return value.to_f64().unwrap_or_default();
+}
+
+fn encoding_error<E>(_: E) -> AppError {
+	return AppError::SystemError(t(Message::InternalError));
+}
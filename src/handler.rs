@@ -1,46 +1,185 @@
 use crate::{
-	dto::{ApiTransaction, TxId, UserId},
-	repository::{models::Transaction, Repository},
-	system_models::{AppError, Success},
+	dto::{
+		bytes_rejection_to_error, AdminId, ApiDirectoryEntry, ApiImplementation, ApiTransaction,
+		ApiTransactionBatch, ApiTransactionPatch, BatchMode, BatchParams, CsvExportParams,
+		DeletedMode, DeletedVisibility, DirectoryEntryId, DirectoryFilter, DirectoryKind,
+		ETagCondition, ExpandParams, IdempotencyKey, ImplementationId, ImplementationsFilter,
+		ImportColumns, ImportParams, Page, Pagination, PeriodMonth, StatsParams, TransactionsFilter,
+		TxId, UserId, VarianceParams, XlsxExportParams,
+	},
+	export::{
+		build_xlsx, margin_tables, stats_tables, transaction_cells, variance_tables, CsvEncoder,
+		Table, XlsxEncoder, TRANSACTION_COLUMNS,
+	},
+	i18n::Lang,
+	import,
+	repository::{
+		models::{
+			BatchResult, ClosedPeriod, DirectoryEntry, Implementation, ImplementationStatus,
+			ImportReport, MarginReport, Transaction, TransactionHistoryEntry, TransactionRefunds,
+			TransactionsPage, TransactionsStats, VarianceReport,
+		},
+		Repository,
+	},
+	system_models::{AppError, ETag, Problem, Success, WeakETag},
 };
 use ::std::sync::Arc;
 use axum::{
+	body::{Body, Bytes},
 	extract::{FromRequest, Request, State},
-	http::StatusCode,
+	http::{header, StatusCode},
+	response::{IntoResponse, Response},
 };
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
 
 #[utoipa::path(
 	get,
 	path = "/api/v1/transactions",
+	params(TransactionsFilter, DeletedVisibility, Pagination, ExpandParams),
 	responses(
-		(status = 200, description = "Returns a list of transactions", body = [Transaction])
+		(status = 200, description = "Returns a page of transactions", body = TransactionsPage),
+		(status = 400, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
 	)
 )]
 pub async fn get_transactions_list(
 	State(repo): State<Arc<Repository>>,
-) -> Result<Success<Vec<Transaction>>, AppError> {
-	let list = repo.get_transactions_list().await?;
+	req: Request,
+) -> Result<Success<TransactionsPage>, AppError> {
+	let filter = TransactionsFilter::from_uri(req.uri())?;
+	let deleted = DeletedVisibility::from_uri(req.uri())?;
+	let expand = ExpandParams::from_uri(req.uri())?;
+	let page = Page::from_request(req, &()).await?;
+
+	let mut list = repo.get_transactions_list(filter, deleted, page).await?;
+	repo.expand_transactions(&mut list.items, &expand).await?;
+
 	return Ok(Success(StatusCode::OK, list));
 }
 
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/export.csv",
+	params(TransactionsFilter, DeletedVisibility, CsvExportParams),
+	responses(
+		(status = 200, description = "Streams every matching transaction as RFC 4180 CSV in the order of the list", body = String, content_type = "text/csv"),
+		(status = 400, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn export_transactions_csv(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Response, AppError> {
+	let filter = TransactionsFilter::from_uri(req.uri())?;
+	let deleted = DeletedVisibility::from_uri(req.uri())?;
+	let format = CsvExportParams::from_uri(req.uri())?;
+
+	let batches = repo.export_transactions(filter, deleted).await?;
+
+	let encoder = CsvEncoder::new(format);
+	let header = encoder.header()?;
+	let rows = ReceiverStream::new(batches).map(move |batch| encoder.rows(&batch?));
+
+	return Ok(attachment(
+		CSV_CONTENT_TYPE,
+		"transactions.csv",
+		Body::from_stream(tokio_stream::once(Ok(header)).chain(rows)),
+	));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/export.xlsx",
+	params(TransactionsFilter, DeletedVisibility, XlsxExportParams),
+	responses(
+		(status = 200, description = "Returns every matching transaction as an Excel register with a totals row", body = Vec<u8>, content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+		(status = 400, response = Problem),
+		(status = 422, description = "The transactions don't fit into an Excel sheet", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn export_transactions_xlsx(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Response, AppError> {
+	let filter = TransactionsFilter::from_uri(req.uri())?;
+	let deleted = DeletedVisibility::from_uri(req.uri())?;
+	let lang = XlsxExportParams::from_uri(req.uri())?;
+
+	let mut batches = repo.export_transactions(filter, deleted).await?;
+
+	let file = build_xlsx(move || {
+		let mut encoder = XlsxEncoder::new(lang);
+		let mut sheet = encoder.streaming_sheet("transactions", &TRANSACTION_COLUMNS)?;
+		while let Some(batch) = batches.blocking_recv() {
+			for tx in batch? {
+				sheet.row(&transaction_cells(&tx))?;
+			}
+		}
+		sheet.finish()?;
+
+		return encoder.finish();
+	})
+	.await?;
+	return Ok(attachment(
+		XLSX_CONTENT_TYPE,
+		"transactions.xlsx",
+		Body::from(file),
+	));
+}
+
 #[utoipa::path(
 	get,
 	path = "/api/v1/transactions/{tx_id}",
 	params(
-		("tx_id" = Uuid, Path, description = "transaction id")
+		("tx_id" = Uuid, Path, description = "transaction id"),
+		("If-None-Match" = Option<String>, Header, description = "ETag of the cached representation"),
+		DeletedVisibility,
+		ExpandParams,
 	),
 	responses(
-		(status = 200, description = "Returns a transaction by id", body = Transaction),
-		(status = 404),
-		(status = 500)
+		(status = 200, description = "Returns a transaction by id", body = Transaction,
+			headers(("ETag" = String, description = "Current version of the transaction, weak when `expand` embeds directory entries"))),
+		(status = 304, description = "The cached representation is still current, never answered with `expand`"),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
 	),
 )]
 pub async fn get_transaction(
 	State(repo): State<Arc<Repository>>,
-	tx_id: TxId,
-) -> Result<Success<Transaction>, AppError> {
-	let tx = repo.get_transaction(tx_id).await?;
-	return Ok(Success(StatusCode::OK, tx));
+	req: Request,
+) -> Result<Response, AppError> {
+	let tx_id = TxId::from_uri(req.uri())?;
+	let if_none_match = ETagCondition::if_none_match(req.headers())?;
+	let expand = ExpandParams::from_uri(req.uri())?;
+	let deleted = DeletedMode::from_request(req, &()).await?;
+
+	let tx = repo.get_transaction(tx_id, deleted).await?;
+
+	// embedded entries change without the row version, so an expanded body is never cached
+	if !expand.is_empty() {
+		let mut txs = [tx];
+		repo.expand_transactions(&mut txs, &expand).await?;
+		let [tx] = txs;
+
+		return Ok((WeakETag(tx.version), Success(StatusCode::OK, tx)).into_response());
+	}
+
+	if if_none_match.is_some_and(|condition| condition.matches(tx.version)) {
+		return Ok((StatusCode::NOT_MODIFIED, ETag(tx.version), ()).into_response());
+	}
+
+	return Ok((ETag(tx.version), Success(StatusCode::OK, tx)).into_response());
 }
 
 #[utoipa::path(
@@ -48,23 +187,127 @@ pub async fn get_transaction(
 	path = "/api/v1/transactions",
 	params(
 		("X-USER-ID" = Uuid, Header, description = "Current user id"),
+		("Idempotency-Key" = Option<String>, Header, description = "Unique key of the request, a retry with the same key replays the original response"),
 	),
 	request_body(content = ApiTransaction, content_type = "application/json"),
 	responses(
-		(status = 201, description = "Create a new transaction", body = Transaction),
-		(status = 400),
-		(status = 500)
+		(status = 201, description = "Create a new transaction", body = Transaction,
+			headers(("ETag" = String, description = "Current version of the transaction"))),
+		(status = 400, response = Problem),
+		(status = 409, description = "The transaction falls into a closed period, belongs to a posted implementation or refers to a missing record", body = Problem, content_type = "application/problem+json"),
+		(status = 422, description = "The transaction breaks business rules or the idempotency key has been used with a different request body", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
 	)
 )]
 pub async fn create_transaction(
 	State(repo): State<Arc<Repository>>,
 	req: Request,
-) -> Result<Success<Transaction>, AppError> {
+) -> Result<(ETag, Success<Transaction>), AppError> {
 	let user_id = UserId::from_headers(req.headers())?;
+	let idempotency_key = IdempotencyKey::from_headers(req.headers())?;
 	let new_tx = ApiTransaction::from_request(req, &()).await?;
 
-	let inserted_tx = repo.create_transaction(user_id, new_tx).await?;
-	return Ok(Success(StatusCode::CREATED, inserted_tx));
+	let inserted_tx = repo
+		.create_transaction(user_id, new_tx, idempotency_key)
+		.await?;
+	return Ok((
+		ETag(inserted_tx.version),
+		Success(StatusCode::CREATED, inserted_tx),
+	));
+}
+
+#[utoipa::path(
+	post,
+	path = "/api/v1/transactions/batch",
+	params(
+		("X-USER-ID" = Uuid, Header, description = "Current user id"),
+		BatchParams,
+	),
+	request_body(content = [ApiTransaction], content_type = "application/json"),
+	responses(
+		(status = 201, description = "Every transaction of the batch has been created", body = BatchResult),
+		(status = 207, description = "Some transactions have been created, the rest are reported by index", body = BatchResult),
+		(status = 400, response = Problem),
+		(status = 413, description = "The body is over the 64 MB limit", body = Problem, content_type = "application/problem+json"),
+		(status = 422, description = "Nothing has been created because of the reported items", body = BatchResult),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn create_transactions_batch(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<BatchResult>, AppError> {
+	let user_id = UserId::from_headers(req.headers())?;
+	let mode = BatchParams::from_uri(req.uri())?;
+	let ApiTransactionBatch { items, mut errors } =
+		ApiTransactionBatch::from_request(req, &()).await?;
+
+	if mode == BatchMode::Atomic && !errors.is_empty() {
+		let result = BatchResult {
+			created: Vec::new(),
+			errors,
+		};
+		return Ok(Success(StatusCode::UNPROCESSABLE_ENTITY, result));
+	}
+
+	let mut result = repo.create_transactions_batch(user_id, items, mode).await?;
+	result.errors.append(&mut errors);
+	result.errors.sort_by_key(|err| err.index);
+
+	// an atomic batch may still be rejected by the checks against stored transactions
+	let status = match (result.errors.is_empty(), mode) {
+		(true, _) => StatusCode::CREATED,
+		(false, BatchMode::Atomic) => StatusCode::UNPROCESSABLE_ENTITY,
+		(false, BatchMode::BestEffort) => StatusCode::MULTI_STATUS,
+	};
+
+	return Ok(Success(status, result));
+}
+
+#[utoipa::path(
+	post,
+	path = "/api/v1/transactions/import",
+	params(
+		("X-USER-ID" = Uuid, Header, description = "Current user id"),
+		ImportParams,
+		ImportColumns,
+	),
+	request_body(content = String, content_type = "text/csv", description = "CSV file with a header line"),
+	responses(
+		(status = 200, description = "Every line is valid, nothing has been written on a dry run", body = ImportReport),
+		(status = 201, description = "A transaction has been created out of every line", body = ImportReport),
+		(status = 400, response = Problem),
+		(status = 413, description = "The file is over the 64 MB limit", body = Problem, content_type = "application/problem+json"),
+		(status = 422, description = "Nothing has been created because of the reported lines", body = ImportReport),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn import_transactions(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<ImportReport>, AppError> {
+	let user_id = UserId::from_headers(req.headers())?;
+	let options = ImportParams::from_uri(req.uri())?;
+	let columns = ImportColumns::from_uri(req.uri())?;
+	let data = Bytes::from_request(req, &())
+		.await
+		.map_err(bytes_rejection_to_error)?;
+
+	let report = import::import_csv(&repo, user_id, &data, options, &columns).await?;
+
+	let status = match (report.errors.is_empty(), report.dry_run) {
+		(true, false) => StatusCode::CREATED,
+		(true, true) => StatusCode::OK,
+		(false, _) => StatusCode::UNPROCESSABLE_ENTITY,
+	};
+
+	return Ok(Success(status, report));
 }
 
 #[utoipa::path(
@@ -73,25 +316,78 @@ pub async fn create_transaction(
 	params(
 		("tx_id" = Uuid, Path, description = "transaction id"),
 		("X-USER-ID" = Uuid, Header, description = "Current user id"),
+		("If-Match" = Option<String>, Header, description = "Expected ETag of the transaction"),
 	),
 	request_body(content = ApiTransaction, content_type = "application/json"),
 	responses(
-		(status = 202, description = "Update a transaction by id", body = Transaction),
-		(status = 400),
-		(status = 404),
-		(status = 500)
+		(status = 202, description = "Update a transaction by id", body = Transaction,
+			headers(("ETag" = String, description = "Current version of the transaction"))),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 409, description = "The transaction falls into a closed period, belongs to a posted implementation or refers to a missing record", body = Problem, content_type = "application/problem+json"),
+		(status = 412, description = "The transaction has been modified since the given ETag", body = Problem, content_type = "application/problem+json"),
+		(status = 422, description = "The transaction breaks business rules", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
 	),
 )]
 pub async fn update_transaction(
 	State(repo): State<Arc<Repository>>,
 	req: Request,
-) -> Result<Success<Transaction>, AppError> {
+) -> Result<(ETag, Success<Transaction>), AppError> {
 	let tx_id = TxId::from_uri(req.uri())?;
 	let user_id = UserId::from_headers(req.headers())?;
+	let if_match = ETagCondition::if_match(req.headers())?;
 	let tx = ApiTransaction::from_request(req, &()).await?;
 
-	let updated_tx = repo.update_transaction(tx_id, user_id, tx).await?;
-	return Ok(Success(StatusCode::ACCEPTED, updated_tx));
+	let updated_tx = repo
+		.update_transaction(tx_id, user_id, tx, if_match)
+		.await?;
+	return Ok((
+		ETag(updated_tx.version),
+		Success(StatusCode::ACCEPTED, updated_tx),
+	));
+}
+
+#[utoipa::path(
+	patch,
+	path = "/api/v1/transactions/{tx_id}",
+	params(
+		("tx_id" = Uuid, Path, description = "transaction id"),
+		("X-USER-ID" = Uuid, Header, description = "Current user id"),
+		("If-Match" = Option<String>, Header, description = "Expected ETag of the transaction"),
+	),
+	request_body(content = ApiTransactionPatch, content_type = "application/merge-patch+json"),
+	responses(
+		(status = 202, description = "Partially update a transaction by id", body = Transaction,
+			headers(("ETag" = String, description = "Current version of the transaction"))),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 409, description = "The transaction falls into a closed period, belongs to a posted implementation or refers to a missing record", body = Problem, content_type = "application/problem+json"),
+		(status = 412, description = "The transaction has been modified since the given ETag", body = Problem, content_type = "application/problem+json"),
+		(status = 422, description = "The patched transaction breaks business rules", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	),
+)]
+pub async fn patch_transaction(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<(ETag, Success<Transaction>), AppError> {
+	let tx_id = TxId::from_uri(req.uri())?;
+	let user_id = UserId::from_headers(req.headers())?;
+	let if_match = ETagCondition::if_match(req.headers())?;
+	let patch = ApiTransactionPatch::from_request(req, &()).await?;
+
+	let patched_tx = repo
+		.patch_transaction(tx_id, user_id, patch, if_match)
+		.await?;
+	return Ok((
+		ETag(patched_tx.version),
+		Success(StatusCode::ACCEPTED, patched_tx),
+	));
 }
 
 #[utoipa::path(
@@ -99,12 +395,17 @@ pub async fn update_transaction(
 	path = "/api/v1/transactions/{tx_id}",
 	responses(
 		(status = 204, description = "Delete a transaction by id", body = ()),
-		(status = 404),
-		(status = 500)
+		(status = 404, response = Problem),
+		(status = 409, description = "The transaction has active refunds, falls into a closed period or belongs to a posted implementation", body = Problem, content_type = "application/problem+json"),
+		(status = 412, description = "The transaction has been modified since the given ETag", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
 	),
 	params(
 		("tx_id" = Uuid, Path, description = "transaction id"),
 		("X-USER-ID" = Uuid, Header, description = "Current user id"),
+		("If-Match" = Option<String>, Header, description = "Expected ETag of the transaction"),
 	),
 )]
 pub async fn delete_transaction(
@@ -113,7 +414,700 @@ pub async fn delete_transaction(
 ) -> Result<StatusCode, AppError> {
 	let tx_id = TxId::from_uri(req.uri())?;
 	let user_id = UserId::from_headers(req.headers())?;
+	let if_match = ETagCondition::if_match(req.headers())?;
 
-	repo.delete_transaction(tx_id, user_id).await?;
+	repo.delete_transaction(tx_id, user_id, if_match).await?;
 	return Ok(StatusCode::NO_CONTENT);
 }
+
+#[utoipa::path(
+	post,
+	path = "/api/v1/transactions/{tx_id}/restore",
+	params(
+		("tx_id" = Uuid, Path, description = "transaction id"),
+		("X-USER-ID" = Uuid, Header, description = "Current user id"),
+	),
+	responses(
+		(status = 200, description = "Restore a soft-deleted transaction by id", body = Transaction,
+			headers(("ETag" = String, description = "Current version of the transaction"))),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 409, description = "The transaction is not deleted, falls into a closed period or belongs to a posted implementation", body = Problem, content_type = "application/problem+json"),
+		(status = 422, description = "The refund no longer fits into its original transaction", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	),
+)]
+pub async fn restore_transaction(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<(ETag, Success<Transaction>), AppError> {
+	let tx_id = TxId::from_uri(req.uri())?;
+	let user_id = UserId::from_headers(req.headers())?;
+
+	let restored_tx = repo.restore_transaction(tx_id, user_id).await?;
+	return Ok((
+		ETag(restored_tx.version),
+		Success(StatusCode::OK, restored_tx),
+	));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/{tx_id}/history",
+	params(
+		("tx_id" = Uuid, Path, description = "transaction id"),
+	),
+	responses(
+		(status = 200, description = "Returns the change history of a transaction, oldest first", body = Vec<TransactionHistoryEntry>),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	),
+)]
+pub async fn get_transaction_history(
+	State(repo): State<Arc<Repository>>,
+	tx_id: TxId,
+) -> Result<Success<Vec<TransactionHistoryEntry>>, AppError> {
+	let history = repo.get_transaction_history(tx_id).await?;
+	return Ok(Success(StatusCode::OK, history));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/stats",
+	params(StatsParams, TransactionsFilter),
+	responses(
+		(status = 200, description = "Returns transaction counts and sums per group, refunds are subtracted", body = TransactionsStats),
+		(status = 400, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_transactions_stats(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<TransactionsStats>, AppError> {
+	let group_by = StatsParams::from_uri(req.uri())?;
+	let filter = TransactionsFilter::from_request(req, &()).await?;
+
+	let stats = repo.get_transactions_stats(filter, group_by).await?;
+	return Ok(Success(StatusCode::OK, stats));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/stats/export.xlsx",
+	params(StatsParams, TransactionsFilter, XlsxExportParams),
+	responses(
+		(status = 200, description = "Returns the transaction statistics as an Excel sheet with a totals row", body = Vec<u8>, content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+		(status = 400, response = Problem),
+		(status = 422, description = "The report doesn't fit into an Excel sheet", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn export_transactions_stats_xlsx(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Response, AppError> {
+	let group_by = StatsParams::from_uri(req.uri())?;
+	let lang = XlsxExportParams::from_uri(req.uri())?;
+	let filter = TransactionsFilter::from_request(req, &()).await?;
+
+	let stats = repo.get_transactions_stats(filter, group_by).await?;
+
+	let file = tables_to_xlsx(lang, stats_tables(&stats)).await?;
+	return Ok(attachment(
+		XLSX_CONTENT_TYPE,
+		"stats.xlsx",
+		Body::from(file),
+	));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/variance",
+	params(VarianceParams, TransactionsFilter),
+	responses(
+		(status = 200, description = "Returns transactions whose fact deviates from plan with totals per contract and station", body = VarianceReport),
+		(status = 400, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_variance_report(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<VarianceReport>, AppError> {
+	let thresholds = VarianceParams::from_uri(req.uri())?;
+	let filter = TransactionsFilter::from_request(req, &()).await?;
+
+	let report = repo.get_variance_report(filter, thresholds).await?;
+	return Ok(Success(StatusCode::OK, report));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/variance/export.xlsx",
+	params(VarianceParams, TransactionsFilter, XlsxExportParams),
+	responses(
+		(status = 200, description = "Returns the variance report as an Excel workbook with the deviating transactions and the totals per contract and station", body = Vec<u8>, content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+		(status = 400, response = Problem),
+		(status = 422, description = "The report doesn't fit into an Excel sheet", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn export_variance_report_xlsx(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Response, AppError> {
+	let thresholds = VarianceParams::from_uri(req.uri())?;
+	let lang = XlsxExportParams::from_uri(req.uri())?;
+	let filter = TransactionsFilter::from_request(req, &()).await?;
+
+	let report = repo.get_variance_report(filter, thresholds).await?;
+
+	let file = tables_to_xlsx(lang, variance_tables(&report)).await?;
+	return Ok(attachment(
+		XLSX_CONTENT_TYPE,
+		"variance.xlsx",
+		Body::from(file),
+	));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/margin",
+	params(TransactionsFilter),
+	responses(
+		(status = 200, description = "Returns margins overall and per contract, station and nomenclature, use op_date_from and op_date_to for the date range", body = MarginReport),
+		(status = 400, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_margin_report(
+	State(repo): State<Arc<Repository>>,
+	filter: TransactionsFilter,
+) -> Result<Success<MarginReport>, AppError> {
+	let report = repo.get_margin_report(filter).await?;
+	return Ok(Success(StatusCode::OK, report));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/margin/export.xlsx",
+	params(TransactionsFilter, XlsxExportParams),
+	responses(
+		(status = 200, description = "Returns the margin report as an Excel workbook with a sheet per contract, station and nomenclature", body = Vec<u8>, content_type = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+		(status = 400, response = Problem),
+		(status = 422, description = "The report doesn't fit into an Excel sheet", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn export_margin_report_xlsx(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Response, AppError> {
+	let lang = XlsxExportParams::from_uri(req.uri())?;
+	let filter = TransactionsFilter::from_request(req, &()).await?;
+
+	let report = repo.get_margin_report(filter).await?;
+
+	let file = tables_to_xlsx(lang, margin_tables(&report)).await?;
+	return Ok(attachment(
+		XLSX_CONTENT_TYPE,
+		"margin.xlsx",
+		Body::from(file),
+	));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/transactions/{tx_id}/refunds",
+	params(
+		("tx_id" = Uuid, Path, description = "transaction id"),
+	),
+	responses(
+		(status = 200, description = "Returns the active refunds of a transaction with what is left to refund", body = TransactionRefunds),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 409, description = "The transaction is a refund itself", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	),
+)]
+pub async fn get_transaction_refunds(
+	State(repo): State<Arc<Repository>>,
+	tx_id: TxId,
+) -> Result<Success<TransactionRefunds>, AppError> {
+	let refunds = repo.get_transaction_refunds(tx_id).await?;
+	return Ok(Success(StatusCode::OK, refunds));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/directories/{kind}",
+	params(
+		("kind" = DirectoryKind, Path, description = "directory"),
+		DirectoryFilter,
+	),
+	responses(
+		(status = 200, description = "Returns the entries of a directory ordered by code", body = [DirectoryEntry]),
+		(status = 400, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_directory_entries(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<Vec<DirectoryEntry>>, AppError> {
+	let kind = DirectoryKind::from_uri(req.uri())?;
+	let filter = DirectoryFilter::from_uri(req.uri())?;
+
+	let entries = repo.get_directory_entries(kind, filter).await?;
+	return Ok(Success(StatusCode::OK, entries));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/directories/{kind}/{entry_id}",
+	params(
+		("kind" = DirectoryKind, Path, description = "directory"),
+		("entry_id" = Uuid, Path, description = "directory entry id"),
+	),
+	responses(
+		(status = 200, description = "Returns a directory entry by id", body = DirectoryEntry),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_directory_entry(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<DirectoryEntry>, AppError> {
+	let kind = DirectoryKind::from_uri(req.uri())?;
+	let entry_id = DirectoryEntryId::from_uri(req.uri())?;
+
+	let entry = repo.get_directory_entry(kind, entry_id).await?;
+	return Ok(Success(StatusCode::OK, entry));
+}
+
+#[utoipa::path(
+	post,
+	path = "/api/v1/directories/{kind}",
+	params(
+		("kind" = DirectoryKind, Path, description = "directory"),
+	),
+	request_body(content = ApiDirectoryEntry, content_type = "application/json"),
+	responses(
+		(status = 201, description = "Create a new directory entry", body = DirectoryEntry),
+		(status = 400, response = Problem),
+		(status = 409, description = "The code is already taken", body = Problem, content_type = "application/problem+json"),
+		(status = 422, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn create_directory_entry(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<DirectoryEntry>, AppError> {
+	let kind = DirectoryKind::from_uri(req.uri())?;
+	let entry = ApiDirectoryEntry::from_request(req, &()).await?;
+
+	let entry = repo.create_directory_entry(kind, entry).await?;
+	return Ok(Success(StatusCode::CREATED, entry));
+}
+
+#[utoipa::path(
+	put,
+	path = "/api/v1/directories/{kind}/{entry_id}",
+	params(
+		("kind" = DirectoryKind, Path, description = "directory"),
+		("entry_id" = Uuid, Path, description = "directory entry id"),
+	),
+	request_body(content = ApiDirectoryEntry, content_type = "application/json"),
+	responses(
+		(status = 200, description = "Update a directory entry, deactivate it instead of deleting once it is referenced", body = DirectoryEntry),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 409, description = "The code is already taken", body = Problem, content_type = "application/problem+json"),
+		(status = 422, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn update_directory_entry(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<DirectoryEntry>, AppError> {
+	let kind = DirectoryKind::from_uri(req.uri())?;
+	let entry_id = DirectoryEntryId::from_uri(req.uri())?;
+	let entry = ApiDirectoryEntry::from_request(req, &()).await?;
+
+	let entry = repo.update_directory_entry(kind, entry_id, entry).await?;
+	return Ok(Success(StatusCode::OK, entry));
+}
+
+#[utoipa::path(
+	delete,
+	path = "/api/v1/directories/{kind}/{entry_id}",
+	params(
+		("kind" = DirectoryKind, Path, description = "directory"),
+		("entry_id" = Uuid, Path, description = "directory entry id"),
+	),
+	responses(
+		(status = 204, description = "Delete a directory entry"),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 409, description = "The entry is referenced by transactions or implementations", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn delete_directory_entry(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<StatusCode, AppError> {
+	let kind = DirectoryKind::from_uri(req.uri())?;
+	let entry_id = DirectoryEntryId::from_uri(req.uri())?;
+
+	repo.delete_directory_entry(kind, entry_id).await?;
+	return Ok(StatusCode::NO_CONTENT);
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/implementations",
+	params(ImplementationsFilter),
+	responses(
+		(status = 200, description = "Returns implementations ordered by date and number", body = [Implementation]),
+		(status = 400, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_implementations(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<Vec<Implementation>>, AppError> {
+	let filter = ImplementationsFilter::from_uri(req.uri())?;
+
+	let implementations = repo.get_implementations(filter).await?;
+	return Ok(Success(StatusCode::OK, implementations));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/implementations/{implementation_id}",
+	params(
+		("implementation_id" = Uuid, Path, description = "implementation id"),
+	),
+	responses(
+		(status = 200, description = "Returns an implementation by id with the totals of its transactions", body = Implementation),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_implementation(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<Implementation>, AppError> {
+	let implementation_id = ImplementationId::from_uri(req.uri())?;
+
+	let implementation = repo.get_implementation(implementation_id).await?;
+	return Ok(Success(StatusCode::OK, implementation));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/implementations/{implementation_id}/transactions",
+	params(
+		("implementation_id" = Uuid, Path, description = "implementation id"),
+		ExpandParams,
+	),
+	responses(
+		(status = 200, description = "Returns the active transactions of an implementation ordered by op_date", body = [Transaction]),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_implementation_transactions(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<Vec<Transaction>>, AppError> {
+	let implementation_id = ImplementationId::from_uri(req.uri())?;
+	let expand = ExpandParams::from_uri(req.uri())?;
+
+	let mut transactions = repo
+		.get_implementation_transactions(implementation_id)
+		.await?;
+	repo.expand_transactions(&mut transactions, &expand).await?;
+
+	return Ok(Success(StatusCode::OK, transactions));
+}
+
+#[utoipa::path(
+	post,
+	path = "/api/v1/implementations",
+	request_body(content = ApiImplementation, content_type = "application/json"),
+	responses(
+		(status = 201, description = "Create a new draft implementation", body = Implementation),
+		(status = 400, response = Problem),
+		(status = 409, description = "The number is already taken or the contract does not exist", body = Problem, content_type = "application/problem+json"),
+		(status = 422, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn create_implementation(
+	State(repo): State<Arc<Repository>>,
+	implementation: ApiImplementation,
+) -> Result<Success<Implementation>, AppError> {
+	let implementation = repo.create_implementation(implementation).await?;
+	return Ok(Success(StatusCode::CREATED, implementation));
+}
+
+#[utoipa::path(
+	put,
+	path = "/api/v1/implementations/{implementation_id}",
+	params(
+		("implementation_id" = Uuid, Path, description = "implementation id"),
+	),
+	request_body(content = ApiImplementation, content_type = "application/json"),
+	responses(
+		(status = 200, description = "Update a draft implementation", body = Implementation),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 409, description = "The implementation is posted, the number is already taken or the contract does not exist", body = Problem, content_type = "application/problem+json"),
+		(status = 422, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn update_implementation(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<Implementation>, AppError> {
+	let implementation_id = ImplementationId::from_uri(req.uri())?;
+	let implementation = ApiImplementation::from_request(req, &()).await?;
+
+	let implementation = repo
+		.update_implementation(implementation_id, implementation)
+		.await?;
+	return Ok(Success(StatusCode::OK, implementation));
+}
+
+#[utoipa::path(
+	delete,
+	path = "/api/v1/implementations/{implementation_id}",
+	params(
+		("implementation_id" = Uuid, Path, description = "implementation id"),
+	),
+	responses(
+		(status = 204, description = "Delete a draft implementation"),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 409, description = "The implementation is posted or referenced by transactions", body = Problem, content_type = "application/problem+json"),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn delete_implementation(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<StatusCode, AppError> {
+	let implementation_id = ImplementationId::from_uri(req.uri())?;
+
+	repo.delete_implementation(implementation_id).await?;
+	return Ok(StatusCode::NO_CONTENT);
+}
+
+#[utoipa::path(
+	post,
+	path = "/api/v1/implementations/{implementation_id}/post",
+	params(
+		("implementation_id" = Uuid, Path, description = "implementation id"),
+	),
+	responses(
+		(status = 200, description = "Post an implementation, its transactions become read-only", body = Implementation),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn post_implementation(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<Implementation>, AppError> {
+	let implementation_id = ImplementationId::from_uri(req.uri())?;
+
+	let implementation = repo
+		.set_implementation_status(implementation_id, ImplementationStatus::Posted)
+		.await?;
+	return Ok(Success(StatusCode::OK, implementation));
+}
+
+#[utoipa::path(
+	post,
+	path = "/api/v1/implementations/{implementation_id}/unpost",
+	params(
+		("implementation_id" = Uuid, Path, description = "implementation id"),
+	),
+	responses(
+		(status = 200, description = "Return an implementation to draft", body = Implementation),
+		(status = 400, response = Problem),
+		(status = 404, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn unpost_implementation(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<Implementation>, AppError> {
+	let implementation_id = ImplementationId::from_uri(req.uri())?;
+
+	let implementation = repo
+		.set_implementation_status(implementation_id, ImplementationStatus::Draft)
+		.await?;
+	return Ok(Success(StatusCode::OK, implementation));
+}
+
+#[utoipa::path(
+	get,
+	path = "/api/v1/closed-periods",
+	responses(
+		(status = 200, description = "Returns the closed months, their transactions are read-only", body = [ClosedPeriod]),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn get_closed_periods(
+	State(repo): State<Arc<Repository>>,
+) -> Result<Success<Vec<ClosedPeriod>>, AppError> {
+	let periods = repo.get_closed_periods().await?;
+	return Ok(Success(StatusCode::OK, periods));
+}
+
+#[utoipa::path(
+	put,
+	path = "/api/v1/admin/closed-periods/{month}",
+	params(
+		("month" = String, Path, description = "month formatted as YYYY-MM"),
+		("X-USER-ID" = Uuid, Header, description = "Id of a user listed in ADMIN_USER_IDS"),
+	),
+	responses(
+		(status = 200, description = "Close a month, transactions dated within it can no longer be created, changed or deleted", body = ClosedPeriod),
+		(status = 400, response = Problem),
+		(status = 403, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn close_period(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<Success<ClosedPeriod>, AppError> {
+	let admin_id = AdminId::from_headers(req.headers())?;
+	let month = PeriodMonth::from_uri(req.uri())?;
+
+	let period = repo.close_period(month, admin_id).await?;
+	return Ok(Success(StatusCode::OK, period));
+}
+
+#[utoipa::path(
+	delete,
+	path = "/api/v1/admin/closed-periods/{month}",
+	params(
+		("month" = String, Path, description = "month formatted as YYYY-MM"),
+		("X-USER-ID" = Uuid, Header, description = "Id of a user listed in ADMIN_USER_IDS"),
+	),
+	responses(
+		(status = 204, description = "Reopen a closed month"),
+		(status = 400, response = Problem),
+		(status = 403, response = Problem),
+		(status = 404, response = Problem),
+		(status = 500, response = Problem),
+		(status = 503, response = Problem),
+		(status = 504, response = Problem)
+	)
+)]
+pub async fn reopen_period(
+	State(repo): State<Arc<Repository>>,
+	req: Request,
+) -> Result<StatusCode, AppError> {
+	AdminId::from_headers(req.headers())?;
+	let month = PeriodMonth::from_uri(req.uri())?;
+
+	repo.reopen_period(month).await?;
+	return Ok(StatusCode::NO_CONTENT);
+}
+
+const CSV_CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+const XLSX_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+/// A file the browser offers to save instead of showing it
+fn attachment(content_type: &'static str, filename: &str, body: Body) -> Response {
+	return (
+		[
+			(header::CONTENT_TYPE, content_type.to_owned()),
+			(
+				header::CONTENT_DISPOSITION,
+				format!("attachment; filename=\"{filename}\""),
+			),
+		],
+		body,
+	)
+		.into_response();
+}
+
+async fn tables_to_xlsx(lang: Lang, tables: Vec<Table>) -> Result<Bytes, AppError> {
+	return build_xlsx(move || {
+		let mut encoder = XlsxEncoder::new(lang);
+
+		for table in &tables {
+			encoder.table(table)?;
+		}
+
+		return encoder.finish();
+	})
+	.await;
+}
@@ -0,0 +1,651 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use super::Lang;
+use crate::system_models::ErrorCode;
+
+/// Every user-facing text of the service
+#[derive(Debug, Clone, Copy)]
+pub enum Message<'a> {
+	ErrorTitle(ErrorCode),
+
+	InvalidBody(Option<&'a str>),
+	MalformedJson,
+	MissingJsonContentType,
+	UnreadableBody,
+	PayloadTooLarge,
+	MissingHeader(&'a str),
+	InvalidHeader(&'a str),
+	InvalidPath,
+	InvalidTransactionId,
+
+	InvalidMoney(&'a str),
+	MoneyFractionDigits(&'a str, u32),
+	MoneyIntegerDigits(&'a str, u32),
+	NullNotAllowed,
+
+	InvalidBatchParams(&'a str),
+	BatchSize(usize),
+	InvalidBatchItem(&'a str),
+
+	InvalidFilterParams(&'a str),
+	InvalidDeletedParams(&'a str),
+	DeletedModesConflict,
+	InvalidPaginationParams(&'a str),
+	PageLimit(u32),
+	CursorWithOffset,
+	InvalidCursor,
+
+	InvalidStatsParams(&'a str),
+	UnknownStatsDimension(&'a str),
+	SeveralStatsPeriods,
+
+	InvalidVarianceParams(&'a str),
+	NegativeVarianceThreshold,
+
+	IdempotencyKeyLength(usize),
+	IdempotencyKeyReused(&'a str),
+	IdempotencyKeyInProgress(&'a str),
+
+	ValidationFailed,
+	OpDateInFuture,
+	NegativeAmount,
+	NegativeSum,
+	PositiveRefundSum,
+	NdsExceedsSum(&'a str),
+	OriginalOfNonRefund,
+	OriginalNotFound(Uuid),
+	OriginalIsRefund(Uuid),
+	RefundOfItself,
+	RefundExceedsOriginal(Decimal),
+	RefundedTransactionIsRefund,
+	BelowRefunded(Decimal),
+
+	DuplicateRecord,
+	MissingReference,
+	ConstraintViolated,
+	RequiredValueMissing,
+	DatabaseUnavailable,
+	DatabaseTimeout,
+	InternalError,
+
+	TransactionNotFound(Uuid),
+	TransactionNotDeleted(Uuid),
+	TransactionModified(Uuid, i64),
+	TransactionIsRefund(Uuid),
+	TransactionHasRefunds(Uuid),
+
+	UnknownDirectory(&'a str),
+	InvalidDirectoryEntryId,
+	InvalidExpandParams(&'a str),
+	UnknownExpand(&'a str),
+	EmptyValue,
+	ValueTooLong(usize),
+	UnitRequired,
+	UnitNotAllowed,
+	DirectoryEntryNotFound(Uuid),
+	DirectoryEntryInUse(Uuid),
+
+	InvalidImplementationId,
+	ImplementationNotFound(Uuid),
+	ImplementationPosted(Uuid),
+	ImplementationHasTransactions(Uuid),
+
+	InvalidPeriod,
+	PeriodClosed(NaiveDate),
+	PeriodNotClosed(NaiveDate),
+	AdminOnly,
+
+	InvalidExportParams(&'a str),
+	InvalidCsvDelimiter,
+	InvalidDecimalSeparator,
+	UnknownLanguage(&'a str),
+	ExportColumn(&'a str),
+	ExportSheet(&'a str),
+	ExportTotal,
+	XlsxRowLimit(u32),
+
+	InvalidImportParams(&'a str),
+	UnknownImportField(&'a str),
+	InvalidImportHeader(&'a str),
+	ImportColumnNotFound(&'a str),
+	ImportColumnMissing(&'a str),
+	ImportEmpty,
+	ImportSize(usize),
+}
+
+impl Message<'_> {
+	pub fn render(&self, lang: Lang) -> String {
+		return match lang {
+			Lang::Ru => self.render_ru(),
+			Lang::En => self.render_en(),
+		};
+	}
+
+	fn render_ru(&self) -> String {
+		return match *self {
+			Message::ErrorTitle(code) => String::from(match code {
+				ErrorCode::BadRequest => "Некорректный запрос",
+				ErrorCode::InvalidBody => "Некорректное тело запроса",
+				ErrorCode::MalformedJson => "Некорректный JSON",
+				ErrorCode::MissingContentType => "Не указан тип содержимого JSON",
+				ErrorCode::UnreadableBody => "Не удалось прочитать тело запроса",
+				ErrorCode::PayloadTooLarge => "Слишком большое тело запроса",
+				ErrorCode::Forbidden => "Доступ запрещён",
+				ErrorCode::NotFound => "Ресурс не найден",
+				ErrorCode::Conflict => "Конфликт с текущим состоянием",
+				ErrorCode::PeriodClosed => "Период закрыт",
+				ErrorCode::PreconditionFailed => "Условие запроса не выполнено",
+				ErrorCode::UnprocessableEntity => "Запрос не может быть обработан",
+				ErrorCode::ValidationFailed => "Ошибка проверки данных",
+				ErrorCode::ServiceUnavailable => "Сервис временно недоступен",
+				ErrorCode::Timeout => "Превышено время ожидания",
+				ErrorCode::InternalError => "Внутренняя ошибка сервера",
+			}),
+
+			Message::InvalidBody(None) => String::from("Передано некорректное тело запроса"),
+			Message::InvalidBody(Some(reason)) => {
+				format!("Передано некорректное тело запроса: {reason}")
+			}
+			Message::MalformedJson => String::from("Передано некорректное тело запроса"),
+			Message::MissingJsonContentType => {
+				String::from("Пожалуйста, укажите заголовок `Content-Type: application/json`")
+			}
+			Message::UnreadableBody => String::from("Не удалось прочитать тело запроса"),
+			Message::PayloadTooLarge => String::from("Тело запроса больше допустимого размера"),
+			Message::MissingHeader(name) => format!("Не передан заголовок {name}"),
+			Message::InvalidHeader(name) => format!("Некорректное значение заголовка {name}"),
+			Message::InvalidPath => String::from("Некорректный путь запроса"),
+			Message::InvalidTransactionId => {
+				String::from("Некорректное значение идентификатора транзакции")
+			}
+
+			Message::InvalidMoney(raw) => format!("некорректное денежное значение `{raw}`"),
+			Message::MoneyFractionDigits(raw, max) => {
+				format!("денежное значение `{raw}` содержит больше {max} знаков после запятой")
+			}
+			Message::MoneyIntegerDigits(raw, max) => {
+				format!("денежное значение `{raw}` содержит больше {max} знаков до запятой")
+			}
+			Message::NullNotAllowed => String::from("поле не может быть равно null"),
+
+			Message::InvalidBatchParams(reason) => {
+				format!("Переданы некорректные параметры пакетной загрузки: {reason}")
+			}
+			Message::BatchSize(max) => format!("Пакет должен содержать от 1 до {max} транзакций"),
+			Message::InvalidBatchItem(reason) => {
+				format!("Передана некорректная транзакция: {reason}")
+			}
+
+			Message::InvalidFilterParams(reason) => {
+				format!("Переданы некорректные параметры фильтрации: {reason}")
+			}
+			Message::InvalidDeletedParams(reason) => {
+				format!("Переданы некорректные параметры отображения удалённых транзакций: {reason}")
+			}
+			Message::DeletedModesConflict => String::from(
+				"Параметры include_deleted и only_deleted не могут быть переданы одновременно",
+			),
+			Message::InvalidPaginationParams(reason) => {
+				format!("Переданы некорректные параметры пагинации: {reason}")
+			}
+			Message::PageLimit(max) => {
+				format!("Параметр limit должен быть в диапазоне от 1 до {max}")
+			}
+			Message::CursorWithOffset => {
+				String::from("Параметры cursor и offset не могут быть переданы одновременно")
+			}
+			Message::InvalidCursor => String::from("Некорректное значение курсора"),
+
+			Message::InvalidStatsParams(reason) => {
+				format!("Переданы некорректные параметры статистики: {reason}")
+			}
+			Message::UnknownStatsDimension(raw) => {
+				format!("Неизвестное измерение группировки `{raw}`")
+			}
+			Message::SeveralStatsPeriods => {
+				String::from("Допускается группировка только по одному из периодов day, week, month")
+			}
+
+			Message::InvalidVarianceParams(reason) => {
+				format!("Переданы некорректные пороги отклонений: {reason}")
+			}
+			Message::NegativeVarianceThreshold => {
+				String::from("Пороги отклонений не могут быть отрицательными")
+			}
+
+			Message::IdempotencyKeyLength(max) => {
+				format!("Длина заголовка Idempotency-Key должна быть от 1 до {max} символов")
+			}
+			Message::IdempotencyKeyReused(key) => {
+				format!("Ключ идемпотентности {key} уже использован с другим телом запроса")
+			}
+			Message::IdempotencyKeyInProgress(key) => {
+				format!("Запрос с ключом идемпотентности {key} ещё выполняется")
+			}
+
+			Message::ValidationFailed => String::from("Переданные данные не прошли проверку"),
+			Message::OpDateInFuture => String::from("Дата операции не может быть в будущем"),
+			Message::NegativeAmount => String::from("Количество не может быть отрицательным"),
+			Message::NegativeSum => String::from("Сумма не может быть отрицательной"),
+			Message::PositiveRefundSum => String::from("Сумма возврата не может быть положительной"),
+			Message::NdsExceedsSum(sum_field) => {
+				format!("НДС не может превышать сумму {sum_field}")
+			}
+			Message::OriginalOfNonRefund => {
+				String::from("Исходная транзакция указывается только для возврата")
+			}
+			Message::OriginalNotFound(tx_id) => {
+				format!("Исходная транзакция с id {tx_id} не найдена")
+			}
+			Message::OriginalIsRefund(tx_id) => {
+				format!("Исходная транзакция с id {tx_id} сама является возвратом")
+			}
+			Message::RefundOfItself => String::from("Транзакция не может быть возвратом самой себя"),
+			Message::RefundExceedsOriginal(left) => {
+				format!("Возвраты превышают исходную транзакцию, осталось вернуть {left}")
+			}
+			Message::RefundedTransactionIsRefund => {
+				String::from("Транзакция, по которой есть возвраты, не может быть возвратом")
+			}
+			Message::BelowRefunded(refunded) => {
+				format!("Значение не может быть меньше уже возвращённого {refunded}")
+			}
+
+			Message::DuplicateRecord => String::from("Такая запись уже существует"),
+			Message::MissingReference => String::from("Запись ссылается на несуществующий объект"),
+			Message::ConstraintViolated => String::from("Данные нарушают ограничения хранилища"),
+			Message::RequiredValueMissing => String::from("Не передано обязательное значение"),
+			Message::DatabaseUnavailable => {
+				String::from("База данных временно недоступна, повторите запрос позже")
+			}
+			Message::DatabaseTimeout => {
+				String::from("Превышено время выполнения запроса к базе данных")
+			}
+			Message::InternalError => String::from("Внутренняя ошибка сервера"),
+
+			Message::TransactionNotFound(tx_id) => format!("Транзакция с id {tx_id} не найдена"),
+			Message::TransactionNotDeleted(tx_id) => {
+				format!("Транзакция с id {tx_id} не удалена")
+			}
+			Message::TransactionModified(tx_id, version) => {
+				format!("Транзакция с id {tx_id} была изменена, текущая версия {version}")
+			}
+			Message::TransactionIsRefund(tx_id) => {
+				format!("Транзакция с id {tx_id} является возвратом")
+			}
+			Message::TransactionHasRefunds(tx_id) => {
+				format!("По транзакции с id {tx_id} есть возвраты, сначала удалите их")
+			}
+
+			Message::UnknownDirectory(kind) => format!("Неизвестный справочник `{kind}`"),
+			Message::InvalidDirectoryEntryId => {
+				String::from("Передан некорректный id записи справочника")
+			}
+			Message::InvalidExpandParams(reason) => {
+				format!("Передан некорректный параметр expand: {reason}")
+			}
+			Message::UnknownExpand(raw) => format!("Неизвестный справочник `{raw}` в expand"),
+			Message::EmptyValue => String::from("Значение не может быть пустым"),
+			Message::ValueTooLong(max_length) => {
+				format!("Значение не может быть длиннее {max_length} символов")
+			}
+			Message::UnitRequired => String::from("Для номенклатуры нужна единица измерения"),
+			Message::UnitNotAllowed => {
+				String::from("Единица измерения указывается только для номенклатуры")
+			}
+			Message::DirectoryEntryNotFound(id) => {
+				format!("Запись справочника с id {id} не найдена")
+			}
+			Message::DirectoryEntryInUse(id) => {
+				format!("На запись справочника с id {id} ссылаются транзакции или реализации")
+			}
+
+			Message::InvalidImplementationId => String::from("Передан некорректный id реализации"),
+			Message::ImplementationNotFound(id) => format!("Реализация с id {id} не найдена"),
+			Message::ImplementationPosted(id) => {
+				format!("Реализация с id {id} проведена, сначала отмените проведение")
+			}
+			Message::ImplementationHasTransactions(id) => {
+				format!("На реализацию с id {id} ссылаются транзакции")
+			}
+
+			Message::InvalidPeriod => String::from("Период передаётся как месяц в формате YYYY-MM"),
+			Message::PeriodClosed(month) => {
+				format!(
+					"Период {} закрыт, его транзакции нельзя менять",
+					month.format("%Y-%m")
+				)
+			}
+			Message::PeriodNotClosed(month) => format!("Период {} не закрыт", month.format("%Y-%m")),
+			Message::AdminOnly => String::from("Операция доступна только администраторам"),
+
+			Message::InvalidExportParams(reason) => {
+				format!("Переданы некорректные параметры выгрузки: {reason}")
+			}
+			Message::InvalidCsvDelimiter => String::from(
+				"Разделитель должен быть одним ASCII-символом, кроме кавычки и перевода строки",
+			),
+			Message::InvalidDecimalSeparator => {
+				String::from("Десятичный разделитель может быть только точкой или запятой")
+			}
+			Message::UnknownLanguage(lang) => format!("Неизвестный язык {lang}, доступны ru и en"),
+			Message::ExportColumn(name) => String::from(match name {
+				"id" => "Идентификатор",
+				"op_date" => "Дата операции",
+				"gas_station_id" => "АЗС",
+				"card_id" => "Карта",
+				"contract_id" => "Договор",
+				"nomenclature_id" => "Номенклатура",
+				"amount" => "Количество",
+				"stella_sum" => "Сумма Stella",
+				"stella_nds_sum" => "НДС Stella",
+				"refund" => "Возврат",
+				"buy_sum_plan" => "Сумма закупки, план",
+				"buy_nds_sum_plan" => "НДС закупки, план",
+				"buy_sum_fact" => "Сумма закупки, факт",
+				"buy_nds_sum_fact" => "НДС закупки, факт",
+				"sell_sum_plan" => "Сумма продажи, план",
+				"sell_nds_sum_plan" => "НДС продажи, план",
+				"sell_sum_fact" => "Сумма продажи, факт",
+				"sell_nds_sum_fact" => "НДС продажи, факт",
+				"margin_plan" => "Маржа, план",
+				"margin_fact" => "Маржа, факт",
+				"margin_plan_net" => "Маржа без НДС, план",
+				"margin_fact_net" => "Маржа без НДС, факт",
+				"implementation_id" => "Реализация",
+				"original_transaction_id" => "Исходная транзакция",
+				"user_id" => "Пользователь",
+				"created_by" => "Создал",
+				"updated_by" => "Изменил",
+				"deleted_by" => "Удалил",
+				"date_created" => "Дата создания",
+				"date_updated" => "Дата изменения",
+				"deleted" => "Удалена",
+				"version" => "Версия",
+				"period" => "Период",
+				"count" => "Транзакций",
+				"refund_count" => "Возвратов",
+				"buy_variance" => "Отклонение закупки",
+				"buy_variance_pct" => "Отклонение закупки, %",
+				"sell_variance" => "Отклонение продажи",
+				"sell_variance_pct" => "Отклонение продажи, %",
+				other => other,
+			}),
+			Message::ExportSheet(name) => String::from(match name {
+				"transactions" => "Транзакции",
+				"stats" => "Статистика",
+				"by_contract" => "По договорам",
+				"by_station" => "По АЗС",
+				"by_nomenclature" => "По номенклатуре",
+				other => other,
+			}),
+			Message::ExportTotal => String::from("Итого"),
+			Message::XlsxRowLimit(limit) => format!(
+				"В лист Excel помещается не больше {limit} строк, сузьте фильтры или выгрузите CSV"
+			),
+
+			Message::InvalidImportParams(reason) => {
+				format!("Переданы некорректные параметры загрузки: {reason}")
+			}
+			Message::UnknownImportField(field) => format!("У транзакции нет поля {field}"),
+			Message::InvalidImportHeader(reason) => {
+				format!("Не удалось прочитать заголовок файла: {reason}")
+			}
+			Message::ImportColumnNotFound(header) => format!("В файле нет колонки {header}"),
+			Message::ImportColumnMissing(field) => {
+				format!("В файле нет колонки для обязательного поля {field}")
+			}
+			Message::ImportEmpty => String::from("В файле нет строк для загрузки"),
+			Message::ImportSize(max) => format!("За раз можно загрузить не больше {max} строк"),
+		};
+	}
+
+	fn render_en(&self) -> String {
+		return match *self {
+			Message::ErrorTitle(code) => String::from(match code {
+				ErrorCode::BadRequest => "Bad request",
+				ErrorCode::InvalidBody => "Invalid request body",
+				ErrorCode::MalformedJson => "Malformed JSON",
+				ErrorCode::MissingContentType => "Missing JSON content type",
+				ErrorCode::UnreadableBody => "Unreadable request body",
+				ErrorCode::PayloadTooLarge => "Payload too large",
+				ErrorCode::Forbidden => "Forbidden",
+				ErrorCode::NotFound => "Resource not found",
+				ErrorCode::Conflict => "Conflict with the current state",
+				ErrorCode::PeriodClosed => "Period closed",
+				ErrorCode::PreconditionFailed => "Precondition failed",
+				ErrorCode::UnprocessableEntity => "Unprocessable request",
+				ErrorCode::ValidationFailed => "Validation failed",
+				ErrorCode::ServiceUnavailable => "Service unavailable",
+				ErrorCode::Timeout => "Timeout",
+				ErrorCode::InternalError => "Internal server error",
+			}),
+
+			Message::InvalidBody(None) => String::from("Invalid request body"),
+			Message::InvalidBody(Some(reason)) => format!("Invalid request body: {reason}"),
+			Message::MalformedJson => String::from("The request body is not valid JSON"),
+			Message::MissingJsonContentType => {
+				String::from("Please set the `Content-Type: application/json` header")
+			}
+			Message::UnreadableBody => String::from("Failed to read the request body"),
+			Message::PayloadTooLarge => String::from("The request body exceeds the allowed size"),
+			Message::MissingHeader(name) => format!("The {name} header is missing"),
+			Message::InvalidHeader(name) => format!("Invalid value of the {name} header"),
+			Message::InvalidPath => String::from("Invalid request path"),
+			Message::InvalidTransactionId => String::from("Invalid transaction id"),
+
+			Message::InvalidMoney(raw) => format!("invalid money value `{raw}`"),
+			Message::MoneyFractionDigits(raw, max) => {
+				format!("money value `{raw}` has more than {max} fraction digits")
+			}
+			Message::MoneyIntegerDigits(raw, max) => {
+				format!("money value `{raw}` has more than {max} integer digits")
+			}
+			Message::NullNotAllowed => String::from("the field can't be null"),
+
+			Message::InvalidBatchParams(reason) => format!("Invalid batch parameters: {reason}"),
+			Message::BatchSize(max) => format!("A batch must contain from 1 to {max} transactions"),
+			Message::InvalidBatchItem(reason) => format!("Invalid transaction: {reason}"),
+
+			Message::InvalidFilterParams(reason) => format!("Invalid filter parameters: {reason}"),
+			Message::InvalidDeletedParams(reason) => {
+				format!("Invalid deleted transactions visibility parameters: {reason}")
+			}
+			Message::DeletedModesConflict => {
+				String::from("include_deleted and only_deleted can't be used together")
+			}
+			Message::InvalidPaginationParams(reason) => {
+				format!("Invalid pagination parameters: {reason}")
+			}
+			Message::PageLimit(max) => format!("limit must be between 1 and {max}"),
+			Message::CursorWithOffset => String::from("cursor and offset can't be used together"),
+			Message::InvalidCursor => String::from("Invalid cursor"),
+
+			Message::InvalidStatsParams(reason) => format!("Invalid statistics parameters: {reason}"),
+			Message::UnknownStatsDimension(raw) => format!("Unknown grouping dimension `{raw}`"),
+			Message::SeveralStatsPeriods => {
+				String::from("Only one of the day, week and month periods can be used")
+			}
+
+			Message::InvalidVarianceParams(reason) => {
+				format!("Invalid variance thresholds: {reason}")
+			}
+			Message::NegativeVarianceThreshold => {
+				String::from("Variance thresholds cannot be negative")
+			}
+
+			Message::IdempotencyKeyLength(max) => {
+				format!("The Idempotency-Key header must be from 1 to {max} characters long")
+			}
+			Message::IdempotencyKeyReused(key) => {
+				format!("Idempotency key {key} has already been used with a different request body")
+			}
+			Message::IdempotencyKeyInProgress(key) => {
+				format!("Request with idempotency key {key} is still in progress")
+			}
+
+			Message::ValidationFailed => String::from("The transaction breaks business rules"),
+			Message::OpDateInFuture => String::from("The operation date can't be in the future"),
+			Message::NegativeAmount => String::from("The amount can't be negative"),
+			Message::NegativeSum => String::from("The sum can't be negative"),
+			Message::PositiveRefundSum => String::from("A refund sum can't be positive"),
+			Message::NdsExceedsSum(sum_field) => format!("NDS can't exceed {sum_field}"),
+			Message::OriginalOfNonRefund => {
+				String::from("Only a refund can reference an original transaction")
+			}
+			Message::OriginalNotFound(tx_id) => {
+				format!("Original transaction with id {tx_id} not found")
+			}
+			Message::OriginalIsRefund(tx_id) => {
+				format!("Original transaction with id {tx_id} is a refund itself")
+			}
+			Message::RefundOfItself => String::from("A transaction can't be a refund of itself"),
+			Message::RefundExceedsOriginal(left) => {
+				format!("Refunds exceed the original transaction, {left} is left to refund")
+			}
+			Message::RefundedTransactionIsRefund => {
+				String::from("A transaction with refunds can't be a refund")
+			}
+			Message::BelowRefunded(refunded) => {
+				format!("The value can't be less than the {refunded} already refunded")
+			}
+
+			Message::DuplicateRecord => String::from("Such a record already exists"),
+			Message::MissingReference => String::from("The record refers to a missing object"),
+			Message::ConstraintViolated => String::from("The data violates a storage constraint"),
+			Message::RequiredValueMissing => String::from("A required value is missing"),
+			Message::DatabaseUnavailable => {
+				String::from("The database is temporarily unavailable, please retry later")
+			}
+			Message::DatabaseTimeout => String::from("The database query has timed out"),
+			Message::InternalError => String::from("Internal server error"),
+
+			Message::TransactionNotFound(tx_id) => format!("Transaction with id {tx_id} not found"),
+			Message::TransactionNotDeleted(tx_id) => {
+				format!("Transaction with id {tx_id} is not deleted")
+			}
+			Message::TransactionModified(tx_id, version) => {
+				format!("Transaction with id {tx_id} has been modified, current version is {version}")
+			}
+			Message::TransactionIsRefund(tx_id) => {
+				format!("Transaction with id {tx_id} is a refund")
+			}
+			Message::TransactionHasRefunds(tx_id) => {
+				format!("Transaction with id {tx_id} has refunds, delete them first")
+			}
+
+			Message::UnknownDirectory(kind) => format!("Unknown directory `{kind}`"),
+			Message::InvalidDirectoryEntryId => String::from("Invalid directory entry id"),
+			Message::InvalidExpandParams(reason) => format!("Invalid expand parameter: {reason}"),
+			Message::UnknownExpand(raw) => format!("Unknown directory `{raw}` in expand"),
+			Message::EmptyValue => String::from("The value can't be empty"),
+			Message::ValueTooLong(max_length) => {
+				format!("The value can't be longer than {max_length} characters")
+			}
+			Message::UnitRequired => String::from("Nomenclature needs a unit of measure"),
+			Message::UnitNotAllowed => String::from("Only nomenclature has a unit of measure"),
+			Message::DirectoryEntryNotFound(id) => {
+				format!("Directory entry with id {id} not found")
+			}
+			Message::DirectoryEntryInUse(id) => {
+				format!("Directory entry with id {id} is referenced by transactions or implementations")
+			}
+
+			Message::InvalidImplementationId => String::from("Invalid implementation id"),
+			Message::ImplementationNotFound(id) => format!("Implementation with id {id} not found"),
+			Message::ImplementationPosted(id) => {
+				format!("Implementation with id {id} is posted, unpost it first")
+			}
+			Message::ImplementationHasTransactions(id) => {
+				format!("Implementation with id {id} is referenced by transactions")
+			}
+
+			Message::InvalidPeriod => String::from("The period must be a month formatted as YYYY-MM"),
+			Message::PeriodClosed(month) => format!(
+				"Period {} is closed, its transactions can't be changed",
+				month.format("%Y-%m")
+			),
+			Message::PeriodNotClosed(month) => {
+				format!("Period {} is not closed", month.format("%Y-%m"))
+			}
+			Message::AdminOnly => String::from("Only administrators can do this"),
+
+			Message::InvalidExportParams(reason) => format!("Invalid export params: {reason}"),
+			Message::InvalidCsvDelimiter => String::from(
+				"The delimiter must be a single ASCII character other than a quote or a line break",
+			),
+			Message::InvalidDecimalSeparator => {
+				String::from("The decimal separator can only be a dot or a comma")
+			}
+			Message::UnknownLanguage(lang) => format!("Unknown language {lang}, use ru or en"),
+			Message::ExportColumn(name) => String::from(match name {
+				"id" => "Id",
+				"op_date" => "Operation date",
+				"gas_station_id" => "Gas station",
+				"card_id" => "Card",
+				"contract_id" => "Contract",
+				"nomenclature_id" => "Nomenclature",
+				"amount" => "Amount",
+				"stella_sum" => "Stella sum",
+				"stella_nds_sum" => "Stella NDS",
+				"refund" => "Refund",
+				"buy_sum_plan" => "Buy sum, plan",
+				"buy_nds_sum_plan" => "Buy NDS, plan",
+				"buy_sum_fact" => "Buy sum, fact",
+				"buy_nds_sum_fact" => "Buy NDS, fact",
+				"sell_sum_plan" => "Sell sum, plan",
+				"sell_nds_sum_plan" => "Sell NDS, plan",
+				"sell_sum_fact" => "Sell sum, fact",
+				"sell_nds_sum_fact" => "Sell NDS, fact",
+				"margin_plan" => "Margin, plan",
+				"margin_fact" => "Margin, fact",
+				"margin_plan_net" => "Net margin, plan",
+				"margin_fact_net" => "Net margin, fact",
+				"implementation_id" => "Implementation",
+				"original_transaction_id" => "Original transaction",
+				"user_id" => "User",
+				"created_by" => "Created by",
+				"updated_by" => "Updated by",
+				"deleted_by" => "Deleted by",
+				"date_created" => "Created at",
+				"date_updated" => "Updated at",
+				"deleted" => "Deleted",
+				"version" => "Version",
+				"period" => "Period",
+				"count" => "Transactions",
+				"refund_count" => "Refunds",
+				"buy_variance" => "Buy variance",
+				"buy_variance_pct" => "Buy variance, %",
+				"sell_variance" => "Sell variance",
+				"sell_variance_pct" => "Sell variance, %",
+				other => other,
+			}),
+			Message::ExportSheet(name) => String::from(match name {
+				"transactions" => "Transactions",
+				"stats" => "Statistics",
+				"by_contract" => "By contract",
+				"by_station" => "By station",
+				"by_nomenclature" => "By nomenclature",
+				other => other,
+			}),
+			Message::ExportTotal => String::from("Total"),
+			Message::XlsxRowLimit(limit) => {
+				format!("An Excel sheet holds at most {limit} rows, narrow the filters or export CSV")
+			}
+
+			Message::InvalidImportParams(reason) => format!("Invalid import params: {reason}"),
+			Message::UnknownImportField(field) => format!("A transaction has no field {field}"),
+			Message::InvalidImportHeader(reason) => {
+				format!("Failed to read the header of the file: {reason}")
+			}
+			Message::ImportColumnNotFound(header) => format!("The file has no column {header}"),
+			Message::ImportColumnMissing(field) => {
+				format!("The file has no column for the required field {field}")
+			}
+			Message::ImportEmpty => String::from("The file has no lines to import"),
+			Message::ImportSize(max) => format!("At most {max} lines can be imported at once"),
+		};
+	}
+}
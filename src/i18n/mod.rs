@@ -0,0 +1,99 @@
+mod messages;
+
+use ::std::str::FromStr;
+use axum::{
+	extract::Request,
+	http::{header, HeaderMap},
+	middleware::Next,
+	response::Response,
+};
+
+use crate::config;
+
+pub use messages::Message;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lang {
+	Ru,
+	En,
+}
+
+impl Lang {
+	pub fn tag(&self) -> &'static str {
+		return match self {
+			Lang::Ru => "ru",
+			Lang::En => "en",
+		};
+	}
+
+	/// Picks the supported language with the highest weight, `None` if nothing fits
+	pub fn from_accept_language(headers: &HeaderMap) -> Option<Self> {
+		let header = headers.get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+
+		let mut best: Option<(Lang, f32)> = None;
+
+		for range in header.split(',') {
+			let mut parts = range.split(';').map(|part| part.trim());
+			let tag = parts.next().unwrap_or_default();
+
+			let weight = parts
+				.find_map(|param| param.strip_prefix("q="))
+				.map_or(Some(1.0), |q| q.parse::<f32>().ok());
+
+			let Some(weight) = weight else {
+				continue;
+			};
+
+			// "ru-RU" is as good as "ru", the wildcard means the configured default
+			let primary = tag.split('-').next().unwrap_or_default();
+			let lang = match primary {
+				"*" => config::get_default_lang(),
+				other => match Lang::from_str(other) {
+					Ok(lang) => lang,
+					Err(_) => continue,
+				},
+			};
+
+			if weight > 0.0 && best.is_none_or(|(_, best_weight)| weight > best_weight) {
+				best = Some((lang, weight));
+			}
+		}
+
+		return best.map(|(lang, _)| lang);
+	}
+}
+
+impl FromStr for Lang {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		return match s.to_ascii_lowercase().as_str() {
+			"ru" => Ok(Lang::Ru),
+			"en" => Ok(Lang::En),
+			_ => Err(()),
+		};
+	}
+}
+
+tokio::task_local! {
+	static REQUEST_LANG: Lang;
+}
+
+/// Language of the request being handled, the configured default outside of a request
+pub fn current_lang() -> Lang {
+	return REQUEST_LANG
+		.try_with(|lang| *lang)
+		.unwrap_or_else(|_| config::get_default_lang());
+}
+
+/// Renders a message in the language of the current request
+pub fn t(message: Message) -> String {
+	return message.render(current_lang());
+}
+
+/// Makes the `Accept-Language` of the request available to every error built while handling it
+pub async fn negotiate_language(req: Request, next: Next) -> Response {
+	let lang = Lang::from_accept_language(req.headers()).unwrap_or_else(config::get_default_lang);
+
+	return REQUEST_LANG.scope(lang, next.run(req)).await;
+}
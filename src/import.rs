@@ -0,0 +1,233 @@
+use ::csv::{ReaderBuilder, StringRecord};
+use chrono::NaiveDate;
+use serde_json::{Map, Value};
+
+use crate::dto::{
+	ApiTransaction, ImportColumns, ImportLineError, ImportOptions, UserId, MAX_IMPORT_LINES,
+};
+use crate::i18n::{t, Lang, Message};
+use crate::repository::models::ImportReport;
+use crate::repository::Repository;
+use crate::system_models::AppError;
+
+/// A file without a column for any of these can't hold a single valid transaction
+const REQUIRED_FIELDS: [&str; 4] = ["op_date", "gas_station_id", "nomenclature_id", "refund"];
+
+const MONEY_FIELDS: [&str; 11] = [
+	"amount",
+	"stella_sum",
+	"stella_nds_sum",
+	"buy_sum_plan",
+	"buy_nds_sum_plan",
+	"buy_sum_fact",
+	"buy_nds_sum_fact",
+	"sell_sum_plan",
+	"sell_nds_sum_plan",
+	"sell_sum_fact",
+	"sell_nds_sum_fact",
+];
+
+/// Transactions read out of a CSV file, the lines which can't be read are reported instead
+pub struct ImportFile {
+	pub lines: Vec<(usize, ApiTransaction)>,
+	pub errors: Vec<ImportLineError>,
+}
+
+struct Column {
+	field: &'static str,
+	index: usize,
+}
+
+/// Reads the file, checks every line against the stored data and creates the transactions
+/// only when all of them are valid
+pub async fn import_csv(
+	repo: &Repository,
+	user_id: UserId,
+	data: &[u8],
+	options: ImportOptions,
+	columns: &ImportColumns,
+) -> Result<ImportReport, AppError> {
+	let ImportFile { lines, mut errors } = parse_csv(data, options, columns)?;
+	let count = lines.len() + errors.len();
+
+	// the readable lines are still checked so that the report lists every problem at once
+	let dry_run = options.dry_run || !errors.is_empty();
+	errors.append(&mut repo.import_transactions(user_id, lines, dry_run).await?);
+	errors.sort_by_key(|err| err.line);
+
+	let created = match dry_run || !errors.is_empty() {
+		true => 0,
+		false => count,
+	};
+
+	return Ok(ImportReport {
+		dry_run: options.dry_run,
+		lines: count,
+		created,
+		errors,
+	});
+}
+
+pub fn parse_csv(
+	data: &[u8],
+	options: ImportOptions,
+	columns: &ImportColumns,
+) -> Result<ImportFile, AppError> {
+	let mut reader = ReaderBuilder::new()
+		.delimiter(options.delimiter)
+		.flexible(true)
+		.from_reader(data);
+
+	let headers = reader
+		.headers()
+		.map_err(|err| AppError::BadRequest(t(Message::InvalidImportHeader(&err.to_string()))))?;
+	let columns = resolve_columns(headers, columns)?;
+
+	let mut lines = Vec::new();
+	let mut errors = Vec::new();
+	let mut record = StringRecord::new();
+
+	loop {
+		let line = reader.position().line();
+		let read = reader.read_record(&mut record);
+
+		// unreadable lines count too, or a broken file would grow the report without bound
+		if !matches!(read, Ok(false)) && lines.len() + errors.len() == MAX_IMPORT_LINES {
+			return Err(AppError::BadRequest(t(Message::ImportSize(
+				MAX_IMPORT_LINES,
+			))));
+		}
+
+		match read {
+			Ok(false) => break,
+			Ok(true) => {}
+			Err(err) => {
+				let line = err.position().map_or(line, |position| position.line());
+				errors.push(ImportLineError::new(line as usize, invalid_line(&err)));
+				continue;
+			}
+		}
+
+		let line = record.position().map_or(line, |position| position.line()) as usize;
+
+		match parse_line(&record, &columns, options) {
+			Ok(tx) => lines.push((line, tx)),
+			Err(err) => errors.push(ImportLineError::new(line, err)),
+		}
+	}
+
+	if lines.is_empty() && errors.is_empty() {
+		return Err(AppError::BadRequest(t(Message::ImportEmpty)));
+	}
+
+	return Ok(ImportFile { lines, errors });
+}
+
+/// A mapped field takes its column by the header given, the others are looked up by
+/// the field name and by the column titles of the CSV export in every language
+fn resolve_columns(
+	headers: &StringRecord,
+	columns: &ImportColumns,
+) -> Result<Vec<Column>, AppError> {
+	let headers: Vec<String> = headers
+		.iter()
+		.map(|header| header.trim_start_matches('\u{feff}').trim().to_lowercase())
+		.collect();
+	let find = |name: &str| {
+		headers
+			.iter()
+			.position(|header| *header == name.to_lowercase())
+	};
+
+	let mut resolved = Vec::new();
+
+	for (field, mapped) in columns.fields() {
+		let index = match mapped {
+			Some(header) => match find(header.trim()) {
+				Some(index) => Some(index),
+				None => {
+					return Err(AppError::BadRequest(t(Message::ImportColumnNotFound(
+						header,
+					))))
+				}
+			},
+			None => find(field)
+				.or_else(|| find(&Message::ExportColumn(field).render(Lang::Ru)))
+				.or_else(|| find(&Message::ExportColumn(field).render(Lang::En))),
+		};
+
+		match index {
+			Some(index) => resolved.push(Column { field, index }),
+			None if REQUIRED_FIELDS.contains(&field) => {
+				return Err(AppError::BadRequest(t(Message::ImportColumnMissing(field))));
+			}
+			None => {}
+		}
+	}
+
+	return Ok(resolved);
+}
+
+/// Empty cells are left out, so they read as missing values
+fn parse_line(
+	record: &StringRecord,
+	columns: &[Column],
+	options: ImportOptions,
+) -> Result<ApiTransaction, AppError> {
+	let mut object = Map::new();
+
+	for Column { field, index } in columns {
+		let raw = record.get(*index).unwrap_or_default().trim();
+
+		if raw.is_empty() {
+			continue;
+		}
+
+		object.insert(String::from(*field), parse_value(field, raw, options));
+	}
+
+	let tx = serde_json::from_value::<ApiTransaction>(Value::Object(object)).map_err(|err| {
+		return AppError::InvalidBody(t(Message::InvalidBatchItem(&err.to_string())));
+	})?;
+	tx.validate_at("")?;
+
+	return Ok(tx);
+}
+
+/// Converts a cell to what the JSON API takes, a value which doesn't fit is passed on
+/// as is to be reported by the deserializer
+fn parse_value(field: &str, raw: &str, options: ImportOptions) -> Value {
+	if MONEY_FIELDS.contains(&field) {
+		// spreadsheets group the digits with spaces, including the non-breaking ones
+		let amount: String = raw
+			.chars()
+			.filter(|c| !c.is_whitespace())
+			.map(|c| match c == options.decimal_separator {
+				true => '.',
+				false => c,
+			})
+			.collect();
+		return Value::from(amount);
+	}
+
+	if field == "refund" {
+		return match raw.to_lowercase().as_str() {
+			"true" | "1" | "yes" | "да" => Value::Bool(true),
+			"false" | "0" | "no" | "нет" => Value::Bool(false),
+			_ => Value::from(raw),
+		};
+	}
+
+	// a bare date stands for the start of the day in UTC
+	if field == "op_date" {
+		if let Ok(date) = NaiveDate::parse_from_str(raw, "%Y-%m-%d") {
+			return Value::from(format!("{date}T00:00:00Z"));
+		}
+	}
+
+	return Value::from(raw);
+}
+
+fn invalid_line(err: &::csv::Error) -> AppError {
+	return AppError::InvalidBody(t(Message::InvalidBatchItem(&err.to_string())));
+}
@@ -1,7 +1,10 @@
 pub mod config;
 pub mod dto;
+pub mod export;
 pub mod graceful_shutdown;
 pub mod handler;
+pub mod i18n;
+pub mod import;
 pub mod repository;
 pub mod router;
 pub mod system_models;
@@ -1,11 +1,91 @@
+use ::std::path::PathBuf;
+use ::std::process::ExitCode;
 use ::std::sync::Arc;
+use clap::{Parser, Subcommand};
 use fuel::config;
+use fuel::dto::{ImportColumns, ImportOptions, UserId};
 use fuel::graceful_shutdown::shutdown_signal;
+use fuel::import;
 use fuel::repository::Repository;
 use fuel::router;
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(about = "Fuel transactions service")]
+struct Cli {
+	/// Runs the HTTP server when omitted
+	#[command(subcommand)]
+	command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Creates transactions out of a CSV file, all of them or none
+	Import {
+		file: PathBuf,
+		/// Author of the created transactions
+		#[arg(long)]
+		user_id: Uuid,
+		/// Single ASCII character between the fields
+		#[arg(long)]
+		delimiter: Option<String>,
+		/// `.` or `,`
+		#[arg(long)]
+		decimal_separator: Option<String>,
+		/// Checks every line without writing anything
+		#[arg(long)]
+		dry_run: bool,
+		/// Header of the column holding a field, e.g. `op_date=Дата`
+		#[arg(long = "column", value_name = "FIELD=HEADER")]
+		columns: Vec<String>,
+	},
+}
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+	// fail on a broken environment before serving anything
+	config::get_default_lang();
+
+	return match Cli::parse().command {
+		None => serve().await,
+		Some(Command::Import {
+			file,
+			user_id,
+			delimiter,
+			decimal_separator,
+			dry_run,
+			columns,
+		}) => {
+			let options =
+				ImportOptions::new(delimiter.as_deref(), decimal_separator.as_deref(), dry_run);
+			let columns = ImportColumns::from_pairs(columns.iter().map(String::as_str));
+
+			let (options, columns) = match (options, columns) {
+				(Ok(options), Ok(columns)) => (options, columns),
+				(Err(err), _) | (_, Err(err)) => {
+					eprintln!("{err}");
+					return ExitCode::FAILURE;
+				}
+			};
+
+			let data = match tokio::fs::read(&file).await {
+				Ok(data) => data,
+				Err(err) => {
+					eprintln!("Failed to read {}: {err}", file.display());
+					return ExitCode::FAILURE;
+				}
+			};
+
+			import_file(UserId(user_id), &data, options, &columns).await
+		}
+	};
+}
+
+async fn serve() -> ExitCode {
+	config::get_variance_abs_threshold();
+	config::get_variance_pct_threshold();
+	config::get_admin_user_ids();
+
 	let repo = Repository::new().await;
 	let repo = Arc::new(repo);
 	let app = router::create_router(repo.clone());
@@ -19,4 +99,33 @@ async fn main() {
 		.with_graceful_shutdown(shutdown_signal(repo))
 		.await
 		.unwrap();
+
+	return ExitCode::SUCCESS;
+}
+
+/// Prints the report as JSON, the exit code tells whether every line is valid
+async fn import_file(
+	user_id: UserId,
+	data: &[u8],
+	options: ImportOptions,
+	columns: &ImportColumns,
+) -> ExitCode {
+	let repo = Repository::new().await;
+	let report = import::import_csv(&repo, user_id, data, options, columns).await;
+	repo.close().await;
+
+	return match report {
+		Ok(report) => {
+			println!("{}", serde_json::to_string_pretty(&report).unwrap());
+
+			match report.errors.is_empty() {
+				true => ExitCode::SUCCESS,
+				false => ExitCode::FAILURE,
+			}
+		}
+		Err(err) => {
+			eprintln!("{err}");
+			ExitCode::FAILURE
+		}
+	};
 }
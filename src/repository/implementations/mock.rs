@@ -1,41 +1,438 @@
-use super::super::Store;
-use crate::dto::{ApiTransaction, TxId, UserId};
-use crate::repository::models::Transaction;
+use super::super::{Store, TransactionBatches};
+use crate::config;
+use crate::dto::{
+	AdminId, ApiDirectoryEntry, ApiImplementation, ApiTransaction, ApiTransactionPatch,
+	BatchItemError, BatchMode, DeletedMode, DirectoryEntryId, DirectoryFilter, DirectoryKind,
+	ETagCondition, IdempotencyKey, ImplementationId, ImplementationsFilter, ImportLineError, Page,
+	PeriodMonth, StatsDimension, TransactionsFilter, TxId, UserId, VarianceThresholds,
+};
+use crate::i18n::{t, Message};
+use crate::repository::models::{
+	BatchItem, BatchResult, ClosedPeriod, DirectoryEntry, HistoryOperation, Implementation,
+	ImplementationStatus, ImplementationTotals, MarginReport, MarginTotals, StatsRow, Transaction,
+	TransactionHistoryEntry, TransactionRefunds, TransactionVariance, TransactionsPage,
+	TransactionsStats, VarianceReport,
+};
 use crate::system_models::AppError;
+use ::std::collections::{BTreeMap, HashMap};
 use ::std::sync::Arc;
-use chrono::Utc;
-use rust_decimal::prelude::FromPrimitive;
-use rust_decimal::Decimal;
-use tokio::sync::RwLock;
+use chrono::{DateTime, Datelike, Days, Duration, NaiveDate, Utc};
+use sqlx::types::Json;
+use tokio::sync::{mpsc, RwLock};
 use uuid::Uuid;
 
+struct IdempotencyRecord {
+	request_hash: String,
+	response: Transaction,
+	date_created: DateTime<Utc>,
+}
+
 #[derive(Clone)]
 pub struct MockStore {
 	store: Arc<RwLock<Vec<Transaction>>>,
+	history: Arc<RwLock<Vec<TransactionHistoryEntry>>>,
+	closed_periods: Arc<RwLock<BTreeMap<NaiveDate, ClosedPeriod>>>,
+	implementations: Arc<RwLock<Vec<Implementation>>>,
+	directories: Arc<RwLock<HashMap<DirectoryKind, Vec<DirectoryEntry>>>>,
+	idempotency_keys: Arc<RwLock<HashMap<(Uuid, String), IdempotencyRecord>>>,
+	idempotency_key_ttl: Duration,
 }
 
 impl MockStore {
 	pub fn new() -> Self {
 		Self {
 			store: Arc::new(RwLock::new(Vec::new())),
+			history: Arc::new(RwLock::new(Vec::new())),
+			closed_periods: Arc::new(RwLock::new(BTreeMap::new())),
+			implementations: Arc::new(RwLock::new(Vec::new())),
+			directories: Arc::new(RwLock::new(HashMap::new())),
+			idempotency_keys: Arc::new(RwLock::new(HashMap::new())),
+			idempotency_key_ttl: config::get_idempotency_key_ttl(),
+		}
+	}
+}
+
+fn matches_filter(tx: &Transaction, filter: &TransactionsFilter) -> bool {
+	return filter
+		.gas_station_id
+		.is_none_or(|id| tx.gas_station_id == id)
+		&& filter.card_id.is_none_or(|id| tx.card_id == Some(id))
+		&& filter
+			.contract_id
+			.is_none_or(|id| tx.contract_id == Some(id))
+		&& filter
+			.nomenclature_id
+			.is_none_or(|id| tx.nomenclature_id == id)
+		&& filter
+			.implementation_id
+			.is_none_or(|id| tx.implementation_id == Some(id))
+		&& filter.user_id.is_none_or(|id| tx.user_id == id)
+		&& filter.created_by.is_none_or(|id| tx.created_by == id)
+		&& filter.refund.is_none_or(|refund| tx.refund == refund)
+		&& filter.op_date_from.is_none_or(|from| tx.op_date >= from)
+		&& filter.op_date_to.is_none_or(|to| tx.op_date < to);
+}
+
+fn new_transaction(user_id: Uuid, new_tx: ApiTransaction, now: DateTime<Utc>) -> Transaction {
+	let mut tx = Transaction {
+		id: Uuid::new_v4(),
+		op_date: new_tx.op_date,
+		gas_station_id: new_tx.gas_station_id,
+		card_id: new_tx.card_id,
+		contract_id: new_tx.contract_id,
+		nomenclature_id: new_tx.nomenclature_id,
+		amount: new_tx.amount,
+		stella_sum: new_tx.stella_sum,
+		stella_nds_sum: new_tx.stella_nds_sum,
+		refund: new_tx.refund,
+		buy_sum_plan: new_tx.buy_sum_plan,
+		buy_nds_sum_plan: new_tx.buy_nds_sum_plan,
+		buy_sum_fact: new_tx.buy_sum_fact,
+		buy_nds_sum_fact: new_tx.buy_nds_sum_fact,
+		sell_sum_plan: new_tx.sell_sum_plan,
+		sell_nds_sum_plan: new_tx.sell_nds_sum_plan,
+		sell_sum_fact: new_tx.sell_sum_fact,
+		sell_nds_sum_fact: new_tx.sell_nds_sum_fact,
+		margin_plan: None,
+		margin_fact: None,
+		margin_plan_net: None,
+		margin_fact_net: None,
+		implementation_id: new_tx.implementation_id,
+		original_transaction_id: new_tx.original_transaction_id,
+		user_id,
+		created_by: user_id,
+		updated_by: None,
+		deleted_by: None,
+		date_created: now,
+		date_updated: Some(now),
+		deleted: false,
+		version: 1,
+		gas_station: None,
+		card: None,
+		contract: None,
+		nomenclature: None,
+	};
+	tx.refresh_margins();
+
+	return tx;
+}
+
+fn apply_changes(existing_tx: &mut Transaction, user_id: Uuid, tx: ApiTransaction) {
+	existing_tx.op_date = tx.op_date;
+	existing_tx.gas_station_id = tx.gas_station_id;
+	existing_tx.card_id = tx.card_id;
+	existing_tx.contract_id = tx.contract_id;
+	existing_tx.nomenclature_id = tx.nomenclature_id;
+	existing_tx.amount = tx.amount;
+	existing_tx.stella_sum = tx.stella_sum;
+	existing_tx.stella_nds_sum = tx.stella_nds_sum;
+	existing_tx.refund = tx.refund;
+	existing_tx.buy_sum_plan = tx.buy_sum_plan;
+	existing_tx.buy_nds_sum_plan = tx.buy_nds_sum_plan;
+	existing_tx.buy_sum_fact = tx.buy_sum_fact;
+	existing_tx.buy_nds_sum_fact = tx.buy_nds_sum_fact;
+	existing_tx.sell_sum_plan = tx.sell_sum_plan;
+	existing_tx.sell_nds_sum_plan = tx.sell_nds_sum_plan;
+	existing_tx.sell_sum_fact = tx.sell_sum_fact;
+	existing_tx.sell_nds_sum_fact = tx.sell_nds_sum_fact;
+	existing_tx.refresh_margins();
+	existing_tx.implementation_id = tx.implementation_id;
+	existing_tx.original_transaction_id = tx.original_transaction_id;
+	existing_tx.user_id = user_id;
+	existing_tx.updated_by = Some(user_id);
+	existing_tx.date_updated = Some(Utc::now());
+	existing_tx.version += 1;
+}
+
+/// Active refunds of a purchase, except the one being edited
+fn refunds_of(
+	store: &[Transaction],
+	original_id: Uuid,
+	except_id: Option<Uuid>,
+) -> Vec<&Transaction> {
+	return store
+		.iter()
+		.filter(|tx| {
+			!tx.deleted && tx.original_transaction_id == Some(original_id) && Some(tx.id) != except_id
+		})
+		.collect();
+}
+
+fn check_refund(
+	store: &[Transaction],
+	pointer: &str,
+	tx: &ApiTransaction,
+	own_id: Option<Uuid>,
+) -> Result<(), AppError> {
+	let Some(original_id) = tx.original_transaction_id else {
+		return Ok(());
+	};
+
+	let original = store.iter().find(|o| o.id == original_id && !o.deleted);
+	let refunds: Vec<ApiTransaction> = refunds_of(store, original_id, own_id)
+		.into_iter()
+		.map(ApiTransaction::from)
+		.collect();
+
+	return tx.validate_refund_at(pointer, own_id, original, &refunds);
+}
+
+fn check_refunded(store: &[Transaction], tx_id: Uuid, tx: &ApiTransaction) -> Result<(), AppError> {
+	let refunds: Vec<ApiTransaction> = refunds_of(store, tx_id, None)
+		.into_iter()
+		.map(ApiTransaction::from)
+		.collect();
+
+	return tx.validate_refunded_at("", &refunds);
+}
+
+/// Mirrors the foreign keys of `transactions`
+fn check_references(
+	implementations: &[Implementation],
+	directories: &HashMap<DirectoryKind, Vec<DirectoryEntry>>,
+	tx: &ApiTransaction,
+) -> Result<(), AppError> {
+	let exists = |kind: DirectoryKind, id: Uuid| {
+		directories
+			.get(&kind)
+			.is_some_and(|entries| entries.iter().any(|entry| entry.id == id))
+	};
+
+	let missing = tx
+		.references()
+		.into_iter()
+		.any(|(kind, id)| id.is_some_and(|id| !exists(kind, id)))
+		|| tx
+			.implementation_id
+			.is_some_and(|id| !implementations.iter().any(|doc| doc.id == id));
+
+	return match missing {
+		true => Err(AppError::Conflict(t(Message::MissingReference))),
+		false => Ok(()),
+	};
+}
+
+fn check_periods(
+	closed_periods: &BTreeMap<NaiveDate, ClosedPeriod>,
+	op_dates: &[DateTime<Utc>],
+) -> Result<(), AppError> {
+	let closed = op_dates
+		.iter()
+		.map(|op_date| ClosedPeriod::month_of(*op_date))
+		.find(|month| closed_periods.contains_key(month));
+
+	return match closed {
+		Some(month) => Err(AppError::PeriodClosed(t(Message::PeriodClosed(month)))),
+		None => Ok(()),
+	};
+}
+
+/// Transactions of a posted document can be neither attached, detached nor edited
+fn check_implementations(
+	implementations: &[Implementation],
+	ids: &[Option<Uuid>],
+) -> Result<(), AppError> {
+	let posted = implementations
+		.iter()
+		.find(|doc| doc.status == ImplementationStatus::Posted && ids.contains(&Some(doc.id)));
+
+	return match posted {
+		Some(doc) => Err(AppError::Conflict(t(Message::ImplementationPosted(doc.id)))),
+		None => Ok(()),
+	};
+}
+
+/// The document with totals of its active transactions
+fn with_totals(store: &[Transaction], implementation: &Implementation) -> Implementation {
+	let mut totals = ImplementationTotals::default();
+
+	for tx in store
+		.iter()
+		.filter(|tx| !tx.deleted && tx.implementation_id == Some(implementation.id))
+	{
+		totals.add(tx);
+	}
+
+	return Implementation {
+		totals,
+		..implementation.clone()
+	};
+}
+
+fn contract_exists(
+	directories: &HashMap<DirectoryKind, Vec<DirectoryEntry>>,
+	contract_id: Uuid,
+) -> bool {
+	return directories
+		.get(&DirectoryKind::Contracts)
+		.is_some_and(|entries| entries.iter().any(|entry| entry.id == contract_id));
+}
+
+fn directory_entry(
+	id: Uuid,
+	entry: ApiDirectoryEntry,
+	date_created: DateTime<Utc>,
+) -> DirectoryEntry {
+	return DirectoryEntry {
+		id,
+		name: entry.name,
+		code: entry.code,
+		active: entry.active,
+		unit: entry.unit,
+		date_created,
+		date_updated: Some(Utc::now()),
+	};
+}
+
+fn history_entry(
+	operation: HistoryOperation,
+	user_id: Uuid,
+	before: Option<Transaction>,
+	after: &Transaction,
+) -> TransactionHistoryEntry {
+	return TransactionHistoryEntry {
+		id: Uuid::new_v4(),
+		transaction_id: after.id,
+		operation,
+		user_id,
+		before: before.map(Json),
+		after: Some(Json(after.clone())),
+		date_created: after.date_updated.unwrap_or(after.date_created),
+	};
+}
+
+/// Group of the in-memory statistics, sorted the same way as `NULLS FIRST` in Postgres
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct StatsKey {
+	gas_station_id: Option<Uuid>,
+	card_id: Option<Uuid>,
+	contract_id: Option<Uuid>,
+	nomenclature_id: Option<Uuid>,
+	period: Option<NaiveDate>,
+}
+
+impl StatsKey {
+	fn of(tx: &Transaction, group_by: &[StatsDimension]) -> Self {
+		let mut key = StatsKey::default();
+		let date = tx.op_date.date_naive();
+
+		for dimension in group_by {
+			match dimension {
+				StatsDimension::GasStationId => key.gas_station_id = Some(tx.gas_station_id),
+				StatsDimension::CardId => key.card_id = tx.card_id,
+				StatsDimension::ContractId => key.contract_id = tx.contract_id,
+				StatsDimension::NomenclatureId => key.nomenclature_id = Some(tx.nomenclature_id),
+				StatsDimension::Day => key.period = Some(date),
+				StatsDimension::Week => {
+					key.period = Some(date - Days::new(u64::from(date.weekday().num_days_from_monday())))
+				}
+				StatsDimension::Month => key.period = date.with_day(1),
+			}
 		}
+
+		return key;
+	}
+
+	fn to_row(self) -> StatsRow {
+		return StatsRow {
+			gas_station_id: self.gas_station_id,
+			card_id: self.card_id,
+			contract_id: self.contract_id,
+			nomenclature_id: self.nomenclature_id,
+			period: self.period,
+			..StatsRow::default()
+		};
+	}
+}
+
+fn margin_totals<F>(transactions: &[&Transaction], key: F) -> Vec<MarginTotals>
+where
+	F: Fn(&Transaction) -> Option<Uuid>,
+{
+	let mut totals: BTreeMap<Option<Uuid>, MarginTotals> = BTreeMap::new();
+
+	for tx in transactions {
+		let id = key(tx);
+
+		totals
+			.entry(id)
+			.or_insert_with(|| MarginTotals {
+				id,
+				..MarginTotals::default()
+			})
+			.add(tx);
 	}
+
+	return totals.into_values().collect();
 }
 
 impl Store for MockStore {
-	async fn get_transactions_list(&self) -> Result<Vec<Transaction>, AppError> {
+	async fn get_transactions_list(
+		&self,
+		filter: TransactionsFilter,
+		deleted: DeletedMode,
+		page: Page,
+	) -> Result<TransactionsPage, AppError> {
+		let current_store = self.store.read().await;
+
+		let mut matched: Vec<&Transaction> = current_store
+			.iter()
+			.filter(|tx| deleted.allows(tx.deleted) && matches_filter(tx, &filter))
+			.collect();
+		matched.sort_by_key(|tx| (tx.date_created, tx.id));
+
+		let total = page.with_total.then_some(matched.len() as i64);
+
+		let rows = matched
+			.into_iter()
+			.filter(|tx| {
+				page
+					.after
+					.is_none_or(|after| (tx.date_created, tx.id) > (after.date_created, after.id))
+			})
+			.skip(page.offset as usize)
+			.take(page.limit as usize + 1)
+			.cloned()
+			.collect();
+
+		return Ok(TransactionsPage::from_rows(rows, page.limit, total));
+	}
+
+	async fn export_transactions(
+		&self,
+		filter: TransactionsFilter,
+		deleted: DeletedMode,
+	) -> Result<TransactionBatches, AppError> {
 		let current_store = self.store.read().await;
-		return Ok(current_store.iter().cloned().collect());
+
+		let mut matched: Vec<Transaction> = current_store
+			.iter()
+			.filter(|tx| deleted.allows(tx.deleted) && matches_filter(tx, &filter))
+			.cloned()
+			.collect();
+		matched.sort_by_key(|tx| (tx.date_created, tx.id));
+
+		// The whole store is in memory anyway, a single batch is enough
+		let (sender, receiver) = mpsc::channel(1);
+		if !matched.is_empty() {
+			let _ = sender.try_send(Ok(matched));
+		}
+
+		return Ok(receiver);
 	}
 
-	async fn get_transaction(&self, TxId(tx_id): TxId) -> Result<Transaction, AppError> {
+	async fn get_transaction(
+		&self,
+		TxId(tx_id): TxId,
+		deleted: DeletedMode,
+	) -> Result<Transaction, AppError> {
 		let current_store = self.store.read().await;
-		let entry = current_store.iter().find(|tx| tx.id == tx_id);
+		let entry = current_store
+			.iter()
+			.find(|tx| tx.id == tx_id && deleted.allows(tx.deleted));
 
 		return match entry {
-			None => Err(AppError::NotFound(format!(
-				"Transaction with id {tx_id} not found"
-			))),
+			None => Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id)))),
 			Some(tx) => Ok(tx.clone()),
 		};
 	}
@@ -44,86 +441,259 @@ impl Store for MockStore {
 		&self,
 		UserId(user_id): UserId,
 		new_tx: ApiTransaction,
+		idempotency_key: Option<IdempotencyKey>,
 	) -> Result<Transaction, AppError> {
+		let mut idempotency_keys = self.idempotency_keys.write().await;
 		let now = Utc::now();
+		let request_hash = new_tx.fingerprint();
 
-		let tx = Transaction {
-			id: Uuid::new_v4(),
-			op_date: new_tx.op_date,
-			gas_station_id: new_tx.gas_station_id,
-			card_id: new_tx.card_id,
-			contract_id: new_tx.contract_id,
-			nomenclature_id: new_tx.nomenclature_id,
-			amount: new_tx.amount.map(|n| Decimal::from_f64(n).unwrap()),
-			stella_sum: new_tx.stella_sum.map(|n| Decimal::from_f64(n).unwrap()),
-			stella_nds_sum: new_tx.stella_nds_sum.map(|n| Decimal::from_f64(n).unwrap()),
-			refund: new_tx.refund,
-			buy_sum_plan: new_tx.buy_sum_plan.map(|n| Decimal::from_f64(n).unwrap()),
-			buy_nds_sum_plan: new_tx
-				.buy_nds_sum_plan
-				.map(|n| Decimal::from_f64(n).unwrap()),
-			buy_sum_fact: new_tx.buy_sum_fact.map(|n| Decimal::from_f64(n).unwrap()),
-			buy_nds_sum_fact: new_tx
-				.buy_nds_sum_fact
-				.map(|n| Decimal::from_f64(n).unwrap()),
-			sell_sum_plan: new_tx.sell_sum_plan.map(|n| Decimal::from_f64(n).unwrap()),
-			sell_nds_sum_plan: new_tx
-				.sell_nds_sum_plan
-				.map(|n| Decimal::from_f64(n).unwrap()),
-			sell_sum_fact: new_tx.sell_sum_fact.map(|n| Decimal::from_f64(n).unwrap()),
-			sell_nds_sum_fact: new_tx
-				.sell_nds_sum_fact
-				.map(|n| Decimal::from_f64(n).unwrap()),
-			implementation_id: new_tx.implementation_id,
-			user_id,
-			date_created: now,
-			date_updated: Some(now),
-			deleted: false,
-		};
+		idempotency_keys.retain(|_, record| record.date_created + self.idempotency_key_ttl > now);
+
+		if let Some(IdempotencyKey(key)) = &idempotency_key {
+			if let Some(record) = idempotency_keys.get(&(user_id, key.clone())) {
+				if record.request_hash != request_hash {
+					return Err(AppError::UnprocessableEntity(t(
+						Message::IdempotencyKeyReused(key),
+					)));
+				}
+
+				return Ok(record.response.clone());
+			}
+		}
 
 		let mut current_store = self.store.write().await;
+		check_periods(&*self.closed_periods.read().await, &[new_tx.op_date])?;
+		let implementations = self.implementations.read().await;
+		check_references(&implementations, &*self.directories.read().await, &new_tx)?;
+		check_implementations(&implementations, &[new_tx.implementation_id])?;
+		drop(implementations);
+		check_refund(&current_store, "", &new_tx, None)?;
+
+		let tx = new_transaction(user_id, new_tx, now);
 		current_store.push(tx.clone());
 
+		let mut history = self.history.write().await;
+		history.push(history_entry(HistoryOperation::Create, user_id, None, &tx));
+
+		if let Some(IdempotencyKey(key)) = idempotency_key {
+			idempotency_keys.insert(
+				(user_id, key),
+				IdempotencyRecord {
+					request_hash,
+					response: tx.clone(),
+					date_created: now,
+				},
+			);
+		}
+
 		return Ok(tx);
 	}
 
+	async fn create_transactions_batch(
+		&self,
+		UserId(user_id): UserId,
+		items: Vec<(usize, ApiTransaction)>,
+		mode: BatchMode,
+	) -> Result<BatchResult, AppError> {
+		let now = Utc::now();
+		let mut current_store = self.store.write().await;
+		let mut history = self.history.write().await;
+		let closed_periods = self.closed_periods.read().await;
+		let implementations = self.implementations.read().await;
+		let directories = self.directories.read().await;
+
+		// created items are visible to the refund checks of the later ones
+		let stored = current_store.len();
+		let mut created = Vec::with_capacity(items.len());
+		let mut errors = Vec::new();
+
+		for (index, new_tx) in items {
+			if let Err(err) = check_references(&implementations, &directories, &new_tx) {
+				errors.push(BatchItemError::new(index, err));
+				continue;
+			}
+
+			if let Err(err) = check_periods(&closed_periods, &[new_tx.op_date]) {
+				errors.push(BatchItemError::new(index, err));
+				continue;
+			}
+
+			if let Err(err) = check_implementations(&implementations, &[new_tx.implementation_id]) {
+				errors.push(BatchItemError::new(index, err));
+				continue;
+			}
+
+			if let Err(err) = check_refund(&current_store, &format!("/{index}"), &new_tx, None) {
+				errors.push(BatchItemError::new(index, err));
+				continue;
+			}
+
+			let tx = new_transaction(user_id, new_tx, now);
+			current_store.push(tx.clone());
+			created.push(BatchItem {
+				index,
+				transaction: tx,
+			});
+		}
+
+		if mode == BatchMode::Atomic && !errors.is_empty() {
+			current_store.truncate(stored);
+
+			return Ok(BatchResult {
+				created: Vec::new(),
+				errors,
+			});
+		}
+
+		for item in &created {
+			history.push(history_entry(
+				HistoryOperation::Create,
+				user_id,
+				None,
+				&item.transaction,
+			));
+		}
+
+		return Ok(BatchResult { created, errors });
+	}
+
+	async fn import_transactions(
+		&self,
+		UserId(user_id): UserId,
+		lines: Vec<(usize, ApiTransaction)>,
+		dry_run: bool,
+	) -> Result<Vec<ImportLineError>, AppError> {
+		let now = Utc::now();
+		let mut current_store = self.store.write().await;
+		let mut history = self.history.write().await;
+		let closed_periods = self.closed_periods.read().await;
+		let implementations = self.implementations.read().await;
+		let directories = self.directories.read().await;
+
+		// every line is checked even after a failure so the report is complete
+		let stored = current_store.len();
+		let mut errors = Vec::new();
+
+		for (line, new_tx) in lines {
+			let checked = check_references(&implementations, &directories, &new_tx)
+				.and_then(|_| check_periods(&closed_periods, &[new_tx.op_date]))
+				.and_then(|_| check_implementations(&implementations, &[new_tx.implementation_id]))
+				.and_then(|_| check_refund(&current_store, "", &new_tx, None));
+
+			match checked {
+				Ok(()) => current_store.push(new_transaction(user_id, new_tx, now)),
+				Err(err) => errors.push(ImportLineError::new(line, err)),
+			}
+		}
+
+		if dry_run || !errors.is_empty() {
+			current_store.truncate(stored);
+			return Ok(errors);
+		}
+
+		for tx in &current_store[stored..] {
+			history.push(history_entry(HistoryOperation::Create, user_id, None, tx));
+		}
+
+		return Ok(errors);
+	}
+
 	async fn update_transaction(
 		&self,
 		TxId(tx_id): TxId,
 		UserId(user_id): UserId,
 		tx: ApiTransaction,
+		if_match: Option<ETagCondition>,
 	) -> Result<Transaction, AppError> {
 		let mut current_store = self.store.write().await;
 
-		let existing_tx = current_store.iter_mut().find(|t| t.id == tx_id);
+		let existing_tx = current_store.iter().find(|t| t.id == tx_id && !t.deleted);
 
 		if existing_tx.is_none() {
-			return Err(AppError::NotFound(format!(
-				"Transaction with id {tx_id} not found"
-			)));
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
 		}
 
 		let existing_tx = existing_tx.unwrap();
-		existing_tx.op_date = tx.op_date;
-		existing_tx.gas_station_id = tx.gas_station_id;
-		existing_tx.card_id = tx.card_id;
-		existing_tx.contract_id = tx.contract_id;
-		existing_tx.nomenclature_id = tx.nomenclature_id;
-		existing_tx.amount = tx.amount.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.stella_sum = tx.stella_sum.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.stella_nds_sum = tx.stella_nds_sum.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.refund = tx.refund;
-		existing_tx.buy_sum_plan = tx.buy_sum_plan.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.buy_nds_sum_plan = tx.buy_nds_sum_plan.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.buy_sum_fact = tx.buy_sum_fact.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.buy_nds_sum_fact = tx.buy_nds_sum_fact.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.sell_sum_plan = tx.sell_sum_plan.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.sell_nds_sum_plan = tx.sell_nds_sum_plan.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.sell_sum_fact = tx.sell_sum_fact.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.sell_nds_sum_fact = tx.sell_nds_sum_fact.map(|n| Decimal::from_f64(n).unwrap());
-		existing_tx.implementation_id = tx.implementation_id;
-		existing_tx.user_id = user_id;
-		existing_tx.date_updated = Some(Utc::now());
+		ETagCondition::check(&if_match, tx_id, existing_tx.version)?;
+		check_periods(
+			&*self.closed_periods.read().await,
+			&[existing_tx.op_date, tx.op_date],
+		)?;
+		let implementations = self.implementations.read().await;
+		check_references(&implementations, &*self.directories.read().await, &tx)?;
+		check_implementations(
+			&implementations,
+			&[existing_tx.implementation_id, tx.implementation_id],
+		)?;
+		drop(implementations);
+		check_refund(&current_store, "", &tx, Some(tx_id))?;
+		check_refunded(&current_store, tx_id, &tx)?;
+
+		let existing_tx = current_store
+			.iter_mut()
+			.find(|t| t.id == tx_id && !t.deleted)
+			.unwrap();
+		let before = existing_tx.clone();
+		apply_changes(existing_tx, user_id, tx);
+
+		let mut history = self.history.write().await;
+		history.push(history_entry(
+			HistoryOperation::Update,
+			user_id,
+			Some(before),
+			existing_tx,
+		));
+
+		return Ok(existing_tx.clone());
+	}
+
+	async fn patch_transaction(
+		&self,
+		TxId(tx_id): TxId,
+		UserId(user_id): UserId,
+		patch: ApiTransactionPatch,
+		if_match: Option<ETagCondition>,
+	) -> Result<Transaction, AppError> {
+		let mut current_store = self.store.write().await;
+
+		let existing_tx = current_store.iter().find(|t| t.id == tx_id && !t.deleted);
+
+		if existing_tx.is_none() {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
+		}
+
+		let existing_tx = existing_tx.unwrap();
+		ETagCondition::check(&if_match, tx_id, existing_tx.version)?;
+		let tx = patch.apply(existing_tx);
+		tx.validate_at("")?;
+		check_periods(
+			&*self.closed_periods.read().await,
+			&[existing_tx.op_date, tx.op_date],
+		)?;
+		let implementations = self.implementations.read().await;
+		check_references(&implementations, &*self.directories.read().await, &tx)?;
+		check_implementations(
+			&implementations,
+			&[existing_tx.implementation_id, tx.implementation_id],
+		)?;
+		drop(implementations);
+		check_refund(&current_store, "", &tx, Some(tx_id))?;
+		check_refunded(&current_store, tx_id, &tx)?;
+
+		let existing_tx = current_store
+			.iter_mut()
+			.find(|t| t.id == tx_id && !t.deleted)
+			.unwrap();
+		let before = existing_tx.clone();
+		apply_changes(existing_tx, user_id, tx);
+
+		let mut history = self.history.write().await;
+		history.push(history_entry(
+			HistoryOperation::Update,
+			user_id,
+			Some(before),
+			existing_tx,
+		));
 
 		return Ok(existing_tx.clone());
 	}
@@ -132,24 +702,559 @@ impl Store for MockStore {
 		&self,
 		TxId(tx_id): TxId,
 		UserId(user_id): UserId,
+		if_match: Option<ETagCondition>,
 	) -> Result<(), AppError> {
 		let mut current_store = self.store.write().await;
+		let has_refunds = !refunds_of(&current_store, tx_id, None).is_empty();
 
-		let existing_tx = current_store.iter_mut().find(|t| t.id == tx_id);
+		let existing_tx = current_store
+			.iter_mut()
+			.find(|t| t.id == tx_id && !t.deleted);
 
 		if existing_tx.is_none() {
-			return Err(AppError::NotFound(format!(
-				"Transaction with id {tx_id} not found"
-			)));
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
 		}
 
 		let existing_tx = existing_tx.unwrap();
+		ETagCondition::check(&if_match, tx_id, existing_tx.version)?;
+		check_periods(&*self.closed_periods.read().await, &[existing_tx.op_date])?;
+		check_implementations(
+			&self.implementations.read().await,
+			&[existing_tx.implementation_id],
+		)?;
+
+		if has_refunds {
+			return Err(AppError::Conflict(t(Message::TransactionHasRefunds(tx_id))));
+		}
+
+		let before = existing_tx.clone();
 		existing_tx.deleted = true;
 		existing_tx.user_id = user_id;
+		existing_tx.deleted_by = Some(user_id);
+		existing_tx.date_updated = Some(Utc::now());
+		existing_tx.version += 1;
+
+		let mut history = self.history.write().await;
+		history.push(history_entry(
+			HistoryOperation::Delete,
+			user_id,
+			Some(before),
+			existing_tx,
+		));
+
+		return Ok(());
+	}
+
+	async fn restore_transaction(
+		&self,
+		TxId(tx_id): TxId,
+		UserId(user_id): UserId,
+	) -> Result<Transaction, AppError> {
+		let mut current_store = self.store.write().await;
+
+		let existing_tx = current_store.iter().find(|t| t.id == tx_id);
+
+		if existing_tx.is_none() {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
+		}
+
+		let existing_tx = existing_tx.unwrap();
+
+		if !existing_tx.deleted {
+			return Err(AppError::Conflict(t(Message::TransactionNotDeleted(tx_id))));
+		}
+
+		check_periods(&*self.closed_periods.read().await, &[existing_tx.op_date])?;
+		check_implementations(
+			&self.implementations.read().await,
+			&[existing_tx.implementation_id],
+		)?;
+
+		// the refund must still fit into what is left of its purchase
+		check_refund(
+			&current_store,
+			"",
+			&ApiTransaction::from(existing_tx),
+			Some(tx_id),
+		)?;
+
+		let existing_tx = current_store.iter_mut().find(|t| t.id == tx_id).unwrap();
+
+		let before = existing_tx.clone();
+		existing_tx.deleted = false;
+		existing_tx.user_id = user_id;
+		existing_tx.updated_by = Some(user_id);
+		existing_tx.deleted_by = None;
 		existing_tx.date_updated = Some(Utc::now());
+		existing_tx.version += 1;
+
+		let mut history = self.history.write().await;
+		history.push(history_entry(
+			HistoryOperation::Restore,
+			user_id,
+			Some(before),
+			existing_tx,
+		));
+
+		return Ok(existing_tx.clone());
+	}
+
+	async fn get_transaction_history(
+		&self,
+		TxId(tx_id): TxId,
+	) -> Result<Vec<TransactionHistoryEntry>, AppError> {
+		let current_store = self.store.read().await;
+
+		if !current_store.iter().any(|tx| tx.id == tx_id) {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
+		}
+
+		let history = self.history.read().await;
+
+		return Ok(history
+			.iter()
+			.filter(|entry| entry.transaction_id == tx_id)
+			.cloned()
+			.collect());
+	}
+
+	async fn get_transactions_stats(
+		&self,
+		filter: TransactionsFilter,
+		group_by: Vec<StatsDimension>,
+	) -> Result<TransactionsStats, AppError> {
+		let current_store = self.store.read().await;
+
+		let mut groups: BTreeMap<StatsKey, StatsRow> = BTreeMap::new();
+
+		if group_by.is_empty() {
+			groups.insert(StatsKey::default(), StatsRow::default());
+		}
+
+		for tx in current_store
+			.iter()
+			.filter(|tx| !tx.deleted && matches_filter(tx, &filter))
+		{
+			let key = StatsKey::of(tx, &group_by);
+
+			groups.entry(key).or_insert_with(|| key.to_row()).add(tx);
+		}
+
+		return Ok(TransactionsStats {
+			group_by,
+			rows: groups.into_values().collect(),
+		});
+	}
+
+	async fn get_variance_report(
+		&self,
+		filter: TransactionsFilter,
+		thresholds: VarianceThresholds,
+	) -> Result<VarianceReport, AppError> {
+		let current_store = self.store.read().await;
+
+		let mut deviating: Vec<&Transaction> = current_store
+			.iter()
+			.filter(|tx| !tx.deleted && matches_filter(tx, &filter))
+			.filter(|tx| TransactionVariance::deviates(tx, &thresholds))
+			.collect();
+
+		deviating.sort_by_key(|tx| (tx.op_date, tx.id));
+
+		let transactions = deviating.into_iter().map(TransactionVariance::of).collect();
+
+		return Ok(VarianceReport::new(thresholds, transactions));
+	}
+
+	async fn get_margin_report(&self, filter: TransactionsFilter) -> Result<MarginReport, AppError> {
+		let current_store = self.store.read().await;
+
+		let transactions: Vec<&Transaction> = current_store
+			.iter()
+			.filter(|tx| !tx.deleted && matches_filter(tx, &filter))
+			.collect();
+
+		let mut total = MarginTotals::default();
+		for tx in &transactions {
+			total.add(tx);
+		}
+
+		return Ok(MarginReport {
+			total,
+			by_contract: margin_totals(&transactions, |tx| tx.contract_id),
+			by_station: margin_totals(&transactions, |tx| Some(tx.gas_station_id)),
+			by_nomenclature: margin_totals(&transactions, |tx| Some(tx.nomenclature_id)),
+		});
+	}
+
+	async fn get_transaction_refunds(
+		&self,
+		TxId(tx_id): TxId,
+	) -> Result<TransactionRefunds, AppError> {
+		let current_store = self.store.read().await;
+
+		let original = current_store
+			.iter()
+			.find(|tx| tx.id == tx_id && !tx.deleted);
+
+		let Some(original) = original else {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
+		};
+
+		if original.refund {
+			return Err(AppError::Conflict(t(Message::TransactionIsRefund(tx_id))));
+		}
+
+		let mut refunds: Vec<Transaction> = refunds_of(&current_store, tx_id, None)
+			.into_iter()
+			.cloned()
+			.collect();
+		refunds.sort_by_key(|tx| (tx.op_date, tx.id));
+
+		return Ok(TransactionRefunds::new(original, refunds));
+	}
+
+	async fn get_directory_entries(
+		&self,
+		kind: DirectoryKind,
+		filter: DirectoryFilter,
+	) -> Result<Vec<DirectoryEntry>, AppError> {
+		let directories = self.directories.read().await;
+
+		let mut entries: Vec<DirectoryEntry> = directories
+			.get(&kind)
+			.into_iter()
+			.flatten()
+			.filter(|entry| filter.active.is_none_or(|active| entry.active == active))
+			.cloned()
+			.collect();
+		entries.sort_by(|a, b| a.code.cmp(&b.code));
+
+		return Ok(entries);
+	}
+
+	async fn get_directory_entries_by_ids(
+		&self,
+		kind: DirectoryKind,
+		ids: Vec<Uuid>,
+	) -> Result<Vec<DirectoryEntry>, AppError> {
+		let directories = self.directories.read().await;
+
+		return Ok(directories
+			.get(&kind)
+			.into_iter()
+			.flatten()
+			.filter(|entry| ids.contains(&entry.id))
+			.cloned()
+			.collect());
+	}
+
+	async fn get_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		DirectoryEntryId(id): DirectoryEntryId,
+	) -> Result<DirectoryEntry, AppError> {
+		let directories = self.directories.read().await;
+
+		let entry = directories
+			.get(&kind)
+			.and_then(|entries| entries.iter().find(|entry| entry.id == id));
+
+		return match entry {
+			None => Err(AppError::NotFound(t(Message::DirectoryEntryNotFound(id)))),
+			Some(entry) => Ok(entry.clone()),
+		};
+	}
+
+	async fn create_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		entry: ApiDirectoryEntry,
+	) -> Result<DirectoryEntry, AppError> {
+		let mut directories = self.directories.write().await;
+		let entries = directories.entry(kind).or_default();
+
+		if entries.iter().any(|existing| existing.code == entry.code) {
+			return Err(AppError::Conflict(t(Message::DuplicateRecord)));
+		}
+
+		let entry = directory_entry(Uuid::new_v4(), entry, Utc::now());
+		entries.push(entry.clone());
+
+		return Ok(entry);
+	}
+
+	async fn update_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		DirectoryEntryId(id): DirectoryEntryId,
+		entry: ApiDirectoryEntry,
+	) -> Result<DirectoryEntry, AppError> {
+		let mut directories = self.directories.write().await;
+		let entries = directories.entry(kind).or_default();
+
+		if entries
+			.iter()
+			.any(|existing| existing.code == entry.code && existing.id != id)
+		{
+			return Err(AppError::Conflict(t(Message::DuplicateRecord)));
+		}
+
+		let existing = entries.iter_mut().find(|existing| existing.id == id);
+
+		let Some(existing) = existing else {
+			return Err(AppError::NotFound(t(Message::DirectoryEntryNotFound(id))));
+		};
+
+		*existing = directory_entry(id, entry, existing.date_created);
+
+		return Ok(existing.clone());
+	}
+
+	async fn delete_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		DirectoryEntryId(id): DirectoryEntryId,
+	) -> Result<(), AppError> {
+		// transactions are always locked before implementations, and those before directories
+		let current_store = self.store.read().await;
+		let implementations = self.implementations.read().await;
+		let mut directories = self.directories.write().await;
+		let entries = directories.entry(kind).or_default();
+
+		let Some(position) = entries.iter().position(|entry| entry.id == id) else {
+			return Err(AppError::NotFound(t(Message::DirectoryEntryNotFound(id))));
+		};
+
+		let in_use = current_store
+			.iter()
+			.any(|tx| tx.reference(kind) == Some(id))
+			|| (kind == DirectoryKind::Contracts
+				&& implementations
+					.iter()
+					.any(|doc| doc.contract_id == Some(id)));
+
+		if in_use {
+			return Err(AppError::Conflict(t(Message::DirectoryEntryInUse(id))));
+		}
+
+		entries.remove(position);
+
+		return Ok(());
+	}
+
+	async fn get_implementations(
+		&self,
+		filter: ImplementationsFilter,
+	) -> Result<Vec<Implementation>, AppError> {
+		let current_store = self.store.read().await;
+		let implementations = self.implementations.read().await;
+
+		let mut list: Vec<Implementation> = implementations
+			.iter()
+			.filter(|doc| filter.status.is_none_or(|status| doc.status == status))
+			.filter(|doc| {
+				filter
+					.contract_id
+					.is_none_or(|id| doc.contract_id == Some(id))
+			})
+			.map(|doc| with_totals(&current_store, doc))
+			.collect();
+		list.sort_by(|a, b| (a.doc_date, &a.number).cmp(&(b.doc_date, &b.number)));
+
+		return Ok(list);
+	}
+
+	async fn get_implementation(
+		&self,
+		ImplementationId(id): ImplementationId,
+	) -> Result<Implementation, AppError> {
+		let current_store = self.store.read().await;
+		let implementations = self.implementations.read().await;
+
+		return match implementations.iter().find(|doc| doc.id == id) {
+			None => Err(AppError::NotFound(t(Message::ImplementationNotFound(id)))),
+			Some(doc) => Ok(with_totals(&current_store, doc)),
+		};
+	}
+
+	async fn get_implementation_transactions(
+		&self,
+		ImplementationId(id): ImplementationId,
+	) -> Result<Vec<Transaction>, AppError> {
+		let current_store = self.store.read().await;
+		let implementations = self.implementations.read().await;
+
+		if !implementations.iter().any(|doc| doc.id == id) {
+			return Err(AppError::NotFound(t(Message::ImplementationNotFound(id))));
+		}
+
+		let mut transactions: Vec<Transaction> = current_store
+			.iter()
+			.filter(|tx| !tx.deleted && tx.implementation_id == Some(id))
+			.cloned()
+			.collect();
+		transactions.sort_by_key(|tx| (tx.op_date, tx.id));
+
+		return Ok(transactions);
+	}
+
+	async fn create_implementation(
+		&self,
+		implementation: ApiImplementation,
+	) -> Result<Implementation, AppError> {
+		let mut implementations = self.implementations.write().await;
+
+		if !contract_exists(&*self.directories.read().await, implementation.contract_id) {
+			return Err(AppError::Conflict(t(Message::MissingReference)));
+		}
+
+		if implementations
+			.iter()
+			.any(|doc| doc.number == implementation.number)
+		{
+			return Err(AppError::Conflict(t(Message::DuplicateRecord)));
+		}
+
+		let now = Utc::now();
+		let doc = Implementation {
+			id: Uuid::new_v4(),
+			number: implementation.number,
+			doc_date: implementation.doc_date,
+			contract_id: Some(implementation.contract_id),
+			status: ImplementationStatus::Draft,
+			posted_at: None,
+			date_created: now,
+			date_updated: Some(now),
+			totals: ImplementationTotals::default(),
+		};
+		implementations.push(doc.clone());
+
+		return Ok(doc);
+	}
+
+	async fn update_implementation(
+		&self,
+		ImplementationId(id): ImplementationId,
+		implementation: ApiImplementation,
+	) -> Result<Implementation, AppError> {
+		let current_store = self.store.read().await;
+		let mut implementations = self.implementations.write().await;
+
+		if !contract_exists(&*self.directories.read().await, implementation.contract_id) {
+			return Err(AppError::Conflict(t(Message::MissingReference)));
+		}
+
+		if implementations
+			.iter()
+			.any(|doc| doc.number == implementation.number && doc.id != id)
+		{
+			return Err(AppError::Conflict(t(Message::DuplicateRecord)));
+		}
+
+		let Some(doc) = implementations.iter_mut().find(|doc| doc.id == id) else {
+			return Err(AppError::NotFound(t(Message::ImplementationNotFound(id))));
+		};
+
+		if doc.status == ImplementationStatus::Posted {
+			return Err(AppError::Conflict(t(Message::ImplementationPosted(id))));
+		}
+
+		doc.number = implementation.number;
+		doc.doc_date = implementation.doc_date;
+		doc.contract_id = Some(implementation.contract_id);
+		doc.date_updated = Some(Utc::now());
+
+		return Ok(with_totals(&current_store, doc));
+	}
+
+	async fn delete_implementation(
+		&self,
+		ImplementationId(id): ImplementationId,
+	) -> Result<(), AppError> {
+		let current_store = self.store.read().await;
+		let mut implementations = self.implementations.write().await;
+
+		let Some(position) = implementations.iter().position(|doc| doc.id == id) else {
+			return Err(AppError::NotFound(t(Message::ImplementationNotFound(id))));
+		};
+
+		if implementations[position].status == ImplementationStatus::Posted {
+			return Err(AppError::Conflict(t(Message::ImplementationPosted(id))));
+		}
+
+		if current_store
+			.iter()
+			.any(|tx| tx.implementation_id == Some(id))
+		{
+			return Err(AppError::Conflict(t(
+				Message::ImplementationHasTransactions(id),
+			)));
+		}
+
+		implementations.remove(position);
 
 		return Ok(());
 	}
 
+	async fn set_implementation_status(
+		&self,
+		ImplementationId(id): ImplementationId,
+		status: ImplementationStatus,
+	) -> Result<Implementation, AppError> {
+		let current_store = self.store.read().await;
+		let mut implementations = self.implementations.write().await;
+
+		let Some(doc) = implementations.iter_mut().find(|doc| doc.id == id) else {
+			return Err(AppError::NotFound(t(Message::ImplementationNotFound(id))));
+		};
+
+		if doc.status != status {
+			let now = Utc::now();
+
+			doc.status = status;
+			doc.posted_at = match status {
+				ImplementationStatus::Posted => Some(now),
+				ImplementationStatus::Draft => None,
+			};
+			doc.date_updated = Some(now);
+		}
+
+		return Ok(with_totals(&current_store, doc));
+	}
+
+	async fn get_closed_periods(&self) -> Result<Vec<ClosedPeriod>, AppError> {
+		let closed_periods = self.closed_periods.read().await;
+
+		return Ok(closed_periods.values().cloned().collect());
+	}
+
+	async fn close_period(
+		&self,
+		PeriodMonth(month): PeriodMonth,
+		AdminId(admin_id): AdminId,
+	) -> Result<ClosedPeriod, AppError> {
+		// transactions are changed under the write lock of the store
+		let _current_store = self.store.read().await;
+		let mut closed_periods = self.closed_periods.write().await;
+
+		let period = closed_periods.entry(month).or_insert_with(|| ClosedPeriod {
+			month,
+			closed_by: admin_id,
+			date_created: Utc::now(),
+		});
+
+		return Ok(period.clone());
+	}
+
+	async fn reopen_period(&self, PeriodMonth(month): PeriodMonth) -> Result<(), AppError> {
+		let mut closed_periods = self.closed_periods.write().await;
+
+		return match closed_periods.remove(&month) {
+			None => Err(AppError::NotFound(t(Message::PeriodNotClosed(month)))),
+			Some(_) => Ok(()),
+		};
+	}
+
 	async fn close(&self) {}
 }
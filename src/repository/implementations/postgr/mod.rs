@@ -1,49 +1,879 @@
 mod pool;
 
-use super::super::Store;
-use crate::dto::{TxId, UserId};
-use crate::repository::models::Transaction;
-use crate::{dto::ApiTransaction, system_models::AppError};
-use sqlx::{Error as EqlxError, PgPool};
+use super::super::{Store, TransactionBatches};
+use crate::config;
+use crate::dto::{
+	AdminId, ApiDirectoryEntry, ApiImplementation, BatchItemError, BatchMode, DeletedMode,
+	DirectoryEntryId, DirectoryFilter, DirectoryKind, ETagCondition, IdempotencyKey,
+	ImplementationId, ImplementationsFilter, ImportLineError, Page, PeriodMonth, StatsDimension,
+	TransactionsFilter, TxId, UserId, VarianceThresholds,
+};
+use crate::i18n::{t, Message};
+use crate::repository::models::{
+	BatchItem, BatchResult, ClosedPeriod, DirectoryEntry, HistoryOperation, Implementation,
+	ImplementationStatus, MarginReport, MarginTotals, StatsRow, Transaction,
+	TransactionHistoryEntry, TransactionRefunds, TransactionVariance, TransactionsPage,
+	TransactionsStats, VarianceReport,
+};
+use crate::{
+	dto::{ApiTransaction, ApiTransactionPatch},
+	system_models::AppError,
+};
+use ::std::collections::HashMap;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{
+	error::ErrorKind, types::Json, Acquire, Error as EqlxError, PgConnection, PgExecutor, PgPool,
+	Postgres, QueryBuilder,
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
 
+/// SQLSTATE codes which have no `ErrorKind` of their own
+const QUERY_CANCELED: &str = "57014";
+const ADMIN_SHUTDOWN: &str = "57P01";
+const CRASH_SHUTDOWN: &str = "57P02";
+const CANNOT_CONNECT_NOW: &str = "57P03";
+const CONNECTION_EXCEPTION_CLASS: &str = "08";
+const INTEGRITY_CONSTRAINT_VIOLATION_CLASS: &str = "23";
+
+/// The raw error stays in the server log, clients only get its category
 impl From<EqlxError> for AppError {
 	fn from(err: EqlxError) -> Self {
-		return AppError::SystemError(err.to_string());
+		let app_err = match &err {
+			EqlxError::Database(db_err) => match db_err.kind() {
+				ErrorKind::UniqueViolation => AppError::Conflict(t(Message::DuplicateRecord)),
+				ErrorKind::ForeignKeyViolation => AppError::Conflict(t(Message::MissingReference)),
+				ErrorKind::CheckViolation => {
+					AppError::UnprocessableEntity(t(Message::ConstraintViolated))
+				}
+				ErrorKind::NotNullViolation => {
+					AppError::UnprocessableEntity(t(Message::RequiredValueMissing))
+				}
+				_ => match db_err.code().as_deref() {
+					Some(QUERY_CANCELED) => AppError::Timeout(t(Message::DatabaseTimeout)),
+					Some(ADMIN_SHUTDOWN | CRASH_SHUTDOWN | CANNOT_CONNECT_NOW) => {
+						AppError::ServiceUnavailable(t(Message::DatabaseUnavailable))
+					}
+					Some(code) if code.starts_with(CONNECTION_EXCEPTION_CLASS) => {
+						AppError::ServiceUnavailable(t(Message::DatabaseUnavailable))
+					}
+					_ => AppError::SystemError(t(Message::InternalError)),
+				},
+			},
+			EqlxError::PoolTimedOut
+			| EqlxError::PoolClosed
+			| EqlxError::Io(_)
+			| EqlxError::Tls(_)
+			| EqlxError::WorkerCrashed => AppError::ServiceUnavailable(t(Message::DatabaseUnavailable)),
+			_ => AppError::SystemError(t(Message::InternalError)),
+		};
+
+		// the constraint is the only clue to an expected violation, so those are kept as well
+		let level = match app_err.code().status().is_server_error() {
+			true => "ERROR",
+			false => "WARN",
+		};
+		let constraint = match &err {
+			EqlxError::Database(db_err) => db_err.constraint(),
+			_ => None,
+		};
+		match constraint {
+			Some(constraint) => eprintln!("{level} Database error: {err} (constraint {constraint})"),
+			None => eprintln!("{level} Database error: {err}"),
+		}
+
+		return app_err;
 	}
 }
 
 #[derive(Clone)]
 pub struct PostgresStore {
 	pool: PgPool,
+	idempotency_key_ttl: Duration,
 }
 
 impl PostgresStore {
 	pub async fn new() -> Self {
 		let pool = pool::create_db_connection().await;
-		Self { pool }
+		Self {
+			pool,
+			idempotency_key_ttl: config::get_idempotency_key_ttl(),
+		}
+	}
+}
+
+fn deleted_condition(deleted: DeletedMode) -> &'static str {
+	return match deleted {
+		DeletedMode::Exclude => " AND NOT deleted",
+		DeletedMode::Include => "",
+		DeletedMode::Only => " AND deleted",
+	};
+}
+
+fn push_filter(
+	query: &mut QueryBuilder<'_, Postgres>,
+	filter: &TransactionsFilter,
+	deleted: DeletedMode,
+) {
+	query.push(" WHERE true").push(deleted_condition(deleted));
+
+	if let Some(id) = filter.gas_station_id {
+		query.push(" AND gas_station_id = ").push_bind(id);
+	}
+	if let Some(id) = filter.card_id {
+		query.push(" AND card_id = ").push_bind(id);
+	}
+	if let Some(id) = filter.contract_id {
+		query.push(" AND contract_id = ").push_bind(id);
+	}
+	if let Some(id) = filter.nomenclature_id {
+		query.push(" AND nomenclature_id = ").push_bind(id);
+	}
+	if let Some(id) = filter.implementation_id {
+		query.push(" AND implementation_id = ").push_bind(id);
+	}
+	if let Some(id) = filter.user_id {
+		query.push(" AND user_id = ").push_bind(id);
+	}
+	if let Some(id) = filter.created_by {
+		query.push(" AND created_by = ").push_bind(id);
+	}
+	if let Some(refund) = filter.refund {
+		query.push(" AND refund = ").push_bind(refund);
+	}
+	if let Some(from) = filter.op_date_from {
+		query.push(" AND op_date >= ").push_bind(from);
+	}
+	if let Some(to) = filter.op_date_to {
+		query.push(" AND op_date < ").push_bind(to);
+	}
+}
+
+/// Key columns of the statistics with the values they take when not grouped by
+const STATS_KEYS: [(&str, &str); 5] = [
+	("gas_station_id", "NULL::uuid"),
+	("card_id", "NULL::uuid"),
+	("contract_id", "NULL::uuid"),
+	("nomenclature_id", "NULL::uuid"),
+	("period", "NULL::date"),
+];
+
+const STATS_SUM_COLUMNS: [&str; 11] = [
+	"amount",
+	"stella_sum",
+	"stella_nds_sum",
+	"buy_sum_plan",
+	"buy_nds_sum_plan",
+	"buy_sum_fact",
+	"buy_nds_sum_fact",
+	"sell_sum_plan",
+	"sell_nds_sum_plan",
+	"sell_sum_fact",
+	"sell_nds_sum_fact",
+];
+
+fn stats_key_column(dimension: StatsDimension) -> &'static str {
+	return match dimension {
+		StatsDimension::GasStationId => "gas_station_id",
+		StatsDimension::CardId => "card_id",
+		StatsDimension::ContractId => "contract_id",
+		StatsDimension::NomenclatureId => "nomenclature_id",
+		StatsDimension::Day | StatsDimension::Week | StatsDimension::Month => "period",
+	};
+}
+
+fn stats_key_expression(dimension: StatsDimension) -> &'static str {
+	return match dimension {
+		StatsDimension::GasStationId => "gas_station_id",
+		StatsDimension::CardId => "card_id",
+		StatsDimension::ContractId => "contract_id",
+		StatsDimension::NomenclatureId => "nomenclature_id",
+		StatsDimension::Day => "date_trunc('day', op_date AT TIME ZONE 'UTC')::date",
+		StatsDimension::Week => "date_trunc('week', op_date AT TIME ZONE 'UTC')::date",
+		StatsDimension::Month => "date_trunc('month', op_date AT TIME ZONE 'UTC')::date",
+	};
+}
+
+const MARGIN_TOTAL_KEY: &str = "NULL::uuid";
+
+/// Sections of the margin report in the order of `MarginReport` fields
+const MARGIN_KEYS: [&str; 4] = [
+	MARGIN_TOTAL_KEY,
+	"contract_id",
+	"gas_station_id",
+	"nomenclature_id",
+];
+
+/// Rows fetched from the export cursor at once
+const EXPORT_BATCH_SIZE: usize = 1000;
+/// Batches fetched before the client has taken the previous ones
+const EXPORT_READ_AHEAD: usize = 2;
+
+const IMPLEMENTATION_SUM_COLUMNS: [&str; 7] = [
+	"amount",
+	"stella_sum",
+	"stella_nds_sum",
+	"sell_sum_plan",
+	"sell_nds_sum_plan",
+	"sell_sum_fact",
+	"sell_nds_sum_fact",
+];
+
+/// Documents along with the totals of their active transactions
+fn implementation_select() -> String {
+	let sums: Vec<String> =
+		IMPLEMENTATION_SUM_COLUMNS
+			.iter()
+			.map(|column| {
+				format!("COALESCE(SUM(CASE WHEN refund THEN -ABS({column}) ELSE {column} END), 0) AS {column}")
+			})
+			.collect();
+
+	return format!(
+		"SELECT implementations.*, totals.*
+		FROM implementations
+		LEFT JOIN LATERAL (
+			SELECT COUNT(*) AS count,
+				COUNT(*) FILTER (WHERE refund) AS refund_count,
+				{}
+			FROM transactions
+			WHERE implementation_id = implementations.id AND NOT deleted
+		) AS totals ON true",
+		sums.join(", ")
+	);
+}
+
+async fn fetch_implementation<'e, E: PgExecutor<'e>>(
+	executor: E,
+	id: Uuid,
+) -> Result<Implementation, AppError> {
+	let implementation = sqlx::query_as::<_, Implementation>(&format!(
+		"{} WHERE implementations.id = $1;",
+		implementation_select()
+	))
+	.bind(id)
+	.fetch_optional(executor)
+	.await?;
+
+	return match implementation {
+		None => Err(AppError::NotFound(t(Message::ImplementationNotFound(id)))),
+		Some(implementation) => Ok(implementation),
+	};
+}
+
+/// Locks a document for the rest of the DB transaction
+async fn lock_implementation(
+	conn: &mut PgConnection,
+	id: Uuid,
+) -> Result<ImplementationStatus, AppError> {
+	let status = sqlx::query_scalar::<_, ImplementationStatus>(
+		"SELECT status FROM implementations WHERE id = $1 FOR UPDATE;",
+	)
+	.bind(id)
+	.fetch_optional(conn)
+	.await?;
+
+	return match status {
+		None => Err(AppError::NotFound(t(Message::ImplementationNotFound(id)))),
+		Some(status) => Ok(status),
+	};
+}
+
+/// Transactions of closed months can be neither created, changed nor deleted.
+/// `FOR SHARE` takes a lock on the table, so a month can't be closed until the DB transaction ends
+async fn check_periods(
+	conn: &mut PgConnection,
+	op_dates: &[DateTime<Utc>],
+) -> Result<(), AppError> {
+	let months: Vec<NaiveDate> = op_dates
+		.iter()
+		.map(|op_date| ClosedPeriod::month_of(*op_date))
+		.collect();
+
+	let closed = sqlx::query_scalar::<_, NaiveDate>(
+		"SELECT month FROM closed_periods WHERE month = ANY($1) ORDER BY month LIMIT 1 FOR SHARE;",
+	)
+	.bind(months)
+	.fetch_optional(conn)
+	.await?;
+
+	return match closed {
+		Some(month) => Err(AppError::PeriodClosed(t(Message::PeriodClosed(month)))),
+		None => Ok(()),
+	};
+}
+
+/// Transactions of a posted document can be neither attached, detached nor edited.
+/// The documents stay shared until the DB transaction ends, so none of them gets posted meanwhile
+async fn check_implementations(
+	conn: &mut PgConnection,
+	ids: &[Option<Uuid>],
+) -> Result<(), AppError> {
+	let ids: Vec<Uuid> = ids.iter().flatten().copied().collect();
+
+	if ids.is_empty() {
+		return Ok(());
 	}
+
+	let statuses = sqlx::query_as::<_, (Uuid, ImplementationStatus)>(
+		"SELECT id, status FROM implementations WHERE id = ANY($1) ORDER BY id FOR SHARE;",
+	)
+	.bind(ids)
+	.fetch_all(conn)
+	.await?;
+
+	let posted = statuses
+		.into_iter()
+		.find(|(_, status)| *status == ImplementationStatus::Posted);
+
+	return match posted {
+		Some((id, _)) => Err(AppError::Conflict(t(Message::ImplementationPosted(id)))),
+		None => Ok(()),
+	};
+}
+
+/// Directories without a unit of measure still return the column to share `DirectoryEntry`
+fn directory_columns(kind: DirectoryKind) -> &'static str {
+	return match kind.has_unit() {
+		true => "id, name, code, active, unit, date_created, date_updated",
+		false => "id, name, code, active, NULL::varchar AS unit, date_created, date_updated",
+	};
+}
+
+async fn record_history(
+	conn: &mut PgConnection,
+	operation: HistoryOperation,
+	user_id: Uuid,
+	before: Option<&Transaction>,
+	after: &Transaction,
+) -> Result<(), EqlxError> {
+	sqlx::query(
+		"INSERT INTO transaction_history (transaction_id, operation, user_id, before, after)
+		VALUES ($1, $2, $3, $4, $5);",
+	)
+	.bind(after.id)
+	.bind(operation)
+	.bind(user_id)
+	.bind(before.map(Json))
+	.bind(Json(after))
+	.execute(conn)
+	.await?;
+
+	return Ok(());
+}
+
+async fn record_creations(
+	conn: &mut PgConnection,
+	user_id: Uuid,
+	created: &[Transaction],
+) -> Result<(), EqlxError> {
+	sqlx::query(
+		"INSERT INTO transaction_history (transaction_id, operation, user_id, after)
+		SELECT rows.id, 'create', $1, rows.after
+		FROM UNNEST($2::uuid[], $3::jsonb[]) AS rows (id, after);",
+	)
+	.bind(user_id)
+	.bind(created.iter().map(|tx| tx.id).collect::<Vec<_>>())
+	.bind(created.iter().map(Json).collect::<Vec<_>>())
+	.execute(conn)
+	.await?;
+
+	return Ok(());
+}
+
+/// Locks an active row for the rest of the DB transaction and checks `If-Match` against it
+async fn lock_row(
+	conn: &mut PgConnection,
+	tx_id: Uuid,
+	if_match: &Option<ETagCondition>,
+) -> Result<Transaction, AppError> {
+	let existing_tx = sqlx::query_as::<_, Transaction>(
+		"SELECT * FROM transactions WHERE id = $1 AND NOT deleted FOR UPDATE;",
+	)
+	.bind(tx_id)
+	.fetch_optional(conn)
+	.await?;
+
+	return match existing_tx {
+		None => Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id)))),
+		Some(tx) => {
+			ETagCondition::check(if_match, tx_id, tx.version)?;
+			Ok(tx)
+		}
+	};
+}
+
+/// Active refunds of a purchase, except the one being edited
+async fn refunds_of(
+	conn: &mut PgConnection,
+	original_id: Uuid,
+	except_id: Option<Uuid>,
+) -> Result<Vec<Transaction>, EqlxError> {
+	return sqlx::query_as::<_, Transaction>(
+		"SELECT * FROM transactions
+		WHERE original_transaction_id = $1 AND NOT deleted AND id IS DISTINCT FROM $2::uuid
+		ORDER BY op_date ASC, id ASC;",
+	)
+	.bind(original_id)
+	.bind(except_id)
+	.fetch_all(conn)
+	.await;
+}
+
+/// Locks the purchase a refund reverses, so that concurrent refunds of it are checked
+/// one after another. `batch` holds refunds about to be inserted along with this one
+async fn check_refund(
+	conn: &mut PgConnection,
+	pointer: &str,
+	tx: &ApiTransaction,
+	own_id: Option<Uuid>,
+	batch: &[(Uuid, ApiTransaction)],
+) -> Result<(), AppError> {
+	let Some(original_id) = tx.original_transaction_id else {
+		return Ok(());
+	};
+
+	let original = sqlx::query_as::<_, Transaction>(
+		"SELECT * FROM transactions WHERE id = $1 AND NOT deleted FOR UPDATE;",
+	)
+	.bind(original_id)
+	.fetch_optional(&mut *conn)
+	.await?;
+
+	let mut refunds: Vec<ApiTransaction> = refunds_of(conn, original_id, own_id)
+		.await?
+		.iter()
+		.map(ApiTransaction::from)
+		.collect();
+	refunds.extend(
+		batch
+			.iter()
+			.filter(|(_, pending)| pending.original_transaction_id == Some(original_id))
+			.map(|(_, pending)| pending.clone()),
+	);
+
+	return tx.validate_refund_at(pointer, own_id, original.as_ref(), &refunds);
+}
+
+async fn check_refunded(
+	conn: &mut PgConnection,
+	tx_id: Uuid,
+	tx: &ApiTransaction,
+) -> Result<(), AppError> {
+	let refunds: Vec<ApiTransaction> = refunds_of(conn, tx_id, None)
+		.await?
+		.iter()
+		.map(ApiTransaction::from)
+		.collect();
+
+	return tx.validate_refunded_at("", &refunds);
+}
+
+async fn insert_row<'e, E: PgExecutor<'e>>(
+	executor: E,
+	user_id: Uuid,
+	new_tx: ApiTransaction,
+) -> Result<Transaction, EqlxError> {
+	return sqlx::query_as::<_, Transaction>(
+		"INSERT INTO transactions (
+			op_date,
+			gas_station_id,
+			card_id,
+			contract_id,
+			nomenclature_id,
+			amount,
+			stella_sum,
+			stella_nds_sum,
+			refund,
+			buy_sum_plan,
+			buy_nds_sum_plan,
+			buy_sum_fact,
+			buy_nds_sum_fact,
+			sell_sum_plan,
+			sell_nds_sum_plan,
+			sell_sum_fact,
+			sell_nds_sum_fact,
+			implementation_id,
+			original_transaction_id,
+			user_id,
+			created_by
+		) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
+			$11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $20)
+		RETURNING *;",
+	)
+	.bind(new_tx.op_date)
+	.bind(new_tx.gas_station_id)
+	.bind(new_tx.card_id)
+	.bind(new_tx.contract_id)
+	.bind(new_tx.nomenclature_id)
+	.bind(new_tx.amount)
+	.bind(new_tx.stella_sum)
+	.bind(new_tx.stella_nds_sum)
+	.bind(new_tx.refund)
+	.bind(new_tx.buy_sum_plan)
+	.bind(new_tx.buy_nds_sum_plan)
+	.bind(new_tx.buy_sum_fact)
+	.bind(new_tx.buy_nds_sum_fact)
+	.bind(new_tx.sell_sum_plan)
+	.bind(new_tx.sell_nds_sum_plan)
+	.bind(new_tx.sell_sum_fact)
+	.bind(new_tx.sell_nds_sum_fact)
+	.bind(new_tx.implementation_id)
+	.bind(new_tx.original_transaction_id)
+	.bind(user_id)
+	.fetch_one(executor)
+	.await;
+}
+
+/// Inserts all rows with a single statement, ids are generated beforehand to match the
+/// returned rows with the request items
+async fn insert_rows<'e, E: PgExecutor<'e>>(
+	executor: E,
+	user_id: Uuid,
+	rows: &[(Uuid, ApiTransaction)],
+) -> Result<Vec<Transaction>, EqlxError> {
+	return sqlx::query_as::<_, Transaction>(
+		"INSERT INTO transactions (
+			id,
+			op_date,
+			gas_station_id,
+			card_id,
+			contract_id,
+			nomenclature_id,
+			amount,
+			stella_sum,
+			stella_nds_sum,
+			refund,
+			buy_sum_plan,
+			buy_nds_sum_plan,
+			buy_sum_fact,
+			buy_nds_sum_fact,
+			sell_sum_plan,
+			sell_nds_sum_plan,
+			sell_sum_fact,
+			sell_nds_sum_fact,
+			implementation_id,
+			original_transaction_id,
+			user_id,
+			created_by
+		)
+		SELECT rows.*, $21, $21 FROM UNNEST(
+			$1::uuid[], $2::timestamptz[], $3::uuid[], $4::uuid[], $5::uuid[],
+			$6::uuid[], $7::numeric[], $8::numeric[], $9::numeric[], $10::boolean[],
+			$11::numeric[], $12::numeric[], $13::numeric[], $14::numeric[], $15::numeric[],
+			$16::numeric[], $17::numeric[], $18::numeric[], $19::uuid[], $20::uuid[]
+		) AS rows
+		RETURNING *;",
+	)
+	.bind(rows.iter().map(|(id, _)| *id).collect::<Vec<_>>())
+	.bind(rows.iter().map(|(_, tx)| tx.op_date).collect::<Vec<_>>())
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.gas_station_id)
+			.collect::<Vec<_>>(),
+	)
+	.bind(rows.iter().map(|(_, tx)| tx.card_id).collect::<Vec<_>>())
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.contract_id)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.nomenclature_id)
+			.collect::<Vec<_>>(),
+	)
+	.bind(rows.iter().map(|(_, tx)| tx.amount).collect::<Vec<_>>())
+	.bind(rows.iter().map(|(_, tx)| tx.stella_sum).collect::<Vec<_>>())
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.stella_nds_sum)
+			.collect::<Vec<_>>(),
+	)
+	.bind(rows.iter().map(|(_, tx)| tx.refund).collect::<Vec<_>>())
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.buy_sum_plan)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.buy_nds_sum_plan)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.buy_sum_fact)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.buy_nds_sum_fact)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.sell_sum_plan)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.sell_nds_sum_plan)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.sell_sum_fact)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.sell_nds_sum_fact)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.implementation_id)
+			.collect::<Vec<_>>(),
+	)
+	.bind(
+		rows
+			.iter()
+			.map(|(_, tx)| tx.original_transaction_id)
+			.collect::<Vec<_>>(),
+	)
+	.bind(user_id)
+	.fetch_all(executor)
+	.await;
+}
+
+/// Checks a new row against the stored data, `pending` holds the rows about to be inserted with it
+async fn check_creation(
+	conn: &mut PgConnection,
+	pointer: &str,
+	tx: &ApiTransaction,
+	pending: &[(Uuid, ApiTransaction)],
+) -> Result<(), AppError> {
+	check_periods(conn, &[tx.op_date]).await?;
+	check_implementations(conn, &[tx.implementation_id]).await?;
+
+	return check_refund(conn, pointer, tx, None, pending).await;
+}
+
+/// Only a broken constraint can be blamed on a row, a timeout or a shutdown fails the request
+fn is_integrity_violation(err: &EqlxError) -> bool {
+	return match err {
+		EqlxError::Database(db_err) => db_err
+			.code()
+			.is_some_and(|code| code.starts_with(INTEGRITY_CONSTRAINT_VIOLATION_CLASS)),
+		_ => false,
+	};
+}
+
+/// Inserts the rows one by one to find out which of them break a constraint
+async fn insert_rows_one_by_one(
+	conn: &mut PgConnection,
+	user_id: Uuid,
+	rows: Vec<(Uuid, ApiTransaction)>,
+) -> Result<(Vec<Transaction>, Vec<(Uuid, AppError)>), AppError> {
+	let mut inserted = Vec::with_capacity(rows.len());
+	let mut failed = Vec::new();
+
+	for (id, tx) in rows {
+		let mut savepoint = conn.begin().await?;
+
+		match insert_rows(&mut *savepoint, user_id, &[(id, tx)]).await {
+			Ok(mut row) => {
+				savepoint.commit().await?;
+				inserted.append(&mut row);
+			}
+			Err(err) if is_integrity_violation(&err) => {
+				savepoint.rollback().await?;
+				failed.push((id, AppError::from(err)));
+			}
+			Err(err) => return Err(err.into()),
+		}
+	}
+
+	return Ok((inserted, failed));
+}
+
+async fn update_row<'e, E: PgExecutor<'e>>(
+	executor: E,
+	tx_id: Uuid,
+	user_id: Uuid,
+	tx: ApiTransaction,
+) -> Result<Option<Transaction>, EqlxError> {
+	return sqlx::query_as::<_, Transaction>(
+		"UPDATE transactions
+		SET op_date = $1,
+			gas_station_id = $2,
+			card_id = $3,
+			contract_id = $4,
+			nomenclature_id = $5,
+			amount = $6,
+			stella_sum = $7,
+			stella_nds_sum = $8,
+			refund = $9,
+			buy_sum_plan = $10,
+			buy_nds_sum_plan = $11,
+			buy_sum_fact = $12,
+			buy_nds_sum_fact = $13,
+			sell_sum_plan = $14,
+			sell_nds_sum_plan = $15,
+			sell_sum_fact = $16,
+			sell_nds_sum_fact = $17,
+			implementation_id = $18,
+			original_transaction_id = $19,
+			user_id = $20,
+			updated_by = $20
+		WHERE id = $21 AND NOT deleted
+		RETURNING *;",
+	)
+	.bind(tx.op_date)
+	.bind(tx.gas_station_id)
+	.bind(tx.card_id)
+	.bind(tx.contract_id)
+	.bind(tx.nomenclature_id)
+	.bind(tx.amount)
+	.bind(tx.stella_sum)
+	.bind(tx.stella_nds_sum)
+	.bind(tx.refund)
+	.bind(tx.buy_sum_plan)
+	.bind(tx.buy_nds_sum_plan)
+	.bind(tx.buy_sum_fact)
+	.bind(tx.buy_nds_sum_fact)
+	.bind(tx.sell_sum_plan)
+	.bind(tx.sell_nds_sum_plan)
+	.bind(tx.sell_sum_fact)
+	.bind(tx.sell_nds_sum_fact)
+	.bind(tx.implementation_id)
+	.bind(tx.original_transaction_id)
+	.bind(user_id)
+	.bind(tx_id)
+	.fetch_optional(executor)
+	.await;
 }
 
 impl Store for PostgresStore {
-	async fn get_transactions_list(&self) -> Result<Vec<Transaction>, AppError> {
-		let txs =
-			sqlx::query_as::<_, Transaction>("SELECT * FROM transactions ORDER BY date_created ASC;")
-				.fetch_all(&self.pool)
+	async fn get_transactions_list(
+		&self,
+		filter: TransactionsFilter,
+		deleted: DeletedMode,
+		page: Page,
+	) -> Result<TransactionsPage, AppError> {
+		let total = if page.with_total {
+			let mut query = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM transactions");
+			push_filter(&mut query, &filter, deleted);
+
+			let (count,) = query
+				.build_query_as::<(i64,)>()
+				.fetch_one(&self.pool)
 				.await?;
 
-		return Ok(txs);
+			Some(count)
+		} else {
+			None
+		};
+
+		let mut query = QueryBuilder::<Postgres>::new("SELECT * FROM transactions");
+		push_filter(&mut query, &filter, deleted);
+
+		if let Some(after) = page.after {
+			query
+				.push(" AND (date_created, id) > (")
+				.push_bind(after.date_created)
+				.push(", ")
+				.push_bind(after.id)
+				.push(")");
+		}
+
+		query
+			.push(" ORDER BY date_created ASC, id ASC LIMIT ")
+			.push_bind(i64::from(page.limit) + 1)
+			.push(" OFFSET ")
+			.push_bind(i64::from(page.offset));
+
+		let rows = query
+			.build_query_as::<Transaction>()
+			.fetch_all(&self.pool)
+			.await?;
+
+		return Ok(TransactionsPage::from_rows(rows, page.limit, total));
 	}
 
-	async fn get_transaction(&self, TxId(tx_id): TxId) -> Result<Transaction, AppError> {
-		let mut rows = sqlx::query_as::<_, Transaction>("SELECT * FROM transactions WHERE id = $1;")
+	async fn export_transactions(
+		&self,
+		filter: TransactionsFilter,
+		deleted: DeletedMode,
+	) -> Result<TransactionBatches, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		let mut query = QueryBuilder::<Postgres>::new(
+			"DECLARE export NO SCROLL CURSOR FOR SELECT * FROM transactions",
+		);
+		push_filter(&mut query, &filter, deleted);
+		query.push(" ORDER BY date_created ASC, id ASC");
+		query.build().execute(&mut *db_tx).await?;
+
+		let (sender, receiver) = mpsc::channel(EXPORT_READ_AHEAD);
+
+		// The cursor lives as long as the transaction, which is rolled back once the reader is gone
+		tokio::spawn(async move {
+			let fetch = format!("FETCH {EXPORT_BATCH_SIZE} FROM export");
+
+			loop {
+				let batch = match sqlx::query_as::<_, Transaction>(&fetch)
+					.fetch_all(&mut *db_tx)
+					.await
+				{
+					Ok(rows) if rows.is_empty() => break,
+					Ok(rows) => Ok(rows),
+					Err(err) => Err(AppError::from(err)),
+				};
+				let last = batch
+					.as_ref()
+					.map_or(true, |rows| rows.len() < EXPORT_BATCH_SIZE);
+
+				if sender.send(batch).await.is_err() || last {
+					break;
+				}
+			}
+		});
+
+		return Ok(receiver);
+	}
+
+	async fn get_transaction(
+		&self,
+		TxId(tx_id): TxId,
+		deleted: DeletedMode,
+	) -> Result<Transaction, AppError> {
+		let sql = format!(
+			"SELECT * FROM transactions WHERE id = $1{};",
+			deleted_condition(deleted)
+		);
+
+		let mut rows = sqlx::query_as::<_, Transaction>(&sql)
 			.bind(tx_id)
 			.fetch_all(&self.pool)
 			.await?;
 
 		return match rows.pop() {
-			None => Err(AppError::NotFound(format!(
-				"Transaction with id {tx_id} not found"
-			))),
+			None => Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id)))),
 			Some(tx) => Ok(tx),
 		};
 	}
@@ -52,143 +882,1013 @@ impl Store for PostgresStore {
 		&self,
 		UserId(user_id): UserId,
 		new_tx: ApiTransaction,
+		idempotency_key: Option<IdempotencyKey>,
 	) -> Result<Transaction, AppError> {
-		let inserted_tx = sqlx::query_as::<_, Transaction>(
-			"INSERT INTO transactions (
-				op_date,
-				gas_station_id,
-				card_id,
-				contract_id,
-				nomenclature_id,
-				amount,
-				stella_sum,
-				stella_nds_sum,
-				refund,
-				buy_sum_plan,
-				buy_nds_sum_plan,
-				buy_sum_fact,
-				buy_nds_sum_fact,
-				sell_sum_plan,
-				sell_nds_sum_plan,
-				sell_sum_fact,
-				sell_nds_sum_fact,
-				implementation_id,
-				user_id
-			) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10,
-				$11, $12, $13, $14, $15, $16, $17, $18, $19)
-			RETURNING *;",
+		let mut db_tx = self.pool.begin().await?;
+
+		let Some(IdempotencyKey(key)) = idempotency_key else {
+			check_periods(&mut db_tx, &[new_tx.op_date]).await?;
+			check_implementations(&mut db_tx, &[new_tx.implementation_id]).await?;
+			check_refund(&mut db_tx, "", &new_tx, None, &[]).await?;
+			let inserted_tx = insert_row(&mut *db_tx, user_id, new_tx).await?;
+			record_history(
+				&mut db_tx,
+				HistoryOperation::Create,
+				user_id,
+				None,
+				&inserted_tx,
+			)
+			.await?;
+			db_tx.commit().await?;
+
+			return Ok(inserted_tx);
+		};
+
+		let request_hash = new_tx.fingerprint();
+
+		sqlx::query("DELETE FROM idempotency_keys WHERE date_created < $1;")
+			.bind(Utc::now() - self.idempotency_key_ttl)
+			.execute(&mut *db_tx)
+			.await?;
+
+		// a concurrent request with the same key blocks here until its DB transaction ends
+		let claimed = sqlx::query(
+			"INSERT INTO idempotency_keys (user_id, key, request_hash)
+			VALUES ($1, $2, $3)
+			ON CONFLICT DO NOTHING;",
 		)
-		.bind(new_tx.op_date)
-		.bind(new_tx.gas_station_id)
-		.bind(new_tx.card_id)
-		.bind(new_tx.contract_id)
-		.bind(new_tx.nomenclature_id)
-		.bind(new_tx.amount)
-		.bind(new_tx.stella_sum)
-		.bind(new_tx.stella_nds_sum)
-		.bind(new_tx.refund)
-		.bind(new_tx.buy_sum_plan)
-		.bind(new_tx.buy_nds_sum_plan)
-		.bind(new_tx.buy_sum_fact)
-		.bind(new_tx.buy_nds_sum_fact)
-		.bind(new_tx.sell_sum_plan)
-		.bind(new_tx.sell_nds_sum_plan)
-		.bind(new_tx.sell_sum_fact)
-		.bind(new_tx.sell_nds_sum_fact)
-		.bind(new_tx.implementation_id)
 		.bind(user_id)
-		.fetch_one(&self.pool)
+		.bind(&key)
+		.bind(&request_hash)
+		.execute(&mut *db_tx)
+		.await?
+		.rows_affected()
+			== 1;
+
+		if !claimed {
+			let (stored_hash, response) = sqlx::query_as::<_, (String, Option<Json<Transaction>>)>(
+				"SELECT request_hash, response FROM idempotency_keys WHERE user_id = $1 AND key = $2;",
+			)
+			.bind(user_id)
+			.bind(&key)
+			.fetch_one(&mut *db_tx)
+			.await?;
+
+			if stored_hash != request_hash {
+				return Err(AppError::UnprocessableEntity(t(
+					Message::IdempotencyKeyReused(&key),
+				)));
+			}
+
+			return match response {
+				None => Err(AppError::Conflict(t(Message::IdempotencyKeyInProgress(
+					&key,
+				)))),
+				Some(Json(tx)) => Ok(tx),
+			};
+		}
+
+		check_periods(&mut db_tx, &[new_tx.op_date]).await?;
+		check_implementations(&mut db_tx, &[new_tx.implementation_id]).await?;
+		check_refund(&mut db_tx, "", &new_tx, None, &[]).await?;
+		let inserted_tx = insert_row(&mut *db_tx, user_id, new_tx).await?;
+		record_history(
+			&mut db_tx,
+			HistoryOperation::Create,
+			user_id,
+			None,
+			&inserted_tx,
+		)
 		.await?;
 
+		sqlx::query(
+			"UPDATE idempotency_keys
+			SET transaction_id = $1,
+				response = $2
+			WHERE user_id = $3 AND key = $4;",
+		)
+		.bind(inserted_tx.id)
+		.bind(Json(&inserted_tx))
+		.bind(user_id)
+		.bind(&key)
+		.execute(&mut *db_tx)
+		.await?;
+
+		db_tx.commit().await?;
+
 		return Ok(inserted_tx);
 	}
 
+	async fn create_transactions_batch(
+		&self,
+		UserId(user_id): UserId,
+		items: Vec<(usize, ApiTransaction)>,
+		mode: BatchMode,
+	) -> Result<BatchResult, AppError> {
+		let mut indexes = HashMap::with_capacity(items.len());
+		let mut rows = Vec::with_capacity(items.len());
+		let mut errors = Vec::new();
+
+		let mut db_tx = self.pool.begin().await?;
+
+		for (index, tx) in items {
+			match check_creation(&mut db_tx, &format!("/{index}"), &tx, &rows).await {
+				Ok(()) => {
+					let id = Uuid::new_v4();
+					indexes.insert(id, index);
+					rows.push((id, tx));
+				}
+				Err(
+					err @ (AppError::Validation(_) | AppError::Conflict(_) | AppError::PeriodClosed(_)),
+				) => errors.push(BatchItemError::new(index, err)),
+				Err(err) => return Err(err),
+			}
+		}
+
+		if mode == BatchMode::Atomic && !errors.is_empty() {
+			return Ok(BatchResult {
+				created: Vec::new(),
+				errors,
+			});
+		}
+
+		let mut savepoint = db_tx.begin().await?;
+
+		let inserted = match insert_rows(&mut *savepoint, user_id, &rows).await {
+			Ok(inserted) => {
+				savepoint.commit().await?;
+				inserted
+			}
+			// an atomic batch is rejected as well, but the broken items are reported by index
+			Err(err) if is_integrity_violation(&err) => {
+				savepoint.rollback().await?;
+
+				let (inserted, failed) = insert_rows_one_by_one(&mut db_tx, user_id, rows).await?;
+				errors.extend(
+					failed
+						.into_iter()
+						.map(|(id, err)| BatchItemError::new(indexes[&id], err)),
+				);
+
+				inserted
+			}
+			Err(err) => return Err(err.into()),
+		};
+
+		// dropping the DB transaction rolls the inserted items back
+		if mode == BatchMode::Atomic && !errors.is_empty() {
+			return Ok(BatchResult {
+				created: Vec::new(),
+				errors,
+			});
+		}
+
+		record_creations(&mut db_tx, user_id, &inserted).await?;
+		db_tx.commit().await?;
+
+		let mut created: Vec<BatchItem> = inserted
+			.into_iter()
+			.map(|tx| BatchItem {
+				index: indexes[&tx.id],
+				transaction: tx,
+			})
+			.collect();
+		created.sort_by_key(|item| item.index);
+
+		return Ok(BatchResult { created, errors });
+	}
+
+	async fn import_transactions(
+		&self,
+		UserId(user_id): UserId,
+		lines: Vec<(usize, ApiTransaction)>,
+		dry_run: bool,
+	) -> Result<Vec<ImportLineError>, AppError> {
+		let mut numbers = HashMap::with_capacity(lines.len());
+		let mut rows = Vec::with_capacity(lines.len());
+		let mut errors = Vec::new();
+
+		let mut db_tx = self.pool.begin().await?;
+
+		for (line, tx) in lines {
+			match check_creation(&mut db_tx, "", &tx, &rows).await {
+				Ok(()) => {
+					let id = Uuid::new_v4();
+					numbers.insert(id, line);
+					rows.push((id, tx));
+				}
+				Err(
+					err @ (AppError::Validation(_) | AppError::Conflict(_) | AppError::PeriodClosed(_)),
+				) => errors.push(ImportLineError::new(line, err)),
+				Err(err) => return Err(err),
+			}
+		}
+
+		// the rows are inserted on a dry run as well, the database has the last word on them
+		let mut savepoint = db_tx.begin().await?;
+
+		let inserted = match insert_rows(&mut *savepoint, user_id, &rows).await {
+			Ok(inserted) => {
+				savepoint.commit().await?;
+				inserted
+			}
+			Err(err) if is_integrity_violation(&err) => {
+				savepoint.rollback().await?;
+
+				let (inserted, failed) = insert_rows_one_by_one(&mut db_tx, user_id, rows).await?;
+				errors.extend(
+					failed
+						.into_iter()
+						.map(|(id, err)| ImportLineError::new(numbers[&id], err)),
+				);
+
+				inserted
+			}
+			Err(err) => return Err(err.into()),
+		};
+
+		// dropping the DB transaction rolls the rows back
+		if dry_run || !errors.is_empty() {
+			errors.sort_by_key(|err| err.line);
+			return Ok(errors);
+		}
+
+		record_creations(&mut db_tx, user_id, &inserted).await?;
+		db_tx.commit().await?;
+
+		return Ok(errors);
+	}
+
 	async fn update_transaction(
 		&self,
 		TxId(tx_id): TxId,
 		UserId(user_id): UserId,
 		tx: ApiTransaction,
+		if_match: Option<ETagCondition>,
 	) -> Result<Transaction, AppError> {
-		let mut rows = sqlx::query_as::<_, Transaction>(
-			"UPDATE transactions
-			SET op_date = $1,
-				gas_station_id = $2,
-				card_id = $3,
-				contract_id = $4,
-				nomenclature_id = $5,
-				amount = $6,
-				stella_sum = $7,
-				stella_nds_sum = $8,
-				refund = $9,
-				buy_sum_plan = $10,
-				buy_nds_sum_plan = $11,
-				buy_sum_fact = $12,
-				buy_nds_sum_fact = $13,
-				sell_sum_plan = $14,
-				sell_nds_sum_plan = $15,
-				sell_sum_fact = $16,
-				sell_nds_sum_fact = $17,
-				implementation_id = $18,
-				user_id = $19
-			WHERE id = $20
-			RETURNING *;",
+		let mut db_tx = self.pool.begin().await?;
+
+		let existing_tx = lock_row(&mut db_tx, tx_id, &if_match).await?;
+		check_periods(&mut db_tx, &[existing_tx.op_date, tx.op_date]).await?;
+		check_implementations(
+			&mut db_tx,
+			&[existing_tx.implementation_id, tx.implementation_id],
 		)
-		.bind(tx.op_date)
-		.bind(tx.gas_station_id)
-		.bind(tx.card_id)
-		.bind(tx.contract_id)
-		.bind(tx.nomenclature_id)
-		.bind(tx.amount)
-		.bind(tx.stella_sum)
-		.bind(tx.stella_nds_sum)
-		.bind(tx.refund)
-		.bind(tx.buy_sum_plan)
-		.bind(tx.buy_nds_sum_plan)
-		.bind(tx.buy_sum_fact)
-		.bind(tx.buy_nds_sum_fact)
-		.bind(tx.sell_sum_plan)
-		.bind(tx.sell_nds_sum_plan)
-		.bind(tx.sell_sum_fact)
-		.bind(tx.sell_nds_sum_fact)
-		.bind(tx.implementation_id)
-		.bind(user_id)
-		.bind(tx_id)
-		.fetch_all(&self.pool)
 		.await?;
+		check_refund(&mut db_tx, "", &tx, Some(tx_id), &[]).await?;
+		check_refunded(&mut db_tx, tx_id, &tx).await?;
+		let updated_tx = update_row(&mut *db_tx, tx_id, user_id, tx).await?;
 
-		return match rows.pop() {
-			None => Err(AppError::NotFound(format!(
-				"Transaction with id {tx_id} not found"
-			))),
-			Some(tx) => Ok(tx),
+		let Some(updated_tx) = updated_tx else {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
 		};
+
+		record_history(
+			&mut db_tx,
+			HistoryOperation::Update,
+			user_id,
+			Some(&existing_tx),
+			&updated_tx,
+		)
+		.await?;
+		db_tx.commit().await?;
+
+		return Ok(updated_tx);
+	}
+
+	async fn patch_transaction(
+		&self,
+		TxId(tx_id): TxId,
+		UserId(user_id): UserId,
+		patch: ApiTransactionPatch,
+		if_match: Option<ETagCondition>,
+	) -> Result<Transaction, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		let existing_tx = lock_row(&mut db_tx, tx_id, &if_match).await?;
+		let tx = patch.apply(&existing_tx);
+		tx.validate_at("")?;
+		check_periods(&mut db_tx, &[existing_tx.op_date, tx.op_date]).await?;
+		check_implementations(
+			&mut db_tx,
+			&[existing_tx.implementation_id, tx.implementation_id],
+		)
+		.await?;
+		check_refund(&mut db_tx, "", &tx, Some(tx_id), &[]).await?;
+		check_refunded(&mut db_tx, tx_id, &tx).await?;
+		let patched_tx = update_row(&mut *db_tx, tx_id, user_id, tx).await?;
+
+		let Some(patched_tx) = patched_tx else {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
+		};
+
+		record_history(
+			&mut db_tx,
+			HistoryOperation::Update,
+			user_id,
+			Some(&existing_tx),
+			&patched_tx,
+		)
+		.await?;
+		db_tx.commit().await?;
+
+		return Ok(patched_tx);
 	}
 
 	async fn delete_transaction(
 		&self,
 		TxId(tx_id): TxId,
 		UserId(user_id): UserId,
+		if_match: Option<ETagCondition>,
 	) -> Result<(), AppError> {
-		let mut rows = sqlx::query_as::<_, Transaction>(
+		let mut db_tx = self.pool.begin().await?;
+
+		let existing_tx = lock_row(&mut db_tx, tx_id, &if_match).await?;
+		check_periods(&mut db_tx, &[existing_tx.op_date]).await?;
+		check_implementations(&mut db_tx, &[existing_tx.implementation_id]).await?;
+
+		if !refunds_of(&mut db_tx, tx_id, None).await?.is_empty() {
+			return Err(AppError::Conflict(t(Message::TransactionHasRefunds(tx_id))));
+		}
+
+		let deleted_tx = sqlx::query_as::<_, Transaction>(
 			"UPDATE transactions
 			SET deleted = true,
-				user_id = $1
+				user_id = $1,
+				deleted_by = $1
+			WHERE id = $2
+			RETURNING *;",
+		)
+		.bind(user_id)
+		.bind(tx_id)
+		.fetch_one(&mut *db_tx)
+		.await?;
+
+		record_history(
+			&mut db_tx,
+			HistoryOperation::Delete,
+			user_id,
+			Some(&existing_tx),
+			&deleted_tx,
+		)
+		.await?;
+		db_tx.commit().await?;
+
+		return Ok(());
+	}
+
+	async fn restore_transaction(
+		&self,
+		TxId(tx_id): TxId,
+		UserId(user_id): UserId,
+	) -> Result<Transaction, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		let existing_tx =
+			sqlx::query_as::<_, Transaction>("SELECT * FROM transactions WHERE id = $1 FOR UPDATE;")
+				.bind(tx_id)
+				.fetch_optional(&mut *db_tx)
+				.await?;
+
+		let Some(existing_tx) = existing_tx else {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
+		};
+
+		if !existing_tx.deleted {
+			return Err(AppError::Conflict(t(Message::TransactionNotDeleted(tx_id))));
+		}
+
+		check_periods(&mut db_tx, &[existing_tx.op_date]).await?;
+		check_implementations(&mut db_tx, &[existing_tx.implementation_id]).await?;
+
+		// the refund must still fit into what is left of its purchase
+		check_refund(
+			&mut db_tx,
+			"",
+			&ApiTransaction::from(&existing_tx),
+			Some(tx_id),
+			&[],
+		)
+		.await?;
+
+		let restored_tx = sqlx::query_as::<_, Transaction>(
+			"UPDATE transactions
+			SET deleted = false,
+				user_id = $1,
+				updated_by = $1,
+				deleted_by = NULL
 			WHERE id = $2
 			RETURNING *;",
 		)
 		.bind(user_id)
 		.bind(tx_id)
+		.fetch_one(&mut *db_tx)
+		.await?;
+
+		record_history(
+			&mut db_tx,
+			HistoryOperation::Restore,
+			user_id,
+			Some(&existing_tx),
+			&restored_tx,
+		)
+		.await?;
+		db_tx.commit().await?;
+
+		return Ok(restored_tx);
+	}
+
+	async fn get_transaction_history(
+		&self,
+		TxId(tx_id): TxId,
+	) -> Result<Vec<TransactionHistoryEntry>, AppError> {
+		let (exists,) =
+			sqlx::query_as::<_, (bool,)>("SELECT EXISTS(SELECT 1 FROM transactions WHERE id = $1);")
+				.bind(tx_id)
+				.fetch_one(&self.pool)
+				.await?;
+
+		if !exists {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
+		}
+
+		let entries = sqlx::query_as::<_, TransactionHistoryEntry>(
+			"SELECT * FROM transaction_history
+			WHERE transaction_id = $1
+			ORDER BY date_created ASC, id ASC;",
+		)
+		.bind(tx_id)
 		.fetch_all(&self.pool)
 		.await?;
 
-		return match rows.pop() {
-			None => Err(AppError::NotFound(format!(
-				"Transaction with id {tx_id} not found"
-			))),
-			Some(_) => Ok(()),
+		return Ok(entries);
+	}
+
+	async fn get_transactions_stats(
+		&self,
+		filter: TransactionsFilter,
+		group_by: Vec<StatsDimension>,
+	) -> Result<TransactionsStats, AppError> {
+		let keys = STATS_KEYS.map(|(column, null_value)| {
+			match group_by.iter().find(|d| stats_key_column(**d) == column) {
+				Some(dimension) => (stats_key_expression(*dimension), true),
+				None => (null_value, false),
+			}
+		});
+
+		let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+
+		for ((expression, _), (column, _)) in keys.iter().zip(STATS_KEYS) {
+			query.push(format!("{expression} AS {column}, "));
+		}
+
+		query.push(
+			"COUNT(*) AS count,
+			COUNT(*) FILTER (WHERE refund) AS refund_count",
+		);
+
+		for column in STATS_SUM_COLUMNS {
+			query.push(format!(
+				", COALESCE(SUM(CASE WHEN refund THEN -ABS({column}) ELSE {column} END), 0) AS {column}"
+			));
+		}
+
+		query.push(" FROM transactions");
+		push_filter(&mut query, &filter, DeletedMode::Exclude);
+
+		let grouped: Vec<&str> = keys
+			.iter()
+			.filter(|(_, is_grouped)| *is_grouped)
+			.map(|(expression, _)| *expression)
+			.collect();
+
+		if !grouped.is_empty() {
+			query.push(format!(" GROUP BY {}", grouped.join(", ")));
+
+			let order: Vec<String> = grouped
+				.iter()
+				.map(|expression| format!("{expression} ASC NULLS FIRST"))
+				.collect();
+			query.push(format!(" ORDER BY {}", order.join(", ")));
+		}
+
+		let rows = query
+			.build_query_as::<StatsRow>()
+			.fetch_all(&self.pool)
+			.await?;
+
+		return Ok(TransactionsStats { group_by, rows });
+	}
+
+	async fn get_variance_report(
+		&self,
+		filter: TransactionsFilter,
+		thresholds: VarianceThresholds,
+	) -> Result<VarianceReport, AppError> {
+		let mut query = QueryBuilder::<Postgres>::new(
+			"SELECT id, op_date, gas_station_id, contract_id, nomenclature_id, refund,
+			buy_sum_plan, buy_sum_fact,
+			buy_sum_fact - buy_sum_plan AS buy_variance,
+			ROUND((buy_sum_fact - buy_sum_plan) * 100 / NULLIF(ABS(buy_sum_plan), 0), 2) AS buy_variance_pct,
+			sell_sum_plan, sell_sum_fact,
+			sell_sum_fact - sell_sum_plan AS sell_variance,
+			ROUND((sell_sum_fact - sell_sum_plan) * 100 / NULLIF(ABS(sell_sum_plan), 0), 2) AS sell_variance_pct
+			FROM transactions",
+		);
+
+		push_filter(&mut query, &filter, DeletedMode::Exclude);
+
+		query.push(" AND (false");
+		for (plan, fact) in [
+			("buy_sum_plan", "buy_sum_fact"),
+			("sell_sum_plan", "sell_sum_fact"),
+		] {
+			if let Some(abs) = thresholds.abs {
+				query
+					.push(format!(" OR ABS({fact} - {plan}) > "))
+					.push_bind(abs);
+			}
+			if let Some(pct) = thresholds.pct {
+				query
+					.push(format!(
+						" OR ({plan} <> 0 AND ABS({fact} - {plan}) * 100 > ABS({plan}) * "
+					))
+					.push_bind(pct)
+					.push(")");
+			}
+		}
+		query.push(") ORDER BY op_date ASC, id ASC");
+
+		let transactions = query
+			.build_query_as::<TransactionVariance>()
+			.fetch_all(&self.pool)
+			.await?;
+
+		return Ok(VarianceReport::new(thresholds, transactions));
+	}
+
+	async fn get_margin_report(&self, filter: TransactionsFilter) -> Result<MarginReport, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		// every section of the report must see the same rows
+		sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY;")
+			.execute(&mut *db_tx)
+			.await?;
+
+		let mut sections = Vec::with_capacity(MARGIN_KEYS.len());
+		for key in MARGIN_KEYS {
+			let mut query = QueryBuilder::<Postgres>::new(format!(
+				"SELECT {key} AS id,
+				COUNT(*) AS count,
+				COALESCE(SUM(margin_plan), 0) AS margin_plan,
+				COALESCE(SUM(margin_fact), 0) AS margin_fact,
+				COALESCE(SUM(margin_plan_net), 0) AS margin_plan_net,
+				COALESCE(SUM(margin_fact_net), 0) AS margin_fact_net
+				FROM transactions"
+			));
+
+			push_filter(&mut query, &filter, DeletedMode::Exclude);
+
+			if key != MARGIN_TOTAL_KEY {
+				query.push(format!(" GROUP BY {key} ORDER BY {key} ASC NULLS FIRST"));
+			}
+
+			let rows = query
+				.build_query_as::<MarginTotals>()
+				.fetch_all(&mut *db_tx)
+				.await?;
+			sections.push(rows);
+		}
+
+		db_tx.commit().await?;
+
+		let [total, by_contract, by_station, by_nomenclature]: [Vec<MarginTotals>; 4] = sections
+			.try_into()
+			.map_err(|_| AppError::SystemError(t(Message::InternalError)))?;
+
+		return Ok(MarginReport {
+			total: total.into_iter().next().unwrap_or_default(),
+			by_contract,
+			by_station,
+			by_nomenclature,
+		});
+	}
+
+	async fn get_transaction_refunds(
+		&self,
+		TxId(tx_id): TxId,
+	) -> Result<TransactionRefunds, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		// the balance must be computed from the refunds being returned
+		sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY;")
+			.execute(&mut *db_tx)
+			.await?;
+
+		let original = sqlx::query_as::<_, Transaction>(
+			"SELECT * FROM transactions WHERE id = $1 AND NOT deleted;",
+		)
+		.bind(tx_id)
+		.fetch_optional(&mut *db_tx)
+		.await?;
+
+		let Some(original) = original else {
+			return Err(AppError::NotFound(t(Message::TransactionNotFound(tx_id))));
+		};
+
+		if original.refund {
+			return Err(AppError::Conflict(t(Message::TransactionIsRefund(tx_id))));
+		}
+
+		let refunds = refunds_of(&mut db_tx, tx_id, None).await?;
+		db_tx.commit().await?;
+
+		return Ok(TransactionRefunds::new(&original, refunds));
+	}
+
+	async fn get_directory_entries(
+		&self,
+		kind: DirectoryKind,
+		filter: DirectoryFilter,
+	) -> Result<Vec<DirectoryEntry>, AppError> {
+		let mut query = QueryBuilder::<Postgres>::new(format!(
+			"SELECT {} FROM {} WHERE true",
+			directory_columns(kind),
+			kind.table()
+		));
+
+		if let Some(active) = filter.active {
+			query.push(" AND active = ").push_bind(active);
+		}
+		query.push(" ORDER BY code ASC");
+
+		let entries = query
+			.build_query_as::<DirectoryEntry>()
+			.fetch_all(&self.pool)
+			.await?;
+
+		return Ok(entries);
+	}
+
+	async fn get_directory_entries_by_ids(
+		&self,
+		kind: DirectoryKind,
+		ids: Vec<Uuid>,
+	) -> Result<Vec<DirectoryEntry>, AppError> {
+		let sql = format!(
+			"SELECT {} FROM {} WHERE id = ANY($1);",
+			directory_columns(kind),
+			kind.table()
+		);
+
+		let entries = sqlx::query_as::<_, DirectoryEntry>(&sql)
+			.bind(ids)
+			.fetch_all(&self.pool)
+			.await?;
+
+		return Ok(entries);
+	}
+
+	async fn get_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		DirectoryEntryId(id): DirectoryEntryId,
+	) -> Result<DirectoryEntry, AppError> {
+		let sql = format!(
+			"SELECT {} FROM {} WHERE id = $1;",
+			directory_columns(kind),
+			kind.table()
+		);
+
+		let entry = sqlx::query_as::<_, DirectoryEntry>(&sql)
+			.bind(id)
+			.fetch_optional(&self.pool)
+			.await?;
+
+		return match entry {
+			None => Err(AppError::NotFound(t(Message::DirectoryEntryNotFound(id)))),
+			Some(entry) => Ok(entry),
 		};
 	}
 
+	async fn create_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		entry: ApiDirectoryEntry,
+	) -> Result<DirectoryEntry, AppError> {
+		let mut query =
+			QueryBuilder::<Postgres>::new(format!("INSERT INTO {} (name, code, active", kind.table()));
+
+		if kind.has_unit() {
+			query.push(", unit");
+		}
+
+		query
+			.push(") VALUES (")
+			.push_bind(entry.name)
+			.push(", ")
+			.push_bind(entry.code)
+			.push(", ")
+			.push_bind(entry.active);
+
+		if kind.has_unit() {
+			query.push(", ").push_bind(entry.unit);
+		}
+
+		query.push(format!(") RETURNING {};", directory_columns(kind)));
+
+		let entry = query
+			.build_query_as::<DirectoryEntry>()
+			.fetch_one(&self.pool)
+			.await?;
+
+		return Ok(entry);
+	}
+
+	async fn update_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		DirectoryEntryId(id): DirectoryEntryId,
+		entry: ApiDirectoryEntry,
+	) -> Result<DirectoryEntry, AppError> {
+		let mut query = QueryBuilder::<Postgres>::new(format!("UPDATE {} SET name = ", kind.table()));
+
+		query
+			.push_bind(entry.name)
+			.push(", code = ")
+			.push_bind(entry.code)
+			.push(", active = ")
+			.push_bind(entry.active);
+
+		if kind.has_unit() {
+			query.push(", unit = ").push_bind(entry.unit);
+		}
+
+		query
+			.push(" WHERE id = ")
+			.push_bind(id)
+			.push(format!(" RETURNING {};", directory_columns(kind)));
+
+		let entry = query
+			.build_query_as::<DirectoryEntry>()
+			.fetch_optional(&self.pool)
+			.await?;
+
+		return match entry {
+			None => Err(AppError::NotFound(t(Message::DirectoryEntryNotFound(id)))),
+			Some(entry) => Ok(entry),
+		};
+	}
+
+	async fn delete_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		DirectoryEntryId(id): DirectoryEntryId,
+	) -> Result<(), AppError> {
+		let sql = format!("DELETE FROM {} WHERE id = $1;", kind.table());
+
+		let deleted = sqlx::query(&sql).bind(id).execute(&self.pool).await;
+
+		return match deleted {
+			Ok(result) if result.rows_affected() == 0 => {
+				Err(AppError::NotFound(t(Message::DirectoryEntryNotFound(id))))
+			}
+			Ok(_) => Ok(()),
+			Err(EqlxError::Database(db_err)) if db_err.kind() == ErrorKind::ForeignKeyViolation => {
+				Err(AppError::Conflict(t(Message::DirectoryEntryInUse(id))))
+			}
+			Err(err) => Err(err.into()),
+		};
+	}
+
+	async fn get_implementations(
+		&self,
+		filter: ImplementationsFilter,
+	) -> Result<Vec<Implementation>, AppError> {
+		let mut query = QueryBuilder::<Postgres>::new(implementation_select());
+		query.push(" WHERE true");
+
+		if let Some(status) = filter.status {
+			query
+				.push(" AND implementations.status = ")
+				.push_bind(status);
+		}
+		if let Some(contract_id) = filter.contract_id {
+			query
+				.push(" AND implementations.contract_id = ")
+				.push_bind(contract_id);
+		}
+		query.push(" ORDER BY implementations.doc_date ASC, implementations.number ASC");
+
+		let implementations = query
+			.build_query_as::<Implementation>()
+			.fetch_all(&self.pool)
+			.await?;
+
+		return Ok(implementations);
+	}
+
+	async fn get_implementation(
+		&self,
+		ImplementationId(id): ImplementationId,
+	) -> Result<Implementation, AppError> {
+		return fetch_implementation(&self.pool, id).await;
+	}
+
+	async fn get_implementation_transactions(
+		&self,
+		ImplementationId(id): ImplementationId,
+	) -> Result<Vec<Transaction>, AppError> {
+		let transactions = sqlx::query_as::<_, Transaction>(
+			"SELECT * FROM transactions
+			WHERE implementation_id = $1 AND NOT deleted
+			ORDER BY op_date ASC, id ASC;",
+		)
+		.bind(id)
+		.fetch_all(&self.pool)
+		.await?;
+
+		if transactions.is_empty() {
+			let exists = sqlx::query_scalar::<_, bool>(
+				"SELECT EXISTS(SELECT 1 FROM implementations WHERE id = $1);",
+			)
+			.bind(id)
+			.fetch_one(&self.pool)
+			.await?;
+
+			if !exists {
+				return Err(AppError::NotFound(t(Message::ImplementationNotFound(id))));
+			}
+		}
+
+		return Ok(transactions);
+	}
+
+	async fn create_implementation(
+		&self,
+		implementation: ApiImplementation,
+	) -> Result<Implementation, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		let id = sqlx::query_scalar::<_, Uuid>(
+			"INSERT INTO implementations (number, doc_date, contract_id)
+			VALUES ($1, $2, $3)
+			RETURNING id;",
+		)
+		.bind(implementation.number)
+		.bind(implementation.doc_date)
+		.bind(implementation.contract_id)
+		.fetch_one(&mut *db_tx)
+		.await?;
+
+		let created = fetch_implementation(&mut *db_tx, id).await?;
+		db_tx.commit().await?;
+
+		return Ok(created);
+	}
+
+	async fn update_implementation(
+		&self,
+		ImplementationId(id): ImplementationId,
+		implementation: ApiImplementation,
+	) -> Result<Implementation, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		if lock_implementation(&mut db_tx, id).await? == ImplementationStatus::Posted {
+			return Err(AppError::Conflict(t(Message::ImplementationPosted(id))));
+		}
+
+		sqlx::query(
+			"UPDATE implementations
+			SET number = $1,
+				doc_date = $2,
+				contract_id = $3
+			WHERE id = $4;",
+		)
+		.bind(implementation.number)
+		.bind(implementation.doc_date)
+		.bind(implementation.contract_id)
+		.bind(id)
+		.execute(&mut *db_tx)
+		.await?;
+
+		let updated = fetch_implementation(&mut *db_tx, id).await?;
+		db_tx.commit().await?;
+
+		return Ok(updated);
+	}
+
+	async fn delete_implementation(
+		&self,
+		ImplementationId(id): ImplementationId,
+	) -> Result<(), AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		if lock_implementation(&mut db_tx, id).await? == ImplementationStatus::Posted {
+			return Err(AppError::Conflict(t(Message::ImplementationPosted(id))));
+		}
+
+		let deleted = sqlx::query("DELETE FROM implementations WHERE id = $1;")
+			.bind(id)
+			.execute(&mut *db_tx)
+			.await;
+
+		return match deleted {
+			Ok(_) => {
+				db_tx.commit().await?;
+				Ok(())
+			}
+			Err(EqlxError::Database(db_err)) if db_err.kind() == ErrorKind::ForeignKeyViolation => {
+				Err(AppError::Conflict(t(
+					Message::ImplementationHasTransactions(id),
+				)))
+			}
+			Err(err) => Err(err.into()),
+		};
+	}
+
+	async fn set_implementation_status(
+		&self,
+		ImplementationId(id): ImplementationId,
+		status: ImplementationStatus,
+	) -> Result<Implementation, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		// waits for the transactions of the document being changed right now
+		if lock_implementation(&mut db_tx, id).await? != status {
+			let posted_at = match status {
+				ImplementationStatus::Posted => Some(Utc::now()),
+				ImplementationStatus::Draft => None,
+			};
+
+			sqlx::query("UPDATE implementations SET status = $1, posted_at = $2 WHERE id = $3;")
+				.bind(status)
+				.bind(posted_at)
+				.bind(id)
+				.execute(&mut *db_tx)
+				.await?;
+		}
+
+		let implementation = fetch_implementation(&mut *db_tx, id).await?;
+		db_tx.commit().await?;
+
+		return Ok(implementation);
+	}
+
+	async fn get_closed_periods(&self) -> Result<Vec<ClosedPeriod>, AppError> {
+		let periods =
+			sqlx::query_as::<_, ClosedPeriod>("SELECT * FROM closed_periods ORDER BY month ASC;")
+				.fetch_all(&self.pool)
+				.await?;
+
+		return Ok(periods);
+	}
+
+	async fn close_period(
+		&self,
+		PeriodMonth(month): PeriodMonth,
+		AdminId(admin_id): AdminId,
+	) -> Result<ClosedPeriod, AppError> {
+		let mut db_tx = self.pool.begin().await?;
+
+		// conflicts with the shared locks of `check_periods` but not with plain reads
+		sqlx::query("LOCK TABLE closed_periods IN EXCLUSIVE MODE;")
+			.execute(&mut *db_tx)
+			.await?;
+
+		sqlx::query(
+			"INSERT INTO closed_periods (month, closed_by)
+			VALUES ($1, $2)
+			ON CONFLICT DO NOTHING;",
+		)
+		.bind(month)
+		.bind(admin_id)
+		.execute(&mut *db_tx)
+		.await?;
+
+		let period =
+			sqlx::query_as::<_, ClosedPeriod>("SELECT * FROM closed_periods WHERE month = $1;")
+				.bind(month)
+				.fetch_one(&mut *db_tx)
+				.await?;
+		db_tx.commit().await?;
+
+		return Ok(period);
+	}
+
+	async fn reopen_period(&self, PeriodMonth(month): PeriodMonth) -> Result<(), AppError> {
+		let deleted = sqlx::query("DELETE FROM closed_periods WHERE month = $1;")
+			.bind(month)
+			.execute(&self.pool)
+			.await?;
+
+		if deleted.rows_affected() == 0 {
+			return Err(AppError::NotFound(t(Message::PeriodNotClosed(month))));
+		}
+
+		return Ok(());
+	}
+
 	async fn close(&self) {
 		self.pool.close().await;
 	}
@@ -1,11 +1,26 @@
 mod implementations;
 pub mod models;
 
-use crate::dto::{ApiTransaction, UserId};
+use crate::dto::{
+	AdminId, ApiDirectoryEntry, ApiImplementation, ApiTransaction, ApiTransactionPatch, BatchMode,
+	DeletedMode, DirectoryEntryId, DirectoryFilter, DirectoryKind, ETagCondition, IdempotencyKey,
+	ImplementationId, ImplementationsFilter, ImportLineError, Page, PeriodMonth, StatsDimension,
+	TransactionsFilter, UserId, VarianceThresholds,
+};
 use crate::system_models::AppError;
 use crate::{config, dto::TxId};
+use ::std::collections::HashMap;
 use implementations::{MockStore, PostgresStore};
-use models::Transaction;
+use models::{
+	BatchResult, ClosedPeriod, DirectoryEntry, Implementation, ImplementationStatus, MarginReport,
+	Transaction, TransactionHistoryEntry, TransactionRefunds, TransactionsPage, TransactionsStats,
+	VarianceReport,
+};
+use tokio::sync::mpsc::Receiver;
+use uuid::Uuid;
+
+/// Transactions read ahead of the consumer, an error ends the sequence
+pub type TransactionBatches = Receiver<Result<Vec<Transaction>, AppError>>;
 
 #[derive(Clone)]
 enum StoreKind {
@@ -14,24 +29,191 @@ enum StoreKind {
 }
 
 trait Store {
-	async fn get_transactions_list(&self) -> Result<Vec<Transaction>, AppError>;
+	async fn get_transactions_list(
+		&self,
+		filter: TransactionsFilter,
+		deleted: DeletedMode,
+		page: Page,
+	) -> Result<TransactionsPage, AppError>;
+
+	/// Every matching transaction in the order of the list, batch by batch as the reader takes them
+	async fn export_transactions(
+		&self,
+		filter: TransactionsFilter,
+		deleted: DeletedMode,
+	) -> Result<TransactionBatches, AppError>;
 
-	async fn get_transaction(&self, tx_id: TxId) -> Result<Transaction, AppError>;
+	async fn get_transaction(
+		&self,
+		tx_id: TxId,
+		deleted: DeletedMode,
+	) -> Result<Transaction, AppError>;
 
 	async fn create_transaction(
 		&self,
 		user_id: UserId,
 		new_tx: ApiTransaction,
+		idempotency_key: Option<IdempotencyKey>,
 	) -> Result<Transaction, AppError>;
 
+	async fn create_transactions_batch(
+		&self,
+		user_id: UserId,
+		items: Vec<(usize, ApiTransaction)>,
+		mode: BatchMode,
+	) -> Result<BatchResult, AppError>;
+
+	/// Checks every line and creates them all or none, nothing is written on a dry run
+	async fn import_transactions(
+		&self,
+		user_id: UserId,
+		lines: Vec<(usize, ApiTransaction)>,
+		dry_run: bool,
+	) -> Result<Vec<ImportLineError>, AppError>;
+
 	async fn update_transaction(
 		&self,
 		tx_id: TxId,
 		user_id: UserId,
 		tx: ApiTransaction,
+		if_match: Option<ETagCondition>,
+	) -> Result<Transaction, AppError>;
+
+	async fn patch_transaction(
+		&self,
+		tx_id: TxId,
+		user_id: UserId,
+		patch: ApiTransactionPatch,
+		if_match: Option<ETagCondition>,
+	) -> Result<Transaction, AppError>;
+
+	async fn delete_transaction(
+		&self,
+		tx_id: TxId,
+		user_id: UserId,
+		if_match: Option<ETagCondition>,
+	) -> Result<(), AppError>;
+
+	async fn restore_transaction(
+		&self,
+		tx_id: TxId,
+		user_id: UserId,
 	) -> Result<Transaction, AppError>;
 
-	async fn delete_transaction(&self, tx_id: TxId, user_id: UserId) -> Result<(), AppError>;
+	async fn get_transaction_history(
+		&self,
+		tx_id: TxId,
+	) -> Result<Vec<TransactionHistoryEntry>, AppError>;
+
+	/// Deleted transactions are never counted
+	async fn get_transactions_stats(
+		&self,
+		filter: TransactionsFilter,
+		group_by: Vec<StatsDimension>,
+	) -> Result<TransactionsStats, AppError>;
+
+	/// Deviating transactions ordered by `op_date`, deleted ones are never listed
+	async fn get_variance_report(
+		&self,
+		filter: TransactionsFilter,
+		thresholds: VarianceThresholds,
+	) -> Result<VarianceReport, AppError>;
+
+	/// Margins of the matching transactions overall and per contract, station and nomenclature,
+	/// deleted ones are never counted
+	async fn get_margin_report(&self, filter: TransactionsFilter) -> Result<MarginReport, AppError>;
+
+	/// Active refunds of an active purchase with what is left to refund
+	async fn get_transaction_refunds(&self, tx_id: TxId) -> Result<TransactionRefunds, AppError>;
+
+	/// Ordered by `code`
+	async fn get_directory_entries(
+		&self,
+		kind: DirectoryKind,
+		filter: DirectoryFilter,
+	) -> Result<Vec<DirectoryEntry>, AppError>;
+
+	/// Missing ids are skipped
+	async fn get_directory_entries_by_ids(
+		&self,
+		kind: DirectoryKind,
+		ids: Vec<Uuid>,
+	) -> Result<Vec<DirectoryEntry>, AppError>;
+
+	async fn get_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		id: DirectoryEntryId,
+	) -> Result<DirectoryEntry, AppError>;
+
+	async fn create_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		entry: ApiDirectoryEntry,
+	) -> Result<DirectoryEntry, AppError>;
+
+	async fn update_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		id: DirectoryEntryId,
+		entry: ApiDirectoryEntry,
+	) -> Result<DirectoryEntry, AppError>;
+
+	/// Entries in use, even by deleted transactions, can only be deactivated
+	async fn delete_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		id: DirectoryEntryId,
+	) -> Result<(), AppError>;
+
+	/// Ordered by `doc_date` and `number`
+	async fn get_implementations(
+		&self,
+		filter: ImplementationsFilter,
+	) -> Result<Vec<Implementation>, AppError>;
+
+	async fn get_implementation(&self, id: ImplementationId) -> Result<Implementation, AppError>;
+
+	/// Active transactions of a document ordered by `op_date`
+	async fn get_implementation_transactions(
+		&self,
+		id: ImplementationId,
+	) -> Result<Vec<Transaction>, AppError>;
+
+	/// Documents are always created as drafts
+	async fn create_implementation(
+		&self,
+		implementation: ApiImplementation,
+	) -> Result<Implementation, AppError>;
+
+	/// Only drafts can be edited
+	async fn update_implementation(
+		&self,
+		id: ImplementationId,
+		implementation: ApiImplementation,
+	) -> Result<Implementation, AppError>;
+
+	/// Only drafts which no transaction, even a deleted one, refers to can be deleted
+	async fn delete_implementation(&self, id: ImplementationId) -> Result<(), AppError>;
+
+	/// Posts or unposts a document, setting the current status again changes nothing
+	async fn set_implementation_status(
+		&self,
+		id: ImplementationId,
+		status: ImplementationStatus,
+	) -> Result<Implementation, AppError>;
+
+	/// Ordered by month
+	async fn get_closed_periods(&self) -> Result<Vec<ClosedPeriod>, AppError>;
+
+	/// Waits for the transactions being changed right now, closing a closed period changes nothing
+	async fn close_period(
+		&self,
+		month: PeriodMonth,
+		admin_id: AdminId,
+	) -> Result<ClosedPeriod, AppError>;
+
+	async fn reopen_period(&self, month: PeriodMonth) -> Result<(), AppError>;
 
 	async fn close(&self);
 }
@@ -54,17 +236,37 @@ impl Repository {
 		};
 	}
 
-	pub async fn get_transactions_list(&self) -> Result<Vec<Transaction>, AppError> {
+	pub async fn get_transactions_list(
+		&self,
+		filter: TransactionsFilter,
+		deleted: DeletedMode,
+		page: Page,
+	) -> Result<TransactionsPage, AppError> {
 		match &self.store {
-			StoreKind::Mock(store) => store.get_transactions_list().await,
-			StoreKind::Postgres(store) => store.get_transactions_list().await,
+			StoreKind::Mock(store) => store.get_transactions_list(filter, deleted, page).await,
+			StoreKind::Postgres(store) => store.get_transactions_list(filter, deleted, page).await,
 		}
 	}
 
-	pub async fn get_transaction(&self, tx_id: TxId) -> Result<Transaction, AppError> {
+	pub async fn export_transactions(
+		&self,
+		filter: TransactionsFilter,
+		deleted: DeletedMode,
+	) -> Result<TransactionBatches, AppError> {
 		match &self.store {
-			StoreKind::Mock(store) => store.get_transaction(tx_id).await,
-			StoreKind::Postgres(store) => store.get_transaction(tx_id).await,
+			StoreKind::Mock(store) => store.export_transactions(filter, deleted).await,
+			StoreKind::Postgres(store) => store.export_transactions(filter, deleted).await,
+		}
+	}
+
+	pub async fn get_transaction(
+		&self,
+		tx_id: TxId,
+		deleted: DeletedMode,
+	) -> Result<Transaction, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_transaction(tx_id, deleted).await,
+			StoreKind::Postgres(store) => store.get_transaction(tx_id, deleted).await,
 		}
 	}
 
@@ -72,10 +274,43 @@ impl Repository {
 		&self,
 		user_id: UserId,
 		new_tx: ApiTransaction,
+		idempotency_key: Option<IdempotencyKey>,
 	) -> Result<Transaction, AppError> {
 		match &self.store {
-			StoreKind::Mock(store) => store.create_transaction(user_id, new_tx).await,
-			StoreKind::Postgres(store) => store.create_transaction(user_id, new_tx).await,
+			StoreKind::Mock(store) => {
+				store
+					.create_transaction(user_id, new_tx, idempotency_key)
+					.await
+			}
+			StoreKind::Postgres(store) => {
+				store
+					.create_transaction(user_id, new_tx, idempotency_key)
+					.await
+			}
+		}
+	}
+
+	pub async fn create_transactions_batch(
+		&self,
+		user_id: UserId,
+		items: Vec<(usize, ApiTransaction)>,
+		mode: BatchMode,
+	) -> Result<BatchResult, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.create_transactions_batch(user_id, items, mode).await,
+			StoreKind::Postgres(store) => store.create_transactions_batch(user_id, items, mode).await,
+		}
+	}
+
+	pub async fn import_transactions(
+		&self,
+		user_id: UserId,
+		lines: Vec<(usize, ApiTransaction)>,
+		dry_run: bool,
+	) -> Result<Vec<ImportLineError>, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.import_transactions(user_id, lines, dry_run).await,
+			StoreKind::Postgres(store) => store.import_transactions(user_id, lines, dry_run).await,
 		}
 	}
 
@@ -84,17 +319,288 @@ impl Repository {
 		tx_id: TxId,
 		user_id: UserId,
 		tx: ApiTransaction,
+		if_match: Option<ETagCondition>,
+	) -> Result<Transaction, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.update_transaction(tx_id, user_id, tx, if_match).await,
+			StoreKind::Postgres(store) => store.update_transaction(tx_id, user_id, tx, if_match).await,
+		}
+	}
+
+	pub async fn patch_transaction(
+		&self,
+		tx_id: TxId,
+		user_id: UserId,
+		patch: ApiTransactionPatch,
+		if_match: Option<ETagCondition>,
+	) -> Result<Transaction, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => {
+				store
+					.patch_transaction(tx_id, user_id, patch, if_match)
+					.await
+			}
+			StoreKind::Postgres(store) => {
+				store
+					.patch_transaction(tx_id, user_id, patch, if_match)
+					.await
+			}
+		}
+	}
+
+	pub async fn delete_transaction(
+		&self,
+		tx_id: TxId,
+		user_id: UserId,
+		if_match: Option<ETagCondition>,
+	) -> Result<(), AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.delete_transaction(tx_id, user_id, if_match).await,
+			StoreKind::Postgres(store) => store.delete_transaction(tx_id, user_id, if_match).await,
+		}
+	}
+
+	pub async fn restore_transaction(
+		&self,
+		tx_id: TxId,
+		user_id: UserId,
 	) -> Result<Transaction, AppError> {
 		match &self.store {
-			StoreKind::Mock(store) => store.update_transaction(tx_id, user_id, tx).await,
-			StoreKind::Postgres(store) => store.update_transaction(tx_id, user_id, tx).await,
+			StoreKind::Mock(store) => store.restore_transaction(tx_id, user_id).await,
+			StoreKind::Postgres(store) => store.restore_transaction(tx_id, user_id).await,
+		}
+	}
+
+	pub async fn get_transaction_history(
+		&self,
+		tx_id: TxId,
+	) -> Result<Vec<TransactionHistoryEntry>, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_transaction_history(tx_id).await,
+			StoreKind::Postgres(store) => store.get_transaction_history(tx_id).await,
+		}
+	}
+
+	pub async fn get_transactions_stats(
+		&self,
+		filter: TransactionsFilter,
+		group_by: Vec<StatsDimension>,
+	) -> Result<TransactionsStats, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_transactions_stats(filter, group_by).await,
+			StoreKind::Postgres(store) => store.get_transactions_stats(filter, group_by).await,
+		}
+	}
+
+	pub async fn get_variance_report(
+		&self,
+		filter: TransactionsFilter,
+		thresholds: VarianceThresholds,
+	) -> Result<VarianceReport, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_variance_report(filter, thresholds).await,
+			StoreKind::Postgres(store) => store.get_variance_report(filter, thresholds).await,
+		}
+	}
+
+	pub async fn get_margin_report(
+		&self,
+		filter: TransactionsFilter,
+	) -> Result<MarginReport, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_margin_report(filter).await,
+			StoreKind::Postgres(store) => store.get_margin_report(filter).await,
+		}
+	}
+
+	pub async fn get_transaction_refunds(
+		&self,
+		tx_id: TxId,
+	) -> Result<TransactionRefunds, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_transaction_refunds(tx_id).await,
+			StoreKind::Postgres(store) => store.get_transaction_refunds(tx_id).await,
+		}
+	}
+
+	pub async fn get_directory_entries(
+		&self,
+		kind: DirectoryKind,
+		filter: DirectoryFilter,
+	) -> Result<Vec<DirectoryEntry>, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_directory_entries(kind, filter).await,
+			StoreKind::Postgres(store) => store.get_directory_entries(kind, filter).await,
+		}
+	}
+
+	pub async fn get_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		id: DirectoryEntryId,
+	) -> Result<DirectoryEntry, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_directory_entry(kind, id).await,
+			StoreKind::Postgres(store) => store.get_directory_entry(kind, id).await,
+		}
+	}
+
+	pub async fn create_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		entry: ApiDirectoryEntry,
+	) -> Result<DirectoryEntry, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.create_directory_entry(kind, entry).await,
+			StoreKind::Postgres(store) => store.create_directory_entry(kind, entry).await,
+		}
+	}
+
+	pub async fn update_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		id: DirectoryEntryId,
+		entry: ApiDirectoryEntry,
+	) -> Result<DirectoryEntry, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.update_directory_entry(kind, id, entry).await,
+			StoreKind::Postgres(store) => store.update_directory_entry(kind, id, entry).await,
+		}
+	}
+
+	pub async fn delete_directory_entry(
+		&self,
+		kind: DirectoryKind,
+		id: DirectoryEntryId,
+	) -> Result<(), AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.delete_directory_entry(kind, id).await,
+			StoreKind::Postgres(store) => store.delete_directory_entry(kind, id).await,
+		}
+	}
+
+	/// Embeds the requested directory entries into the transactions
+	pub async fn expand_transactions(
+		&self,
+		txs: &mut [Transaction],
+		expand: &[DirectoryKind],
+	) -> Result<(), AppError> {
+		for kind in expand {
+			let mut ids: Vec<Uuid> = txs.iter().filter_map(|tx| tx.reference(*kind)).collect();
+			ids.sort_unstable();
+			ids.dedup();
+
+			if ids.is_empty() {
+				continue;
+			}
+
+			let entries = match &self.store {
+				StoreKind::Mock(store) => store.get_directory_entries_by_ids(*kind, ids).await?,
+				StoreKind::Postgres(store) => store.get_directory_entries_by_ids(*kind, ids).await?,
+			};
+
+			let entries: HashMap<Uuid, DirectoryEntry> =
+				entries.into_iter().map(|entry| (entry.id, entry)).collect();
+
+			for tx in txs.iter_mut() {
+				tx.expand(*kind, &entries);
+			}
+		}
+
+		return Ok(());
+	}
+
+	pub async fn get_implementations(
+		&self,
+		filter: ImplementationsFilter,
+	) -> Result<Vec<Implementation>, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_implementations(filter).await,
+			StoreKind::Postgres(store) => store.get_implementations(filter).await,
+		}
+	}
+
+	pub async fn get_implementation(
+		&self,
+		id: ImplementationId,
+	) -> Result<Implementation, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_implementation(id).await,
+			StoreKind::Postgres(store) => store.get_implementation(id).await,
+		}
+	}
+
+	pub async fn get_implementation_transactions(
+		&self,
+		id: ImplementationId,
+	) -> Result<Vec<Transaction>, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_implementation_transactions(id).await,
+			StoreKind::Postgres(store) => store.get_implementation_transactions(id).await,
+		}
+	}
+
+	pub async fn create_implementation(
+		&self,
+		implementation: ApiImplementation,
+	) -> Result<Implementation, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.create_implementation(implementation).await,
+			StoreKind::Postgres(store) => store.create_implementation(implementation).await,
+		}
+	}
+
+	pub async fn update_implementation(
+		&self,
+		id: ImplementationId,
+		implementation: ApiImplementation,
+	) -> Result<Implementation, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.update_implementation(id, implementation).await,
+			StoreKind::Postgres(store) => store.update_implementation(id, implementation).await,
+		}
+	}
+
+	pub async fn delete_implementation(&self, id: ImplementationId) -> Result<(), AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.delete_implementation(id).await,
+			StoreKind::Postgres(store) => store.delete_implementation(id).await,
+		}
+	}
+
+	pub async fn set_implementation_status(
+		&self,
+		id: ImplementationId,
+		status: ImplementationStatus,
+	) -> Result<Implementation, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.set_implementation_status(id, status).await,
+			StoreKind::Postgres(store) => store.set_implementation_status(id, status).await,
+		}
+	}
+
+	pub async fn get_closed_periods(&self) -> Result<Vec<ClosedPeriod>, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.get_closed_periods().await,
+			StoreKind::Postgres(store) => store.get_closed_periods().await,
+		}
+	}
+
+	pub async fn close_period(
+		&self,
+		month: PeriodMonth,
+		admin_id: AdminId,
+	) -> Result<ClosedPeriod, AppError> {
+		match &self.store {
+			StoreKind::Mock(store) => store.close_period(month, admin_id).await,
+			StoreKind::Postgres(store) => store.close_period(month, admin_id).await,
 		}
 	}
 
-	pub async fn delete_transaction(&self, tx_id: TxId, user_id: UserId) -> Result<(), AppError> {
+	pub async fn reopen_period(&self, month: PeriodMonth) -> Result<(), AppError> {
 		match &self.store {
-			StoreKind::Mock(store) => store.delete_transaction(tx_id, user_id).await,
-			StoreKind::Postgres(store) => store.delete_transaction(tx_id, user_id).await,
+			StoreKind::Mock(store) => store.reopen_period(month).await,
+			StoreKind::Postgres(store) => store.reopen_period(month).await,
 		}
 	}
 
@@ -1,10 +1,16 @@
-use chrono::{DateTime, Utc};
-use rust_decimal::Decimal;
+use ::std::collections::{BTreeMap, HashMap};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{types::Json, FromRow, Type};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::dto::{
+	ApiTransaction, BatchItemError, Cursor, DirectoryKind, ImportLineError, StatsDimension,
+	VarianceThresholds,
+};
+
 #[derive(Clone, Debug, FromRow, Deserialize, Serialize, ToSchema)]
 pub struct Transaction {
 	pub id: Uuid,
@@ -60,9 +66,691 @@ pub struct Transaction {
 	#[serde(with = "rust_decimal::serde::float_option")]
 	pub sell_nds_sum_fact: Option<Decimal>,
 
+	/// Sell minus buy sum, computed by the database.
+	/// Margins default to null in snapshots written before they existed
+	#[schema(value_type = Decimal)]
+	#[serde(default, with = "rust_decimal::serde::float_option")]
+	pub margin_plan: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(default, with = "rust_decimal::serde::float_option")]
+	pub margin_fact: Option<Decimal>,
+
+	/// Margin without NDS
+	#[schema(value_type = Decimal)]
+	#[serde(default, with = "rust_decimal::serde::float_option")]
+	pub margin_plan_net: Option<Decimal>,
+
+	/// Margin without NDS
+	#[schema(value_type = Decimal)]
+	#[serde(default, with = "rust_decimal::serde::float_option")]
+	pub margin_fact_net: Option<Decimal>,
+
 	pub implementation_id: Option<Uuid>,
+	/// The purchase this refund reverses
+	pub original_transaction_id: Option<Uuid>,
+	/// The last user who touched the row, whatever the operation was
 	pub user_id: Uuid,
+	pub created_by: Uuid,
+	/// The last user who edited or restored the row
+	pub updated_by: Option<Uuid>,
+	/// The user who deleted the row, cleared on restore
+	pub deleted_by: Option<Uuid>,
 	pub date_created: DateTime<Utc>,
 	pub date_updated: Option<DateTime<Utc>>,
 	pub deleted: bool,
+	pub version: i64,
+
+	/// Embedded on request with `expand`
+	#[sqlx(skip)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub gas_station: Option<DirectoryEntry>,
+
+	#[sqlx(skip)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub card: Option<DirectoryEntry>,
+
+	#[sqlx(skip)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub contract: Option<DirectoryEntry>,
+
+	#[sqlx(skip)]
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub nomenclature: Option<DirectoryEntry>,
+}
+
+fn margin(sell: Option<Decimal>, buy: Option<Decimal>) -> Option<Decimal> {
+	return Some(sell? - buy?);
+}
+
+fn net_margin(
+	sell: Option<Decimal>,
+	sell_nds: Option<Decimal>,
+	buy: Option<Decimal>,
+	buy_nds: Option<Decimal>,
+) -> Option<Decimal> {
+	return Some((sell? - sell_nds.unwrap_or_default()) - (buy? - buy_nds.unwrap_or_default()));
+}
+
+impl Transaction {
+	/// The entry of the directory the transaction refers to
+	pub fn reference(&self, kind: DirectoryKind) -> Option<Uuid> {
+		return match kind {
+			DirectoryKind::GasStations => Some(self.gas_station_id),
+			DirectoryKind::Cards => self.card_id,
+			DirectoryKind::Contracts => self.contract_id,
+			DirectoryKind::Nomenclatures => Some(self.nomenclature_id),
+		};
+	}
+
+	/// Embeds the referenced entry of the directory if it is among `entries`
+	pub fn expand(&mut self, kind: DirectoryKind, entries: &HashMap<Uuid, DirectoryEntry>) {
+		let entry = self
+			.reference(kind)
+			.and_then(|id| entries.get(&id).cloned());
+
+		match kind {
+			DirectoryKind::GasStations => self.gas_station = entry,
+			DirectoryKind::Cards => self.card = entry,
+			DirectoryKind::Contracts => self.contract = entry,
+			DirectoryKind::Nomenclatures => self.nomenclature = entry,
+		}
+	}
+
+	/// Same expressions as the generated columns in Postgres
+	pub fn refresh_margins(&mut self) {
+		self.margin_plan = margin(self.sell_sum_plan, self.buy_sum_plan);
+		self.margin_fact = margin(self.sell_sum_fact, self.buy_sum_fact);
+		self.margin_plan_net = net_margin(
+			self.sell_sum_plan,
+			self.sell_nds_sum_plan,
+			self.buy_sum_plan,
+			self.buy_nds_sum_plan,
+		);
+		self.margin_fact_net = net_margin(
+			self.sell_sum_fact,
+			self.sell_nds_sum_fact,
+			self.buy_sum_fact,
+			self.buy_nds_sum_fact,
+		);
+	}
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct TransactionsPage {
+	pub items: Vec<Transaction>,
+	/// Cursor of the next page, absent on the last one
+	pub next_cursor: Option<String>,
+	/// Count of all rows matching the filters, present only if `with_total` was requested
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub total: Option<i64>,
+}
+
+impl TransactionsPage {
+	/// Builds a page from rows fetched with `limit + 1`, the extra row only signals that
+	/// there is a next page
+	pub fn from_rows(mut rows: Vec<Transaction>, limit: u32, total: Option<i64>) -> Self {
+		let has_more = rows.len() > limit as usize;
+		rows.truncate(limit as usize);
+
+		let next_cursor = match rows.last() {
+			Some(last) if has_more => Some(
+				Cursor {
+					date_created: last.date_created,
+					id: last.id,
+				}
+				.encode(),
+			),
+			_ => None,
+		};
+
+		return Self {
+			items: rows,
+			next_cursor,
+			total,
+		};
+	}
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct BatchItem {
+	/// Position of the transaction in the request array
+	pub index: usize,
+	pub transaction: Transaction,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct BatchResult {
+	pub created: Vec<BatchItem>,
+	pub errors: Vec<BatchItemError>,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct ImportReport {
+	pub dry_run: bool,
+	/// Lines with a transaction, the header is not counted
+	pub lines: usize,
+	/// Nothing is created unless every line is valid
+	pub created: usize,
+	pub errors: Vec<ImportLineError>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema, Type)]
+#[sqlx(type_name = "history_operation", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryOperation {
+	Create,
+	Update,
+	Delete,
+	Restore,
+}
+
+/// Full snapshot of a transaction before and after a mutation
+#[derive(Clone, Debug, FromRow, Serialize, ToSchema)]
+pub struct TransactionHistoryEntry {
+	pub id: Uuid,
+	pub transaction_id: Uuid,
+	pub operation: HistoryOperation,
+	pub user_id: Uuid,
+
+	#[schema(value_type = Option<Transaction>)]
+	pub before: Option<Json<Transaction>>,
+
+	#[schema(value_type = Option<Transaction>)]
+	pub after: Option<Json<Transaction>>,
+
+	pub date_created: DateTime<Utc>,
+}
+
+/// Aggregates of one group of transactions, refunds are subtracted from the sums.
+/// Keys which are not grouped by are always null
+#[derive(Clone, Debug, Default, FromRow, Serialize, ToSchema)]
+pub struct StatsRow {
+	pub gas_station_id: Option<Uuid>,
+	pub card_id: Option<Uuid>,
+	pub contract_id: Option<Uuid>,
+	pub nomenclature_id: Option<Uuid>,
+	/// First day of the period
+	pub period: Option<NaiveDate>,
+
+	pub count: i64,
+	pub refund_count: i64,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub amount: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub stella_sum: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub stella_nds_sum: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_nds_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_sum_fact: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_nds_sum_fact: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_nds_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_sum_fact: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_nds_sum_fact: Decimal,
+}
+
+/// Signed contribution of a transaction value, refunds always count as negative
+pub fn net_value(refund: bool, value: Option<Decimal>) -> Decimal {
+	let value = value.unwrap_or_default();
+
+	return match refund {
+		true => -value.abs(),
+		false => value,
+	};
+}
+
+impl StatsRow {
+	pub fn add(&mut self, tx: &Transaction) {
+		self.count += 1;
+		self.refund_count += i64::from(tx.refund);
+		self.amount += net_value(tx.refund, tx.amount);
+		self.stella_sum += net_value(tx.refund, tx.stella_sum);
+		self.stella_nds_sum += net_value(tx.refund, tx.stella_nds_sum);
+		self.buy_sum_plan += net_value(tx.refund, tx.buy_sum_plan);
+		self.buy_nds_sum_plan += net_value(tx.refund, tx.buy_nds_sum_plan);
+		self.buy_sum_fact += net_value(tx.refund, tx.buy_sum_fact);
+		self.buy_nds_sum_fact += net_value(tx.refund, tx.buy_nds_sum_fact);
+		self.sell_sum_plan += net_value(tx.refund, tx.sell_sum_plan);
+		self.sell_nds_sum_plan += net_value(tx.refund, tx.sell_nds_sum_plan);
+		self.sell_sum_fact += net_value(tx.refund, tx.sell_sum_fact);
+		self.sell_nds_sum_fact += net_value(tx.refund, tx.sell_nds_sum_fact);
+	}
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct TransactionsStats {
+	pub group_by: Vec<StatsDimension>,
+	pub rows: Vec<StatsRow>,
+}
+
+/// Plan against fact of a transaction, a side without both sums has no variance
+#[derive(Clone, Debug, FromRow, Serialize, ToSchema)]
+pub struct TransactionVariance {
+	pub id: Uuid,
+	pub op_date: DateTime<Utc>,
+	pub gas_station_id: Uuid,
+	pub contract_id: Option<Uuid>,
+	pub nomenclature_id: Uuid,
+	pub refund: bool,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub buy_sum_plan: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub buy_sum_fact: Option<Decimal>,
+
+	/// Fact minus plan
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub buy_variance: Option<Decimal>,
+
+	/// Variance in percent of the plan, null for a zero plan
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub buy_variance_pct: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub sell_sum_plan: Option<Decimal>,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub sell_sum_fact: Option<Decimal>,
+
+	/// Fact minus plan
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub sell_variance: Option<Decimal>,
+
+	/// Variance in percent of the plan, null for a zero plan
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float_option")]
+	pub sell_variance_pct: Option<Decimal>,
+}
+
+fn variance(plan: Option<Decimal>, fact: Option<Decimal>) -> Option<Decimal> {
+	return Some(fact? - plan?);
+}
+
+/// Rounded to cents half away from zero, the same way as `ROUND(numeric, 2)` in Postgres
+fn variance_pct(plan: Option<Decimal>, fact: Option<Decimal>) -> Option<Decimal> {
+	let plan = plan?;
+
+	if plan.is_zero() {
+		return None;
+	}
+
+	let pct = (fact? - plan) * Decimal::ONE_HUNDRED / plan.abs();
+	return Some(pct.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero));
+}
+
+fn deviates(plan: Option<Decimal>, fact: Option<Decimal>, thresholds: &VarianceThresholds) -> bool {
+	let (Some(plan), Some(fact)) = (plan, fact) else {
+		return false;
+	};
+
+	let deviation = (fact - plan).abs();
+
+	let over_abs = thresholds.abs.is_some_and(|abs| deviation > abs);
+	let over_pct = thresholds
+		.pct
+		.is_some_and(|pct| !plan.is_zero() && deviation * Decimal::ONE_HUNDRED > pct * plan.abs());
+
+	return over_abs || over_pct;
+}
+
+impl TransactionVariance {
+	pub fn of(tx: &Transaction) -> Self {
+		return TransactionVariance {
+			id: tx.id,
+			op_date: tx.op_date,
+			gas_station_id: tx.gas_station_id,
+			contract_id: tx.contract_id,
+			nomenclature_id: tx.nomenclature_id,
+			refund: tx.refund,
+			buy_sum_plan: tx.buy_sum_plan,
+			buy_sum_fact: tx.buy_sum_fact,
+			buy_variance: variance(tx.buy_sum_plan, tx.buy_sum_fact),
+			buy_variance_pct: variance_pct(tx.buy_sum_plan, tx.buy_sum_fact),
+			sell_sum_plan: tx.sell_sum_plan,
+			sell_sum_fact: tx.sell_sum_fact,
+			sell_variance: variance(tx.sell_sum_plan, tx.sell_sum_fact),
+			sell_variance_pct: variance_pct(tx.sell_sum_plan, tx.sell_sum_fact),
+		};
+	}
+
+	/// Either the buy or the sell side exceeds a threshold
+	pub fn deviates(tx: &Transaction, thresholds: &VarianceThresholds) -> bool {
+		return deviates(tx.buy_sum_plan, tx.buy_sum_fact, thresholds)
+			|| deviates(tx.sell_sum_plan, tx.sell_sum_fact, thresholds);
+	}
+}
+
+/// Variance of the deviating transactions of one contract or station
+#[derive(Clone, Debug, Default, Serialize, ToSchema)]
+pub struct VarianceTotals {
+	/// Contract or station id, null groups transactions without a contract
+	pub id: Option<Uuid>,
+	pub count: i64,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_variance: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_variance: Decimal,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct VarianceReport {
+	pub thresholds: VarianceThresholds,
+	pub transactions: Vec<TransactionVariance>,
+	pub by_contract: Vec<VarianceTotals>,
+	pub by_station: Vec<VarianceTotals>,
+}
+
+fn variance_totals<F>(transactions: &[TransactionVariance], key: F) -> Vec<VarianceTotals>
+where
+	F: Fn(&TransactionVariance) -> Option<Uuid>,
+{
+	let mut totals: BTreeMap<Option<Uuid>, VarianceTotals> = BTreeMap::new();
+
+	for tx in transactions {
+		let id = key(tx);
+		let group = totals.entry(id).or_insert_with(|| VarianceTotals {
+			id,
+			..VarianceTotals::default()
+		});
+
+		group.count += 1;
+		group.buy_variance += tx.buy_variance.unwrap_or_default();
+		group.sell_variance += tx.sell_variance.unwrap_or_default();
+	}
+
+	return totals.into_values().collect();
+}
+
+impl VarianceReport {
+	/// Totals are built from the deviating transactions only
+	pub fn new(thresholds: VarianceThresholds, transactions: Vec<TransactionVariance>) -> Self {
+		let by_contract = variance_totals(&transactions, |tx| tx.contract_id);
+		let by_station = variance_totals(&transactions, |tx| Some(tx.gas_station_id));
+
+		return VarianceReport {
+			thresholds,
+			transactions,
+			by_contract,
+			by_station,
+		};
+	}
+}
+
+/// Margins of one contract, station or nomenclature, losses of refunds included as they are
+#[derive(Clone, Debug, Default, FromRow, Serialize, ToSchema)]
+pub struct MarginTotals {
+	/// Contract, station or nomenclature id, null for the overall total
+	/// and for transactions without a contract
+	pub id: Option<Uuid>,
+	pub count: i64,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub margin_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub margin_fact: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub margin_plan_net: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub margin_fact_net: Decimal,
+}
+
+impl MarginTotals {
+	pub fn add(&mut self, tx: &Transaction) {
+		self.count += 1;
+		self.margin_plan += tx.margin_plan.unwrap_or_default();
+		self.margin_fact += tx.margin_fact.unwrap_or_default();
+		self.margin_plan_net += tx.margin_plan_net.unwrap_or_default();
+		self.margin_fact_net += tx.margin_fact_net.unwrap_or_default();
+	}
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct MarginReport {
+	pub total: MarginTotals,
+	pub by_contract: Vec<MarginTotals>,
+	pub by_station: Vec<MarginTotals>,
+	pub by_nomenclature: Vec<MarginTotals>,
+}
+
+/// What is left to refund of each value of a purchase
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct RefundBalance {
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub amount: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub stella_sum: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub stella_nds_sum: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_nds_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_sum_fact: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub buy_nds_sum_fact: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_nds_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_sum_fact: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_nds_sum_fact: Decimal,
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct TransactionRefunds {
+	pub original_transaction_id: Uuid,
+	/// Active refunds ordered by `op_date`
+	pub refunds: Vec<Transaction>,
+	pub remaining: RefundBalance,
+}
+
+impl TransactionRefunds {
+	pub fn new(original: &Transaction, refunds: Vec<Transaction>) -> Self {
+		let refunded: Vec<ApiTransaction> = refunds.iter().map(ApiTransaction::from).collect();
+
+		let [amount, stella_sum, stella_nds_sum, buy_sum_plan, buy_nds_sum_plan, buy_sum_fact, buy_nds_sum_fact, sell_sum_plan, sell_nds_sum_plan, sell_sum_fact, sell_nds_sum_fact] =
+			ApiTransaction::from(original)
+				.refund_balance(&refunded)
+				.map(|(_, left)| left);
+
+		return TransactionRefunds {
+			original_transaction_id: original.id,
+			refunds,
+			remaining: RefundBalance {
+				amount,
+				stella_sum,
+				stella_nds_sum,
+				buy_sum_plan,
+				buy_nds_sum_plan,
+				buy_sum_fact,
+				buy_nds_sum_fact,
+				sell_sum_plan,
+				sell_nds_sum_plan,
+				sell_sum_fact,
+				sell_nds_sum_fact,
+			},
+		};
+	}
+}
+
+/// Entry of a reference directory: gas station, card, contract or nomenclature
+#[derive(Clone, Debug, FromRow, Deserialize, Serialize, ToSchema)]
+pub struct DirectoryEntry {
+	pub id: Uuid,
+	pub name: String,
+	pub code: String,
+	pub active: bool,
+	/// Unit of measure, nomenclature only
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub unit: Option<String>,
+	pub date_created: DateTime<Utc>,
+	pub date_updated: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema, Type)]
+#[sqlx(type_name = "implementation_status", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ImplementationStatus {
+	Draft,
+	/// Transactions of a posted document can be neither attached, detached nor edited
+	Posted,
+}
+
+/// Sums of the active transactions of a document, refunds are subtracted
+#[derive(Clone, Debug, Default, FromRow, Serialize, ToSchema)]
+pub struct ImplementationTotals {
+	pub count: i64,
+	pub refund_count: i64,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub amount: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub stella_sum: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub stella_nds_sum: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_nds_sum_plan: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_sum_fact: Decimal,
+
+	#[schema(value_type = Decimal)]
+	#[serde(with = "rust_decimal::serde::float")]
+	pub sell_nds_sum_fact: Decimal,
+}
+
+impl ImplementationTotals {
+	pub fn add(&mut self, tx: &Transaction) {
+		self.count += 1;
+		self.refund_count += i64::from(tx.refund);
+		self.amount += net_value(tx.refund, tx.amount);
+		self.stella_sum += net_value(tx.refund, tx.stella_sum);
+		self.stella_nds_sum += net_value(tx.refund, tx.stella_nds_sum);
+		self.sell_sum_plan += net_value(tx.refund, tx.sell_sum_plan);
+		self.sell_nds_sum_plan += net_value(tx.refund, tx.sell_nds_sum_plan);
+		self.sell_sum_fact += net_value(tx.refund, tx.sell_sum_fact);
+		self.sell_nds_sum_fact += net_value(tx.refund, tx.sell_nds_sum_fact);
+	}
+}
+
+/// Sales document grouping transactions
+#[derive(Clone, Debug, FromRow, Serialize, ToSchema)]
+pub struct Implementation {
+	pub id: Uuid,
+	pub number: String,
+	pub doc_date: NaiveDate,
+	/// Contract of the counterparty, absent only on documents backfilled from old transactions
+	pub contract_id: Option<Uuid>,
+	pub status: ImplementationStatus,
+	pub posted_at: Option<DateTime<Utc>>,
+	pub date_created: DateTime<Utc>,
+	pub date_updated: Option<DateTime<Utc>>,
+
+	/// Recomputed from the transactions on every read
+	#[sqlx(flatten)]
+	pub totals: ImplementationTotals,
+}
+
+/// Month in which transactions can no longer be created, changed or deleted
+#[derive(Clone, Debug, FromRow, Serialize, ToSchema)]
+pub struct ClosedPeriod {
+	/// First day of the month
+	pub month: NaiveDate,
+	pub closed_by: Uuid,
+	pub date_created: DateTime<Utc>,
+}
+
+impl ClosedPeriod {
+	/// First day of the month of an operation date, in UTC
+	pub fn month_of(op_date: DateTime<Utc>) -> NaiveDate {
+		let date = op_date.date_naive();
+		return date.with_day(1).unwrap_or(date);
+	}
 }
@@ -1,10 +1,28 @@
 use crate::{
-	dto::ApiTransaction,
+	dto::{
+		ApiDirectoryEntry, ApiImplementation, ApiTransaction, ApiTransactionPatch, BatchItemError,
+		BatchMode, DirectoryKind, ImportLineError, StatsDimension, VarianceThresholds,
+	},
 	handler as H,
-	repository::{models::Transaction, Repository},
+	i18n::negotiate_language,
+	repository::{
+		models::{
+			BatchItem, BatchResult, ClosedPeriod, DirectoryEntry, HistoryOperation, Implementation,
+			ImplementationStatus, ImplementationTotals, ImportReport, MarginReport, MarginTotals,
+			RefundBalance, StatsRow, Transaction, TransactionHistoryEntry, TransactionRefunds,
+			TransactionVariance, TransactionsPage, TransactionsStats, VarianceReport, VarianceTotals,
+		},
+		Repository,
+	},
+	system_models::{problem_instance, ErrorCode, FieldViolation, Problem},
 };
 use ::std::sync::Arc;
-use axum::{routing::get, Router};
+use axum::{
+	extract::DefaultBodyLimit,
+	middleware,
+	routing::{get, post, put},
+	Router,
+};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
@@ -13,23 +31,109 @@ use utoipa_swagger_ui::SwaggerUi;
 	tags(
 		(name = "fuel", description = "a CRUD service to work with transactions of fuel issuers"),
 	),
-	paths(H::get_transactions_list, H::get_transaction, H::create_transaction, H::update_transaction, H::delete_transaction,),
-	components(schemas(ApiTransaction, Transaction))
+	paths(H::get_transactions_list, H::export_transactions_csv, H::export_transactions_xlsx, H::get_transaction, H::create_transaction, H::create_transactions_batch, H::import_transactions, H::update_transaction, H::patch_transaction, H::delete_transaction, H::restore_transaction, H::get_transaction_history, H::get_transactions_stats, H::export_transactions_stats_xlsx, H::get_variance_report, H::export_variance_report_xlsx, H::get_margin_report, H::export_margin_report_xlsx, H::get_transaction_refunds, H::get_directory_entries, H::get_directory_entry, H::create_directory_entry, H::update_directory_entry, H::delete_directory_entry, H::get_implementations, H::get_implementation, H::get_implementation_transactions, H::create_implementation, H::update_implementation, H::delete_implementation, H::post_implementation, H::unpost_implementation, H::get_closed_periods, H::close_period, H::reopen_period,),
+	components(schemas(Problem, ErrorCode, FieldViolation, ApiTransaction, ApiTransactionPatch, BatchMode, BatchItemError, BatchItem, BatchResult, ImportLineError, ImportReport, Transaction, TransactionsPage, HistoryOperation, TransactionHistoryEntry, StatsDimension, StatsRow, TransactionsStats, VarianceThresholds, TransactionVariance, VarianceTotals, VarianceReport, MarginTotals, MarginReport, RefundBalance, TransactionRefunds, DirectoryKind, DirectoryEntry, ApiDirectoryEntry, ImplementationStatus, ImplementationTotals, Implementation, ApiImplementation, ClosedPeriod), responses(Problem))
 )]
 struct ApiDoc;
 
+/// Nightly batches from the processing centre are far larger than the default 2 MB
+const BATCH_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
 pub fn create_router(repo: Arc<Repository>) -> Router {
 	return Router::new()
 		.route(
 			"/api/v1/transactions",
 			get(H::get_transactions_list).post(H::create_transaction),
 		)
+		.route(
+			"/api/v1/transactions/batch",
+			post(H::create_transactions_batch).layer(DefaultBodyLimit::max(BATCH_BODY_LIMIT)),
+		)
+		.route(
+			"/api/v1/transactions/import",
+			post(H::import_transactions).layer(DefaultBodyLimit::max(BATCH_BODY_LIMIT)),
+		)
+		.route(
+			"/api/v1/transactions/export.csv",
+			get(H::export_transactions_csv),
+		)
+		.route(
+			"/api/v1/transactions/export.xlsx",
+			get(H::export_transactions_xlsx),
+		)
+		.route("/api/v1/transactions/stats", get(H::get_transactions_stats))
+		.route(
+			"/api/v1/transactions/stats/export.xlsx",
+			get(H::export_transactions_stats_xlsx),
+		)
+		.route("/api/v1/transactions/variance", get(H::get_variance_report))
+		.route(
+			"/api/v1/transactions/variance/export.xlsx",
+			get(H::export_variance_report_xlsx),
+		)
+		.route("/api/v1/transactions/margin", get(H::get_margin_report))
+		.route(
+			"/api/v1/transactions/margin/export.xlsx",
+			get(H::export_margin_report_xlsx),
+		)
 		.route(
 			"/api/v1/transactions/:id",
 			get(H::get_transaction)
 				.put(H::update_transaction)
+				.patch(H::patch_transaction)
 				.delete(H::delete_transaction),
 		)
+		.route(
+			"/api/v1/transactions/:id/restore",
+			post(H::restore_transaction),
+		)
+		.route(
+			"/api/v1/transactions/:id/history",
+			get(H::get_transaction_history),
+		)
+		.route(
+			"/api/v1/transactions/:id/refunds",
+			get(H::get_transaction_refunds),
+		)
+		.route(
+			"/api/v1/directories/:kind",
+			get(H::get_directory_entries).post(H::create_directory_entry),
+		)
+		.route(
+			"/api/v1/directories/:kind/:id",
+			get(H::get_directory_entry)
+				.put(H::update_directory_entry)
+				.delete(H::delete_directory_entry),
+		)
+		.route(
+			"/api/v1/implementations",
+			get(H::get_implementations).post(H::create_implementation),
+		)
+		.route(
+			"/api/v1/implementations/:id",
+			get(H::get_implementation)
+				.put(H::update_implementation)
+				.delete(H::delete_implementation),
+		)
+		.route(
+			"/api/v1/implementations/:id/transactions",
+			get(H::get_implementation_transactions),
+		)
+		.route(
+			"/api/v1/implementations/:id/post",
+			post(H::post_implementation),
+		)
+		.route(
+			"/api/v1/implementations/:id/unpost",
+			post(H::unpost_implementation),
+		)
+		.route("/api/v1/closed-periods", get(H::get_closed_periods))
+		.route(
+			"/api/v1/admin/closed-periods/:month",
+			put(H::close_period).delete(H::reopen_period),
+		)
 		.with_state(repo)
+		.layer(middleware::from_fn(problem_instance))
+		.layer(middleware::from_fn(negotiate_language))
 		.merge(SwaggerUi::new("/swagger").url("/swagger/swagger.json", ApiDoc::openapi()));
 }
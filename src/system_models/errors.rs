@@ -4,23 +4,218 @@ use axum::{
 	http::StatusCode,
 	response::{IntoResponse, Response},
 };
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::Problem;
+use crate::i18n::{t, Message};
 
 #[derive(Debug)]
 pub enum AppError {
 	BadRequest(String),
+	/// The body is valid JSON but does not match the expected shape
+	InvalidBody(String),
+	MalformedJson(String),
+	MissingContentType(String),
+	UnreadableBody(String),
+	/// The body is over the limit of the route
+	PayloadTooLarge(String),
+	/// The user is not allowed to do the operation
+	Forbidden(String),
 	NotFound(String),
+	Conflict(String),
+	/// The change touches a closed accounting period
+	PeriodClosed(String),
+	PreconditionFailed(String),
+	UnprocessableEntity(String),
+	Validation(Vec<FieldViolation>),
+	/// A dependency such as the database is temporarily out of reach, the request may be retried
+	ServiceUnavailable(String),
+	Timeout(String),
 	SystemError(String),
 }
 
+/// A business rule broken by a single field
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct FieldViolation {
+	/// JSON pointer (RFC 6901) to the field in the request body
+	pub pointer: String,
+	pub message: String,
+}
+
+/// Stable machine-readable error code, clients should rely on it instead of the message text
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+	BadRequest,
+	InvalidBody,
+	MalformedJson,
+	MissingContentType,
+	UnreadableBody,
+	PayloadTooLarge,
+	Forbidden,
+	NotFound,
+	Conflict,
+	PeriodClosed,
+	PreconditionFailed,
+	UnprocessableEntity,
+	ValidationFailed,
+	ServiceUnavailable,
+	Timeout,
+	InternalError,
+}
+
+/// Suggested delay before retrying a request which failed with `SERVICE_UNAVAILABLE`
+const RETRY_AFTER_SECONDS: u32 = 5;
+
+impl ErrorCode {
+	pub fn status(&self) -> StatusCode {
+		return match self {
+			ErrorCode::BadRequest
+			| ErrorCode::InvalidBody
+			| ErrorCode::MalformedJson
+			| ErrorCode::MissingContentType => StatusCode::BAD_REQUEST,
+			ErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+			ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+			ErrorCode::NotFound => StatusCode::NOT_FOUND,
+			ErrorCode::Conflict | ErrorCode::PeriodClosed => StatusCode::CONFLICT,
+			ErrorCode::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+			ErrorCode::UnprocessableEntity | ErrorCode::ValidationFailed => {
+				StatusCode::UNPROCESSABLE_ENTITY
+			}
+			ErrorCode::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+			ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+			ErrorCode::UnreadableBody | ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+		};
+	}
+
+	pub fn retry_after(&self) -> Option<u32> {
+		return match self {
+			ErrorCode::ServiceUnavailable => Some(RETRY_AFTER_SECONDS),
+			_ => None,
+		};
+	}
+
+	pub fn title(&self) -> String {
+		return t(Message::ErrorTitle(*self));
+	}
+
+	/// URI reference identifying the problem type
+	pub fn problem_type(&self) -> &'static str {
+		return match self {
+			ErrorCode::BadRequest => "urn:fuel:problem:bad-request",
+			ErrorCode::InvalidBody => "urn:fuel:problem:invalid-body",
+			ErrorCode::MalformedJson => "urn:fuel:problem:malformed-json",
+			ErrorCode::MissingContentType => "urn:fuel:problem:missing-content-type",
+			ErrorCode::UnreadableBody => "urn:fuel:problem:unreadable-body",
+			ErrorCode::PayloadTooLarge => "urn:fuel:problem:payload-too-large",
+			ErrorCode::Forbidden => "urn:fuel:problem:forbidden",
+			ErrorCode::NotFound => "urn:fuel:problem:not-found",
+			ErrorCode::Conflict => "urn:fuel:problem:conflict",
+			ErrorCode::PeriodClosed => "urn:fuel:problem:period-closed",
+			ErrorCode::PreconditionFailed => "urn:fuel:problem:precondition-failed",
+			ErrorCode::UnprocessableEntity => "urn:fuel:problem:unprocessable-entity",
+			ErrorCode::ValidationFailed => "urn:fuel:problem:validation-failed",
+			ErrorCode::ServiceUnavailable => "urn:fuel:problem:service-unavailable",
+			ErrorCode::Timeout => "urn:fuel:problem:timeout",
+			ErrorCode::InternalError => "urn:fuel:problem:internal-error",
+		};
+	}
+}
+
+impl AppError {
+	pub fn code(&self) -> ErrorCode {
+		return match self {
+			AppError::BadRequest(_) => ErrorCode::BadRequest,
+			AppError::InvalidBody(_) => ErrorCode::InvalidBody,
+			AppError::MalformedJson(_) => ErrorCode::MalformedJson,
+			AppError::MissingContentType(_) => ErrorCode::MissingContentType,
+			AppError::UnreadableBody(_) => ErrorCode::UnreadableBody,
+			AppError::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+			AppError::Forbidden(_) => ErrorCode::Forbidden,
+			AppError::NotFound(_) => ErrorCode::NotFound,
+			AppError::Conflict(_) => ErrorCode::Conflict,
+			AppError::PeriodClosed(_) => ErrorCode::PeriodClosed,
+			AppError::PreconditionFailed(_) => ErrorCode::PreconditionFailed,
+			AppError::UnprocessableEntity(_) => ErrorCode::UnprocessableEntity,
+			AppError::Validation(_) => ErrorCode::ValidationFailed,
+			AppError::ServiceUnavailable(_) => ErrorCode::ServiceUnavailable,
+			AppError::Timeout(_) => ErrorCode::Timeout,
+			AppError::SystemError(_) => ErrorCode::InternalError,
+		};
+	}
+
+	pub fn message(&self) -> String {
+		return match self {
+			AppError::BadRequest(msg)
+			| AppError::InvalidBody(msg)
+			| AppError::MalformedJson(msg)
+			| AppError::MissingContentType(msg)
+			| AppError::UnreadableBody(msg)
+			| AppError::PayloadTooLarge(msg)
+			| AppError::Forbidden(msg)
+			| AppError::NotFound(msg)
+			| AppError::Conflict(msg)
+			| AppError::PeriodClosed(msg)
+			| AppError::PreconditionFailed(msg)
+			| AppError::UnprocessableEntity(msg)
+			| AppError::ServiceUnavailable(msg)
+			| AppError::Timeout(msg)
+			| AppError::SystemError(msg) => msg.clone(),
+			AppError::Validation(_) => t(Message::ValidationFailed),
+		};
+	}
+}
+
 impl Display for AppError {
 	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
 		return match self {
 			AppError::BadRequest(msg) => {
 				write!(f, "BadRequest: {msg}")
 			}
+			AppError::InvalidBody(msg) => {
+				write!(f, "InvalidBody: {msg}")
+			}
+			AppError::MalformedJson(msg) => {
+				write!(f, "MalformedJson: {msg}")
+			}
+			AppError::MissingContentType(msg) => {
+				write!(f, "MissingContentType: {msg}")
+			}
+			AppError::UnreadableBody(msg) => {
+				write!(f, "UnreadableBody: {msg}")
+			}
+			AppError::PayloadTooLarge(msg) => {
+				write!(f, "PayloadTooLarge: {msg}")
+			}
+			AppError::Forbidden(msg) => {
+				write!(f, "Forbidden: {msg}")
+			}
 			AppError::NotFound(msg) => {
 				write!(f, "NotFound: {msg}")
 			}
+			AppError::Conflict(msg) => {
+				write!(f, "Conflict: {msg}")
+			}
+			AppError::PeriodClosed(msg) => {
+				write!(f, "PeriodClosed: {msg}")
+			}
+			AppError::PreconditionFailed(msg) => {
+				write!(f, "PreconditionFailed: {msg}")
+			}
+			AppError::UnprocessableEntity(msg) => {
+				write!(f, "UnprocessableEntity: {msg}")
+			}
+			AppError::Validation(violations) => {
+				let fields: Vec<&str> = violations.iter().map(|v| v.pointer.as_str()).collect();
+				write!(f, "Validation: {}", fields.join(", "))
+			}
+			AppError::ServiceUnavailable(msg) => {
+				write!(f, "ServiceUnavailable: {msg}")
+			}
+			AppError::Timeout(msg) => {
+				write!(f, "Timeout: {msg}")
+			}
 			AppError::SystemError(msg) => {
 				write!(f, "SystemError: {msg}")
 			}
@@ -32,10 +227,6 @@ impl Error for AppError {}
 
 impl IntoResponse for AppError {
 	fn into_response(self) -> Response {
-		match self {
-			AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
-			AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg).into_response(),
-			AppError::SystemError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg).into_response(),
-		}
+		return Problem::from(self).into_response();
 	}
 }
@@ -0,0 +1,48 @@
+use ::std::convert::Infallible;
+use axum::{
+	http::{header, HeaderValue},
+	response::{IntoResponseParts, ResponseParts},
+};
+
+/// Strong entity tag built from the row version
+pub struct ETag(pub i64);
+
+/// Weak entity tag of a representation which holds more than the row, such as embedded
+/// directory entries. It is never matched against `If-None-Match` or `If-Match`
+pub struct WeakETag(pub i64);
+
+impl ETag {
+	pub fn to_header_value(&self) -> String {
+		return format!("\"{}\"", self.0);
+	}
+}
+
+impl WeakETag {
+	pub fn to_header_value(&self) -> String {
+		return format!("W/\"{}\"", self.0);
+	}
+}
+
+impl IntoResponseParts for ETag {
+	type Error = Infallible;
+
+	fn into_response_parts(self, res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+		return Ok(insert_etag(res, self.to_header_value()));
+	}
+}
+
+impl IntoResponseParts for WeakETag {
+	type Error = Infallible;
+
+	fn into_response_parts(self, res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+		return Ok(insert_etag(res, self.to_header_value()));
+	}
+}
+
+fn insert_etag(mut res: ResponseParts, etag: String) -> ResponseParts {
+	if let Ok(value) = HeaderValue::from_str(&etag) {
+		res.headers_mut().insert(header::ETAG, value);
+	}
+
+	return res;
+}
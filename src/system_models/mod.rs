@@ -1,5 +1,9 @@
 mod errors;
+mod etag;
+mod problem;
 mod success;
 
-pub use errors::AppError;
+pub use errors::{AppError, ErrorCode, FieldViolation};
+pub use etag::{ETag, WeakETag};
+pub use problem::{problem_instance, Problem};
 pub use success::Success;
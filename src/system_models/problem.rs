@@ -0,0 +1,117 @@
+use axum::{
+	body::Body,
+	extract::Request,
+	http::{header, HeaderValue},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use utoipa::{ToResponse, ToSchema};
+
+use super::{errors::ErrorCode, AppError, FieldViolation};
+use crate::i18n::current_lang;
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Problem details of a failed request (RFC 7807)
+#[derive(Clone, Debug, Serialize, ToSchema, ToResponse)]
+#[response(
+	description = "Problem details (RFC 7807)",
+	content_type = "application/problem+json"
+)]
+pub struct Problem {
+	/// URI reference identifying the problem type
+	#[serde(rename = "type")]
+	pub problem_type: String,
+	/// Short summary of the problem type, does not change between occurrences
+	pub title: String,
+	pub status: u16,
+	/// Explanation specific to this occurrence
+	pub detail: String,
+	/// Path of the request that caused the problem
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub instance: Option<String>,
+	pub code: ErrorCode,
+	/// Every broken rule, present only for `VALIDATION_FAILED`
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub violations: Vec<FieldViolation>,
+}
+
+impl From<AppError> for Problem {
+	fn from(err: AppError) -> Self {
+		let code = err.code();
+		let detail = err.message();
+
+		let violations = match err {
+			AppError::Validation(violations) => violations,
+			_ => Vec::new(),
+		};
+
+		return Problem {
+			problem_type: String::from(code.problem_type()),
+			title: code.title(),
+			status: code.status().as_u16(),
+			detail,
+			instance: None,
+			code,
+			violations,
+		};
+	}
+}
+
+impl Problem {
+	fn to_body(&self) -> Body {
+		return match serde_json::to_vec(self) {
+			Ok(bytes) => Body::from(bytes),
+			Err(_) => Body::from(self.detail.clone()),
+		};
+	}
+}
+
+impl IntoResponse for Problem {
+	fn into_response(self) -> Response {
+		let mut response = Response::new(self.to_body());
+		*response.status_mut() = self.code.status();
+		response.headers_mut().insert(
+			header::CONTENT_TYPE,
+			HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+		);
+		response.headers_mut().insert(
+			header::CONTENT_LANGUAGE,
+			HeaderValue::from_static(current_lang().tag()),
+		);
+		if let Some(seconds) = self.code.retry_after() {
+			response
+				.headers_mut()
+				.insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+		}
+		// kept for the middleware, errors are built far from the request they belong to
+		response.extensions_mut().insert(self);
+
+		return response;
+	}
+}
+
+/// Fills `instance` of problem responses with the request path
+pub async fn problem_instance(req: Request, next: Next) -> Response {
+	let path = String::from(req.uri().path());
+	let response = next.run(req).await;
+
+	let Some(problem) = response.extensions().get::<Problem>() else {
+		return response;
+	};
+
+	if problem.instance.is_some() {
+		return response;
+	}
+
+	let mut problem = problem.clone();
+	problem.instance = Some(path);
+
+	let (mut parts, _) = response.into_parts();
+	parts.headers.remove(header::CONTENT_LENGTH);
+	let body = problem.to_body();
+	parts.extensions.insert(problem);
+
+	return Response::from_parts(parts, body);
+}